@@ -1,32 +0,0 @@
-#! /bin/sh
-# You have to edit the result to remove the trailing comma
-# before the closing square bracket.
-
-IFS="	"
-
-kvq() {
-    s='  "'
-    s=${s}"$1"
-    s=${s}'": "'
-    s=${s}"$2"
-    s=${s}'"'
-    echo "$s"
-}
-kv() {
-    s='  "'
-    s=${s}"$1"
-    s=${s}'": '
-    s=${s}"$2"
-    echo "$s"
-}
-
-echo "["
-grep -v '^#' | while read rank prompt response; do
-    echo "{"
-    echo `kvq "prompt" "$prompt"`,
-    echo `kvq "response" "$response"`,
-    echo `kv "misses" 0`,
-    echo `kv "hits" "$rank"`
-    echo "},"
-done
-echo "]"
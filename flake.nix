@@ -54,7 +54,8 @@
         };
 
         cargoArtifacts = craneLib.buildDepsOnly (commonArgs // {
-          # You cannot run cargo test on a wasm build
+          # You cannot run cargo test on a wasm build,
+          # but `cargo test -p memoradical-core` runs the core tests on the host
           doCheck = false;
         });
 
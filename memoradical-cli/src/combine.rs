@@ -0,0 +1,81 @@
+//! Merging the cards of several files into one set
+
+use memoradical_core::merge::{self, Change, Strategies, Strategy};
+use memoradical_core::Card;
+
+/// Combine cards, matching them by id or else by prompt as uploads are matched,
+/// ignoring case and spacing but not accents,
+/// and adding up the counters of the cards that match.
+///
+/// The text of the last matching card wins, and the first one's schedules are kept.
+pub fn combine(files: Vec<Vec<Card>>) -> Vec<Card> {
+    let mut strategies = Strategies::default();
+    for change in [Change::Identical, Change::Text, Change::Stats] {
+        strategies.set(change, Strategy::SumCounts);
+    }
+    let incoming = merge::classify(&[], files.into_iter().flatten().collect());
+    let mut cards = Vec::new();
+    merge::merge(&mut cards, incoming, &strategies);
+    cards
+}
+
+#[cfg(test)]
+mod tests {
+    use memoradical_core::CardId;
+
+    use super::*;
+
+    fn card(id: CardId, prompt: &str, hits: usize, misses: usize) -> Card {
+        Card {
+            id,
+            hits,
+            misses,
+            ..Card::new(prompt, "response")
+        }
+    }
+
+    fn summary(cards: &[Card]) -> Vec<(CardId, &str, usize, usize)> {
+        cards
+            .iter()
+            .map(|c| (c.id, c.prompt.as_str(), c.hits, c.misses))
+            .collect()
+    }
+
+    #[test]
+    fn matching_cards_are_merged_and_their_counts_summed() {
+        let first = vec![card(1, "Café", 1, 0), card(2, "thé", 0, 1)];
+        let second = vec![
+            card(4, "THÉ ", 5, 0),
+            card(5, "lait", 1, 1),
+            card(2, "tea", 1, 1),
+        ];
+        let cards = combine(vec![first, second]);
+        assert_eq!(
+            summary(&cards),
+            vec![(1, "Café", 1, 0), (2, "tea", 6, 2), (5, "lait", 1, 1)]
+        );
+    }
+
+    #[test]
+    fn accented_and_unaccented_prompts_stay_separate() {
+        let first = vec![card(1, "Café", 1, 0), card(2, "sí", 3, 0)];
+        let second = vec![card(3, "cafe ", 2, 2), card(4, "si", 0, 1)];
+        let cards = combine(vec![first, second]);
+        assert_eq!(
+            summary(&cards),
+            vec![
+                (1, "Café", 1, 0),
+                (2, "sí", 3, 0),
+                (3, "cafe ", 2, 2),
+                (4, "si", 0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_cards_in_different_files_count_twice() {
+        let cards = combine(vec![vec![card(1, "a", 2, 1)], vec![card(1, "a", 2, 1)]]);
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].hits_misses(false), (4, 2));
+    }
+}
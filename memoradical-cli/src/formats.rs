@@ -0,0 +1,200 @@
+//! The file formats that cards are read from and written to
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+
+use memoradical_core::delimited::{self, Delimiter, TableImport};
+use memoradical_core::schema::{self, Envelope, Settings};
+use memoradical_core::validate::{self, Problem};
+use memoradical_core::{anki, jsonl};
+use memoradical_core::{assign_missing_decks, assign_missing_ids, Card, DEFAULT_DECK};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Json,  // settings and cards, as the web app downloads them
+    Jsonl, // a card to a line
+    Tsv,
+    Csv,
+    Anki, // Anki's "Notes in Plain Text"
+}
+
+impl Format {
+    /// The format implied by a file name
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        match name.rsplit_once('.')?.1 {
+            "json" => Some(Format::Json),
+            "jsonl" | "ndjson" => Some(Format::Jsonl),
+            "txt" => Some(Format::Anki),
+            _ => match Delimiter::from_filename(&name)? {
+                Delimiter::Comma => Some(Format::Csv),
+                Delimiter::Tab => Some(Format::Tsv),
+            },
+        }
+    }
+
+    /// The format that text looks like it is in, for files whose names don't say
+    fn guess(text: &str) -> Self {
+        let first_line = text
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or_default();
+        let is_card = serde_json::from_str::<serde_json::Value>(first_line)
+            .is_ok_and(|value| value.get("prompt").is_some());
+        let trimmed = text.trim_start();
+        if anki::is_text_export(text) {
+            Format::Anki
+        } else if is_card {
+            Format::Jsonl
+        } else if trimmed.starts_with('[') || trimmed.starts_with('{') {
+            Format::Json
+        } else if first_line.matches(',').count() > first_line.matches('\t').count() {
+            Format::Csv
+        } else {
+            Format::Tsv
+        }
+    }
+}
+
+/// The cards read from a file
+pub struct CardFile {
+    pub settings: Option<Settings>, // only card JSON has settings
+    pub cards: Vec<Card>,
+    pub problems: Vec<Problem>,
+}
+
+/// Read a file in the given format, or else the one its name or its text implies,
+/// giving ids and decks to any cards that lack them.
+pub fn read(path: &Path, format: Option<Format>) -> Result<CardFile> {
+    let file = path.display().to_string();
+    let data = fs::read(path).with_context(|| format!("reading {file}"))?;
+    let text = validate::decode(&file, data).map_err(|problem| anyhow!("{problem}"))?;
+    let format = format
+        .or_else(|| Some(Format::Anki).filter(|_| anki::is_text_export(&text)))
+        .or_else(|| Format::from_path(path))
+        .unwrap_or_else(|| Format::guess(&text));
+    parse(&file, text, format)
+}
+
+pub fn parse(file: &str, text: String, format: Format) -> Result<CardFile> {
+    let table = |delimiter| TableImport::new(file.to_owned(), text.clone(), Some(delimiter));
+    let (settings, mut cards, problems) = match format {
+        Format::Json => {
+            let (settings, cards, problems) = schema::read(file, &text);
+            (Some(settings), cards, problems)
+        }
+        Format::Jsonl => {
+            let (cards, problems) = jsonl::read(file, &text);
+            (None, cards, problems)
+        }
+        Format::Tsv => with_no_settings(table(Delimiter::Tab).cards())?,
+        Format::Csv => with_no_settings(table(Delimiter::Comma).cards())?,
+        Format::Anki => with_no_settings(anki::text_import(file.to_owned(), text).cards())?,
+    };
+    assign_missing_ids(&mut cards);
+    assign_missing_decks(&mut cards, DEFAULT_DECK);
+    Ok(CardFile {
+        settings,
+        cards,
+        problems,
+    })
+}
+
+type Parsed = (Option<Settings>, Vec<Card>, Vec<Problem>);
+
+fn with_no_settings(table: Result<(Vec<Card>, Vec<Problem>)>) -> Result<Parsed> {
+    table.map(|(cards, problems)| (None, cards, problems))
+}
+
+/// Cards as text in a format.
+///
+/// Only card JSON keeps the settings, the reverse counts, and the schedules.
+pub fn text(format: Format, settings: &Settings, cards: &[Card]) -> Result<String> {
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(&Envelope::new(settings, cards))
+            .context("serializing cards")?,
+        Format::Jsonl => jsonl::export(cards),
+        Format::Tsv => delimited::export(cards, Delimiter::Tab),
+        Format::Csv => delimited::export(cards, Delimiter::Comma),
+        Format::Anki => anki::export_text(cards),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cards() -> Vec<Card> {
+        let mut first = Card::new("bonjour", "hello, there");
+        first.deck = "French".to_owned();
+        first.tags = vec!["greetings".to_owned()];
+        let second = Card::new("merci", "thank you");
+        vec![first, second]
+    }
+
+    #[test]
+    fn file_names_imply_formats() {
+        for (name, format) in [
+            ("cards.json", Some(Format::Json)),
+            ("cards.JSONL", Some(Format::Jsonl)),
+            ("cards.dat", Some(Format::Tsv)),
+            ("cards.tsv", Some(Format::Tsv)),
+            ("cards.csv", Some(Format::Csv)),
+            ("notes.txt", Some(Format::Anki)),
+            ("cards", None),
+        ] {
+            assert_eq!(Format::from_path(Path::new(name)), format, "{name}");
+        }
+    }
+
+    #[test]
+    fn text_without_a_name_is_recognized() {
+        let settings = Settings::default();
+        for format in [Format::Json, Format::Jsonl, Format::Anki, Format::Csv] {
+            let text = text(format, &settings, &cards()).unwrap();
+            assert_eq!(Format::guess(&text), format);
+        }
+        assert_eq!(
+            Format::guess("prompt\tresponse, with a comma\n"),
+            Format::Tsv
+        );
+    }
+
+    #[test]
+    fn every_format_keeps_the_text_and_decks() {
+        let settings = Settings {
+            reverse_mode: true,
+            ..Settings::default()
+        };
+        for format in [
+            Format::Json,
+            Format::Jsonl,
+            Format::Tsv,
+            Format::Csv,
+            Format::Anki,
+        ] {
+            let text = text(format, &settings, &cards()).unwrap();
+            let read = parse("cards", text, format).unwrap();
+            assert!(read.problems.is_empty(), "{format:?}");
+            let summary = |cards: &[Card]| {
+                cards
+                    .iter()
+                    .map(|c| {
+                        (
+                            c.prompt.clone(),
+                            c.response.clone(),
+                            c.deck.clone(),
+                            c.tags.clone(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(summary(&read.cards), summary(&cards()), "{format:?}");
+            assert!(read.cards.iter().all(|c| c.id != 0));
+            assert_eq!(read.settings.is_some(), format == Format::Json);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Convert, merge, check, and summarize Memoradical card files
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+
+use memoradical_core::delimited::{self, Delimiter};
+use memoradical_core::schema::Settings;
+use memoradical_core::stats::{Stats, GOODNESS_CRITERION};
+use memoradical_core::{cardfile, Card};
+
+use formats::{CardFile, Format};
+
+mod combine;
+mod formats;
+
+/// Work with card files outside the browser.
+///
+/// Formats are known by file extension: .json for card JSON as the web app
+/// downloads it, .jsonl, .tsv (or .tab or .dat), .csv, and .txt for Anki's
+/// "Notes in Plain Text".
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert cards from one format to another
+    Convert {
+        input: PathBuf,
+
+        /// Where to write, or standard output
+        output: Option<PathBuf>,
+
+        /// The input format, if its name doesn't say
+        #[arg(long)]
+        from: Option<Format>,
+
+        /// The output format, if its name doesn't say
+        #[arg(long)]
+        to: Option<Format>,
+
+        /// Leave out records with problems instead of stopping
+        #[arg(long)]
+        skip_bad: bool,
+    },
+
+    /// Combine card files, merging cards with the same prompt and adding up their counts
+    Merge {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Where to write, in card JSON unless its name says otherwise
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Put every card in this deck instead of the ones they were in
+        #[arg(long)]
+        deck: Option<String>,
+
+        /// Leave out records with problems instead of stopping
+        #[arg(long)]
+        skip_bad: bool,
+    },
+
+    /// Clear the hit and miss counts of a card file
+    ResetCounts {
+        file: PathBuf,
+
+        /// Clear the counts for reverse mode instead
+        #[arg(long)]
+        reverse: bool,
+
+        /// Clear only the counts of the cards in this deck
+        #[arg(long)]
+        deck: Option<String>,
+    },
+
+    /// Print the summary and table of the Stats view
+    Stats {
+        file: PathBuf,
+
+        /// Which direction's stats, if not the one the file was last studied in
+        #[arg(long)]
+        direction: Option<Direction>,
+
+        /// The stats for only the cards in this deck
+        #[arg(long)]
+        deck: Option<String>,
+    },
+
+    /// Check card files, reporting where each bad record is and what is wrong with it
+    Validate {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// The format of the files, if their names don't say
+        #[arg(long)]
+        format: Option<Format>,
+    },
+}
+
+/// Which way round the cards are studied
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Direction {
+    Forward,
+    Reverse,
+}
+
+fn main() -> Result<ExitCode> {
+    match Args::parse().command {
+        Command::Convert {
+            input,
+            output,
+            from,
+            to,
+            skip_bad,
+        } => {
+            let to = to
+                .or_else(|| output.as_deref().and_then(Format::from_path))
+                .ok_or_else(|| anyhow!("Which format should be written? Say with --to"))?;
+            let file = read_cards(&input, from, skip_bad)?;
+            let settings = file.settings.unwrap_or_default();
+            write_cards(output.as_deref(), to, &settings, &file.cards)?;
+        }
+        Command::Merge {
+            inputs,
+            output,
+            deck,
+            skip_bad,
+        } => {
+            let mut settings = None;
+            let mut files = Vec::new();
+            for input in inputs.iter() {
+                let file = read_cards(input, None, skip_bad)?;
+                settings = settings.or(file.settings);
+                files.push(file.cards);
+            }
+            let n_read = files.iter().map(Vec::len).sum::<usize>();
+            let mut cards = combine::combine(files);
+            if let Some(deck) = deck {
+                for card in cards.iter_mut() {
+                    card.deck = deck.clone();
+                }
+            }
+            let format = Format::from_path(&output).unwrap_or(Format::Json);
+            write_cards(Some(&output), format, &settings.unwrap_or_default(), &cards)?;
+            eprintln!("Merged {n_read} cards into {}", cards.len());
+        }
+        Command::ResetCounts {
+            file,
+            reverse,
+            deck,
+        } => {
+            // only card JSON can be written back without losing the other counts
+            if Format::from_path(&file).is_some_and(|format| format != Format::Json) {
+                bail!(
+                    "{} is not card JSON; convert it to a .json file to reset its counts",
+                    file.display()
+                );
+            }
+            let (settings, mut cards) = cardfile::read(&file)?;
+            let mut n_cleared = 0;
+            for card in cards.iter_mut().filter(|c| in_deck(&deck, c)) {
+                card.clear_counts(reverse);
+                n_cleared += 1;
+            }
+            cardfile::write(&file, &settings, &cards)?;
+            let direction = if reverse { "reverse" } else { "forward" };
+            eprintln!("Cleared the {direction} counts of {n_cleared} cards");
+        }
+        Command::Stats {
+            file,
+            direction,
+            deck,
+        } => {
+            let file = read_cards(&file, None, false)?;
+            let reverse = match direction {
+                Some(direction) => direction == Direction::Reverse,
+                None => file.settings.is_some_and(|s| s.reverse_mode),
+            };
+            let cards = file
+                .cards
+                .iter()
+                .filter(|c| in_deck(&deck, c))
+                .collect::<Vec<_>>();
+            print_stats(&Stats::new(&cards, &[], reverse, 0.0), reverse);
+        }
+        Command::Validate { files, format } => {
+            let mut all_good = true;
+            for path in files.iter() {
+                match formats::read(path, format) {
+                    Err(e) => {
+                        println!("{}: {e:#}", path.display());
+                        all_good = false;
+                    }
+                    Ok(file) => {
+                        for problem in file.problems.iter() {
+                            println!("{problem}");
+                        }
+                        println!(
+                            "{}: {} good records, {} with problems",
+                            path.display(),
+                            file.cards.len(),
+                            file.problems.len()
+                        );
+                        all_good &= file.problems.is_empty();
+                    }
+                }
+            }
+            if !all_good {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn in_deck(deck: &Option<String>, card: &Card) -> bool {
+    deck.as_ref().is_none_or(|d| *d == card.deck)
+}
+
+/// Read a file's cards, reporting any bad records,
+/// which are an error unless they are to be skipped.
+fn read_cards(path: &Path, format: Option<Format>, skip_bad: bool) -> Result<CardFile> {
+    let file = formats::read(path, format)?;
+    for problem in file.problems.iter() {
+        eprintln!("{problem}");
+    }
+    if !file.problems.is_empty() && !skip_bad {
+        bail!(
+            "{} records in {} have problems; fix them, or leave them out with --skip-bad",
+            file.problems.len(),
+            path.display()
+        );
+    }
+    Ok(file)
+}
+
+/// Write cards to a file, replacing it atomically, or else to standard output.
+fn write_cards(
+    output: Option<&Path>,
+    format: Format,
+    settings: &Settings,
+    cards: &[Card],
+) -> Result<()> {
+    let text = formats::text(format, settings, cards)?;
+    match output {
+        Some(path) => cardfile::write_atomically(path, text.as_bytes()),
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+/// The figures and table of the web app's Stats view,
+/// without the responses today, since card files have no review log
+fn print_stats(stats: &Stats, reverse: bool) {
+    if stats.rows.is_empty() {
+        println!("There are no cards.");
+        return;
+    }
+    println!("Overall score: {:.2}", stats.score);
+    println!(
+        "Cards known well: {:.2}% (visited more than once and with goodness > {:.2})",
+        stats.percent_good, GOODNESS_CRITERION
+    );
+    println!(
+        "Cards visited: {:.2}% of {}",
+        stats.percent_visited,
+        stats.rows.len()
+    );
+    println!("Number of responses: {}", stats.n_responses);
+    println!();
+    let prefix = if reverse { "reverse " } else { "" };
+    println!(
+        "prompt\tresponse\t{prefix}hits\t{prefix}misses\t{prefix}percent hit\t{prefix}goodness"
+    );
+    for row in stats.rows.iter() {
+        println!(
+            "{}\t{}\t{}\t{}\t{:.2}\t{:.2}",
+            delimited::quote(&row.card.prompt, Delimiter::Tab),
+            delimited::quote(&row.card.response, Delimiter::Tab),
+            row.hits,
+            row.misses,
+            row.percent_hit,
+            row.goodness
+        );
+    }
+}
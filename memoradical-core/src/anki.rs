@@ -0,0 +1,425 @@
+//! Moving cards to and from Anki, through its .apkg packages
+//! and its "Notes in Plain Text" export format.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::delimited::{self, Delimiter, Field, TableImport};
+use crate::sqlite::{Database, Value};
+use crate::tags::parse_tags;
+use crate::unzip::Archive;
+use crate::{Card, CardId, CARD_ID_LIMIT};
+
+// newest first; "anki21b" is zstd-compressed, and the older ones may be stubs
+const COLLECTIONS: [&str; 3] = [
+    "collection.anki21b",
+    "collection.anki21",
+    "collection.anki2",
+];
+const FIELD_SEPARATOR: char = '\x1f';
+const EASE_AGAIN: i64 = 1;
+
+/// Turn Anki's HTML field contents into plain text.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut chars = html.chars();
+    while let Some(c) = chars.next() {
+        if c == '<' {
+            let tag = chars
+                .by_ref()
+                .take_while(|c| *c != '>')
+                .collect::<String>()
+                .to_lowercase();
+            let name = tag
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .find(|s| !s.is_empty());
+            if matches!(name, Some("br" | "div" | "p")) && !text.is_empty() && !text.ends_with('\n')
+            {
+                text.push('\n');
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    decode_entities(text.trim())
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| &rest[1..end]).and_then(|name| {
+            let c = match name {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                _ => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => name.strip_prefix('#').and_then(|n| n.parse().ok()),
+                    };
+                    char::from_u32(code?)?
+                }
+            };
+            Some((c, name.len() + 2))
+        });
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Deck names by Anki deck id, with subdecks named as in "Parent::Child"
+fn deck_names(db: &Database) -> Result<HashMap<i64, String>> {
+    let mut names = HashMap::new();
+    if db.has_table("decks")? {
+        // newer collections: id, name, ...
+        for row in db.table("decks")? {
+            let id = row.first().and_then(Value::as_i64);
+            if let (Some(id), Some(name)) = (id, row.get(1).and_then(Value::as_str)) {
+                names.insert(id, name.replace(FIELD_SEPARATOR, "::"));
+            }
+        }
+    } else {
+        // older ones keep JSON in the single row of col: id, crt, mod, scm, ver, dty, usn, ls, conf, models, decks, ...
+        let col = db.table("col")?;
+        let json = col
+            .first()
+            .and_then(|row| row.get(10))
+            .and_then(Value::as_str)
+            .context("no decks in Anki collection")?;
+        let decks: HashMap<String, serde_json::Value> =
+            serde_json::from_str(json).context("parsing Anki decks")?;
+        for (id, deck) in decks {
+            if let (Ok(id), Some(name)) = (id.parse(), deck["name"].as_str()) {
+                names.insert(id, name.to_owned());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Read the notes of an Anki package as cards, with the first two fields
+/// of each note as its prompt and response.
+///
+/// With `with_counts`, hits and misses are counted from the package's review log,
+/// where "Again" is a miss and the other answers are hits.
+pub fn read_package(data: &[u8], with_counts: bool) -> Result<Vec<Card>> {
+    let archive = Archive::new(data)?;
+    let name = COLLECTIONS
+        .into_iter()
+        .find(|name| archive.contains(name))
+        .ok_or_else(|| anyhow!("no Anki collection in package"))?;
+    let mut collection = archive.read(name)?;
+    if name.ends_with('b') {
+        let mut decoder = ruzstd::StreamingDecoder::new(collection.as_slice())
+            .map_err(|e| anyhow!("cannot decompress Anki collection: {e:?}"))?;
+        let mut decompressed = Vec::new();
+        decoder
+            .read_to_end(&mut decompressed)
+            .context("decompressing Anki collection")?;
+        collection = decompressed;
+    }
+    let db = Database::new(&collection)?;
+    let decks = deck_names(&db)?;
+
+    // cards: id, nid, did, ...
+    let mut note_decks = HashMap::new();
+    let mut card_notes = HashMap::new();
+    for row in db.table("cards")? {
+        if let (Some(id), Some(note), Some(deck)) = (
+            row.first().and_then(Value::as_i64),
+            row.get(1).and_then(Value::as_i64),
+            row.get(2).and_then(Value::as_i64),
+        ) {
+            card_notes.insert(id, note);
+            note_decks.entry(note).or_insert(deck);
+        }
+    }
+
+    // revlog: id, cid, usn, ease, ...
+    let mut counts: HashMap<i64, (usize, usize)> = HashMap::new();
+    if with_counts {
+        for row in db.table("revlog")? {
+            let note = row
+                .get(1)
+                .and_then(Value::as_i64)
+                .and_then(|c| card_notes.get(&c));
+            let ease = row.get(3).and_then(Value::as_i64).unwrap_or(0);
+            if let (Some(note), true) = (note, ease > 0) {
+                let (hits, misses) = counts.entry(*note).or_default();
+                if ease == EASE_AGAIN {
+                    *misses += 1;
+                } else {
+                    *hits += 1;
+                }
+            }
+        }
+    }
+
+    // notes: id, guid, mid, mod, usn, tags, flds, ...
+    let mut cards = Vec::new();
+    for row in db.table("notes")? {
+        let id = row
+            .first()
+            .and_then(Value::as_i64)
+            .context("Anki note without an id")?;
+        let tags = row.get(5).and_then(Value::as_str).unwrap_or_default();
+        let fields = row.get(6).and_then(Value::as_str).unwrap_or_default();
+        let mut fields = fields.split(FIELD_SEPARATOR).map(html_to_text);
+        let mut card = Card::new(
+            &fields.next().unwrap_or_default(),
+            &fields.next().unwrap_or_default(),
+        );
+        // note ids are creation times in milliseconds, so they make stable card ids,
+        // and any that JavaScript can't represent get new ones
+        card.id = CardId::try_from(id)
+            .ok()
+            .filter(|id| *id < CARD_ID_LIMIT)
+            .unwrap_or_default();
+        card.tags = parse_tags(tags);
+        card.deck = note_decks
+            .get(&id)
+            .and_then(|deck| decks.get(deck))
+            .cloned()
+            .unwrap_or_default();
+        (card.hits, card.misses) = counts.get(&id).copied().unwrap_or_default();
+        cards.push(card);
+    }
+    Ok(cards)
+}
+
+/// Whether text starts with the header lines of Anki's plain-text export
+pub fn is_text_export(text: &str) -> bool {
+    text.starts_with("#separator:") || text.starts_with("#html:")
+}
+
+/// Prepare Anki's plain-text export for the column-mapping preview,
+/// using its header lines to find the delimiter and the tag and deck columns.
+pub fn text_import(file: String, text: String) -> TableImport {
+    let mut headers = HashMap::new();
+    for line in text.lines().take_while(|l| l.starts_with('#')) {
+        if let Some((key, value)) = line[1..].split_once(':') {
+            headers.insert(key.to_owned(), value.trim().to_owned());
+        }
+    }
+    let delimiter = match headers.get("separator").map(|s| s.as_str()) {
+        Some("comma" | ",") => Delimiter::Comma,
+        _ => Delimiter::Tab,
+    };
+    let html = headers.get("html").map(|s| s.as_str()) != Some("false");
+    let special = |key: &str| {
+        headers
+            .get(key)
+            .and_then(|n| n.parse::<usize>().ok())
+            .and_then(|n| n.checked_sub(1))
+    };
+    let mut import = TableImport::new(file, text, Some(delimiter));
+    import.has_header = false;
+    import.skip_comments = true;
+    import.html = html;
+    let n_columns = import.columns.len();
+    let mut columns = vec![None; n_columns];
+    for (key, field) in [
+        ("tags column", Field::Tags),
+        ("deck column", Field::Deck),
+        ("guid column", Field::Ignore),
+        ("notetype column", Field::Ignore),
+    ] {
+        if let Some(column) = special(key).filter(|c| *c < n_columns) {
+            columns[column] = Some(field);
+        }
+    }
+    let mut note_fields = [Field::Prompt, Field::Response].into_iter();
+    import.columns = columns
+        .into_iter()
+        .map(|field| {
+            field
+                .or_else(|| note_fields.next())
+                .unwrap_or(Field::Ignore)
+        })
+        .collect();
+    import
+}
+
+/// Write cards in Anki's plain-text format, ready for its File > Import.
+pub fn export_text(cards: &[Card]) -> String {
+    let mut text = "#separator:tab\n#html:false\n#tags column:3\n#deck column:4\n".to_owned();
+    for card in cards {
+        let fields = [
+            card.prompt.clone(),
+            card.response.clone(),
+            card.tags.join(" "),
+            card.deck.clone(),
+        ];
+        let fields = fields.map(|f| delimited::quote(&f, Delimiter::Tab));
+        text.push_str(&fields.join("\t"));
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // made with Python's sqlite3 and zipfile modules, using Anki's schema
+    // for collection.anki2, with 1 KiB pages so that the notes need an
+    // interior page and the last note's response spills onto overflow pages
+    const PACKAGE: &[u8] = include_bytes!("../tests/fixtures/deck.apkg");
+
+    fn summary(card: &Card) -> (u64, &str, &str, &str, Vec<&str>, usize, usize) {
+        (
+            card.id,
+            card.prompt.as_str(),
+            card.response.as_str(),
+            card.deck.as_str(),
+            card.tags.iter().map(String::as_str).collect(),
+            card.hits,
+            card.misses,
+        )
+    }
+
+    #[test]
+    fn notes_are_read_as_cards() {
+        let cards = read_package(PACKAGE, false).unwrap();
+        assert_eq!(cards.len(), 41);
+        let first = cards[..3].iter().map(summary).collect::<Vec<_>>();
+        assert_eq!(
+            first,
+            vec![
+                (
+                    1500000000000,
+                    "hablar",
+                    "to speak",
+                    "Spanish::Verbs",
+                    vec!["verbs", "regular"],
+                    0,
+                    0
+                ),
+                (
+                    1500000000001,
+                    "comer",
+                    "to eat & drink",
+                    "Spanish::Verbs",
+                    vec![],
+                    0,
+                    0
+                ),
+                (
+                    1500000000002,
+                    "line one\nline two",
+                    "beber",
+                    "Default",
+                    vec![],
+                    0,
+                    0
+                ),
+            ]
+        );
+        let long = cards.last().unwrap();
+        let response = (0..700).map(|i| format!("{i:05} ")).collect::<String>();
+        assert_eq!(long.prompt, "long");
+        assert_eq!(long.response, response.trim());
+    }
+
+    #[test]
+    fn answers_in_the_review_log_are_counted() {
+        let cards = read_package(PACKAGE, true).unwrap();
+        let counts = cards[..3]
+            .iter()
+            .map(|c| (c.hits, c.misses))
+            .collect::<Vec<_>>();
+        // "Again", "Good", and "Easy"; "Hard"; and a manual reschedule, which isn't an answer
+        assert_eq!(counts, vec![(2, 1), (1, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn a_package_without_notes_has_no_cards() {
+        let empty = include_bytes!("../tests/fixtures/empty.apkg");
+        assert_eq!(read_package(empty, true).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn truncated_and_other_files_are_errors() {
+        let truncated = include_bytes!("../tests/fixtures/truncated.apkg");
+        assert!(read_package(truncated, false).is_err());
+        let database = include_bytes!("../tests/fixtures/overflow.sqlite");
+        assert!(read_package(database, false).is_err());
+    }
+
+    #[test]
+    fn html_becomes_plain_text() {
+        assert_eq!(
+            html_to_text("<div>uno</div><div>dos<br/>tres</div>"),
+            "uno\ndos\ntres"
+        );
+        assert_eq!(
+            html_to_text("<b>fish</b> &amp; chips&nbsp;&lt;3 &#233;&#xE9; &bogus;"),
+            "fish & chips <3 éé &bogus;"
+        );
+        assert_eq!(html_to_text("<br><p>line</p>"), "line");
+    }
+
+    #[test]
+    fn plain_text_exports_read_their_own_headers() {
+        let text = "#separator:comma\n#html:true\n#deck column:1\n#tags column:4\n\
+                    Spanish,<i>perro</i>,dog,animals pets\n"
+            .to_owned();
+        assert!(is_text_export(&text));
+        let (cards, problems) = text_import("notes.txt".to_owned(), text).cards().unwrap();
+        assert!(problems.is_empty());
+        let summary = cards
+            .iter()
+            .map(|c| {
+                (
+                    c.deck.as_str(),
+                    c.prompt.as_str(),
+                    c.response.as_str(),
+                    c.tags.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(
+                "Spanish",
+                "perro",
+                "dog",
+                vec!["animals".to_owned(), "pets".to_owned()]
+            )]
+        );
+    }
+
+    #[test]
+    fn exported_text_imports_again() {
+        let mut card = Card::new("tab\there", "line\nbreak");
+        card.deck = "Spanish::Verbs".to_owned();
+        card.tags = vec!["a".to_owned(), "b".to_owned()];
+        let text = export_text(&[card.clone()]);
+        let (cards, problems) = text_import("notes.txt".to_owned(), text).cards().unwrap();
+        assert!(problems.is_empty());
+        assert_eq!(cards.len(), 1);
+        let read = &cards[0];
+        assert_eq!(
+            (&read.prompt, &read.response, &read.deck, &read.tags),
+            (&card.prompt, &card.response, &card.deck, &card.tags)
+        );
+    }
+}
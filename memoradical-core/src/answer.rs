@@ -0,0 +1,153 @@
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// one typo is forgiven for every this many characters of the expected answer
+const CHARS_PER_TYPO: usize = 6;
+
+/// Fold case, strip diacritics, and collapse whitespace, so that
+/// "  Café au LAIT" and "cafe au lait" compare equal.
+pub fn normalize(text: &str) -> String {
+    let folded = text
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase();
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffPart {
+    Same(String),
+    Missing(String), // expected but not typed
+    Extra(String),   // typed but not expected
+}
+
+/// The result of checking a typed answer against the expected one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checked {
+    pub correct: bool,
+    pub expected: String,
+    pub diff: Vec<DiffPart>,
+}
+
+fn push_part(parts: &mut Vec<DiffPart>, part: DiffPart) {
+    match (parts.last_mut(), part) {
+        (Some(DiffPart::Same(a)), DiffPart::Same(b))
+        | (Some(DiffPart::Missing(a)), DiffPart::Missing(b))
+        | (Some(DiffPart::Extra(a)), DiffPart::Extra(b)) => a.push_str(&b),
+        (_, part) => parts.push(part),
+    }
+}
+
+/// The edit distance between two strings and the edits that make up the difference.
+fn edits(expected: &[char], given: &[char]) -> (usize, Vec<DiffPart>) {
+    let (n, m) = (expected.len(), given.len());
+    let mut dist = vec![vec![0; m + 1]; n + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    dist[0] = (0..=m).collect();
+    for i in 1..=n {
+        for j in 1..=m {
+            let substitution = dist[i - 1][j - 1] + usize::from(expected[i - 1] != given[j - 1]);
+            dist[i][j] = substitution.min(dist[i - 1][j] + 1).min(dist[i][j - 1] + 1);
+        }
+    }
+    let mut parts = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && expected[i - 1] == given[j - 1] && dist[i][j] == dist[i - 1][j - 1] {
+            parts.push(DiffPart::Same(expected[i - 1].to_string()));
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && j > 0 && dist[i][j] == dist[i - 1][j - 1] + 1 {
+            parts.push(DiffPart::Extra(given[j - 1].to_string()));
+            parts.push(DiffPart::Missing(expected[i - 1].to_string()));
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && dist[i][j] == dist[i - 1][j] + 1 {
+            parts.push(DiffPart::Missing(expected[i - 1].to_string()));
+            i -= 1;
+        } else {
+            parts.push(DiffPart::Extra(given[j - 1].to_string()));
+            j -= 1;
+        }
+    }
+    let mut merged = Vec::new();
+    for part in parts.into_iter().rev() {
+        push_part(&mut merged, part);
+    }
+    (dist[n][m], merged)
+}
+
+/// Compare a typed answer to the expected one, forgiving differences in case,
+/// whitespace, and diacritics, as well as the occasional typo.
+pub fn check(expected: &str, given: &str) -> Checked {
+    let expected = normalize(expected);
+    let given = normalize(given);
+    let expected_chars = expected.chars().collect::<Vec<_>>();
+    let given_chars = given.chars().collect::<Vec<_>>();
+    let (distance, diff) = edits(&expected_chars, &given_chars);
+    let tolerance = expected_chars.len() / CHARS_PER_TYPO;
+    Checked {
+        correct: !given.is_empty() && distance <= tolerance,
+        expected,
+        diff,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_diacritics_and_spacing_are_ignored() {
+        assert_eq!(normalize("  Café au\tLAIT "), "cafe au lait");
+        assert_eq!(normalize("Ñandú"), "nandu");
+        assert!(check("Crème brûlée", "creme  BRULEE").correct);
+    }
+
+    #[test]
+    fn a_typo_is_forgiven_for_every_six_characters() {
+        assert!(!check("gato", "gata").correct);
+        assert!(check("mariposa", "maripsa").correct);
+        assert!(check("buenos días", "bueno dias").correct);
+        assert!(!check("buenos días", "buen dias").correct);
+        assert!(!check("buenos días", "buenos dias extra").correct);
+    }
+
+    #[test]
+    fn an_empty_answer_is_never_correct() {
+        assert!(!check("", "").correct);
+        assert!(!check("a", "  ").correct);
+    }
+
+    #[test]
+    fn the_diff_shows_what_was_missing_and_extra() {
+        let checked = check("Perro", "pero");
+        assert_eq!(checked.expected, "perro");
+        assert_eq!(
+            checked.diff,
+            vec![
+                DiffPart::Same("pe".to_owned()),
+                DiffPart::Missing("r".to_owned()),
+                DiffPart::Same("ro".to_owned()),
+            ]
+        );
+        assert_eq!(
+            check("sol", "sal").diff,
+            vec![
+                DiffPart::Same("s".to_owned()),
+                DiffPart::Missing("o".to_owned()),
+                DiffPart::Extra("a".to_owned()),
+                DiffPart::Same("l".to_owned()),
+            ]
+        );
+        assert_eq!(
+            check("sol", "sols").diff,
+            vec![
+                DiffPart::Same("sol".to_owned()),
+                DiffPart::Extra("s".to_owned()),
+            ]
+        );
+    }
+}
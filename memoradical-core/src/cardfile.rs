@@ -0,0 +1,104 @@
+//! Card files on disk, for the clients that run outside the browser
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::schema::{self, Envelope, Settings};
+use crate::{assign_missing_decks, assign_missing_ids, Card, DEFAULT_DECK};
+
+/// Read settings and cards from an envelope or a bare array of cards,
+/// giving ids and decks to any cards that lack them.
+///
+/// Any card that cannot be read is an error,
+/// so that writing the file back cannot lose it.
+pub fn read(path: &Path) -> Result<(Settings, Vec<Card>)> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let (settings, mut cards, problems) = schema::read(&path.display().to_string(), &text);
+    if !problems.is_empty() {
+        let report = problems
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(anyhow!("{report}"));
+    }
+    assign_missing_ids(&mut cards);
+    assign_missing_decks(&mut cards, DEFAULT_DECK);
+    Ok((settings, cards))
+}
+
+/// Save settings and cards in an envelope, replacing the file atomically.
+pub fn write(path: &Path, settings: &Settings, cards: &[Card]) -> Result<()> {
+    let text = serde_json::to_string_pretty(&Envelope::new(settings, cards))
+        .context("serializing cards")?;
+    write_atomically(path, text.as_bytes())
+}
+
+/// Write to a temporary file beside the destination and then rename it,
+/// so that the destination is never left half written.
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file name", path.display()))?;
+    let temp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let written = fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written.with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_files_read_back_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cards.json");
+        let settings = Settings {
+            reverse_mode: true,
+            ..Settings::default()
+        };
+        let cards = vec![Card::new("a", "b")];
+        write(&path, &settings, &cards).unwrap();
+        write(&path, &settings, &cards).unwrap();
+        assert_eq!(read(&path).unwrap(), (settings, cards));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn legacy_cards_get_ids_and_decks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cards.json");
+        let text = r#"[{"prompt": "a", "response": "b", "hits": 1, "misses": 0,
+            "reverse_hits": 0, "reverse_misses": 0,
+            "sm2": null, "reverse_sm2": null, "fsrs": null, "reverse_fsrs": null}]"#;
+        fs::write(&path, text).unwrap();
+        let (_, cards) = read(&path).unwrap();
+        assert!(cards[0].id != 0);
+        assert_eq!(cards[0].deck, DEFAULT_DECK);
+    }
+
+    #[test]
+    fn unreadable_cards_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cards.json");
+        fs::write(&path, "[{\"prompt\": 3}]").unwrap();
+        let error = read(&path).unwrap_err().to_string();
+        assert!(error.contains("cards.json: line 1"), "{error}");
+        assert!(read(&dir.path().join("missing.json")).is_err());
+    }
+}
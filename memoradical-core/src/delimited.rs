@@ -0,0 +1,425 @@
+use anyhow::{anyhow, Result};
+
+use crate::anki::html_to_text;
+use crate::tags::parse_tags;
+use crate::validate::{snippet, Problem};
+use crate::{Card, CARD_ID_LIMIT};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delimiter {
+    Comma,
+    Tab,
+}
+
+impl Delimiter {
+    pub const ALL: [Delimiter; 2] = [Delimiter::Comma, Delimiter::Tab];
+
+    pub fn char(&self) -> char {
+        match self {
+            Delimiter::Comma => ',',
+            Delimiter::Tab => '\t',
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Delimiter::Comma => "comma (CSV)",
+            Delimiter::Tab => "tab (TSV)",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Delimiter::Comma => "csv",
+            Delimiter::Tab => "tsv",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Delimiter::Comma => "text/csv",
+            Delimiter::Tab => "text/tab-separated-values",
+        }
+    }
+
+    /// The delimiter implied by a file name, if it names a delimited text file
+    pub fn from_filename(name: &str) -> Option<Self> {
+        let extension = name.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(Delimiter::Comma),
+            "tsv" | "tab" | "dat" => Some(Delimiter::Tab),
+            _ => None,
+        }
+    }
+
+    /// Whichever delimiter appears more often in the first line
+    fn guess(text: &str) -> Self {
+        let line = text.lines().next().unwrap_or_default();
+        if line.matches('\t').count() >= line.matches(',').count() {
+            Delimiter::Tab
+        } else {
+            Delimiter::Comma
+        }
+    }
+}
+
+/// What a column of a delimited file holds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Ignore,
+    Prompt,
+    Response,
+    Tags,
+    Hits,
+    Misses,
+    Deck,
+    Id,
+}
+
+impl Field {
+    pub const ALL: [Field; 8] = [
+        Field::Ignore,
+        Field::Prompt,
+        Field::Response,
+        Field::Tags,
+        Field::Hits,
+        Field::Misses,
+        Field::Deck,
+        Field::Id,
+    ];
+
+    // also the column names used in exported files
+    pub fn label(&self) -> &'static str {
+        match self {
+            Field::Ignore => "ignore",
+            Field::Prompt => "prompt",
+            Field::Response => "response",
+            Field::Tags => "tags",
+            Field::Hits => "hits",
+            Field::Misses => "misses",
+            Field::Deck => "deck",
+            Field::Id => "id",
+        }
+    }
+
+    fn from_header(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        Field::ALL
+            .into_iter()
+            .find(|f| *f != Field::Ignore && f.label() == name)
+    }
+}
+
+// in the order they are exported
+const EXPORTED: [Field; 7] = [
+    Field::Prompt,
+    Field::Response,
+    Field::Tags,
+    Field::Hits,
+    Field::Misses,
+    Field::Deck,
+    Field::Id,
+];
+
+/// One line of delimited text, or more if a quoted field spans lines
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub line: usize, // where it starts, counting from 1
+    pub fields: Vec<String>,
+}
+
+/// Split delimited text into records of fields.
+///
+/// Fields may be quoted with double quotes, inside which delimiters and
+/// line breaks are literal and a doubled quote stands for one quote.
+/// Blank lines are skipped.
+pub fn parse(text: &str, delimiter: Delimiter) -> Vec<Record> {
+    let delimiter = delimiter.char();
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' || (c == '\r' && chars.peek() != Some(&'\n')) {
+            line += 1;
+        }
+        if quoted {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                field.push('"');
+                chars.next();
+            } else {
+                quoted = false;
+            }
+        } else if c == '"' && field.is_empty() {
+            quoted = true;
+        } else if c == delimiter {
+            record.push(std::mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+                line += 1;
+            }
+            record.push(std::mem::take(&mut field));
+            end_record(&mut records, record_line, std::mem::take(&mut record));
+            record_line = line;
+        } else {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        end_record(&mut records, record_line, record);
+    }
+    records
+}
+
+fn end_record(records: &mut Vec<Record>, line: usize, fields: Vec<String>) {
+    if fields.len() > 1 || fields.iter().any(|f| !f.is_empty()) {
+        records.push(Record { line, fields });
+    }
+}
+
+/// Quote a field if it holds anything that would otherwise end it.
+pub fn quote(field: &str, delimiter: Delimiter) -> String {
+    if field.contains(|c| c == delimiter.char() || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Write cards as delimited text with a header line naming the columns.
+pub fn export(cards: &[Card], delimiter: Delimiter) -> String {
+    let separator = delimiter.char().to_string();
+    let mut lines = vec![EXPORTED
+        .iter()
+        .map(|f| f.label())
+        .collect::<Vec<_>>()
+        .join(&separator)];
+    for card in cards {
+        let fields = EXPORTED.map(|f| match f {
+            Field::Prompt => card.prompt.clone(),
+            Field::Response => card.response.clone(),
+            Field::Tags => card.tags.join(" "),
+            Field::Hits => card.hits.to_string(),
+            Field::Misses => card.misses.to_string(),
+            Field::Deck => card.deck.clone(),
+            Field::Id => card.id.to_string(),
+            Field::Ignore => String::new(),
+        });
+        let fields = fields.map(|f| quote(&f, delimiter));
+        lines.push(fields.join(&separator));
+    }
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+/// An uploaded delimited file waiting for its columns to be mapped to card fields
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableImport {
+    pub file: String,
+    text: String,
+    pub delimiter: Delimiter,
+    pub has_header: bool,
+    pub skip_comments: bool, // lines starting with "#"
+    pub html: bool,          // prompts and responses are HTML to be made plain text
+    pub columns: Vec<Field>,
+    records: Vec<Record>,
+}
+
+impl TableImport {
+    pub fn new(file: String, text: String, delimiter: Option<Delimiter>) -> Self {
+        let delimiter = delimiter.unwrap_or_else(|| Delimiter::guess(&text));
+        let mut import = TableImport {
+            file,
+            text,
+            delimiter,
+            has_header: false,
+            skip_comments: true,
+            html: false,
+            columns: vec![],
+            records: vec![],
+        };
+        import.set_delimiter(delimiter);
+        import
+    }
+
+    /// Re-split the text and guess the columns again.
+    pub fn set_delimiter(&mut self, delimiter: Delimiter) {
+        self.delimiter = delimiter;
+        self.records = parse(&self.text, delimiter);
+        let n_columns = self
+            .records
+            .iter()
+            .map(|r| r.fields.len())
+            .max()
+            .unwrap_or(0);
+        let named = self.records.first().map(|header| {
+            header
+                .fields
+                .iter()
+                .map(|name| Field::from_header(name))
+                .collect::<Vec<_>>()
+        });
+        self.has_header = named
+            .as_ref()
+            .is_some_and(|fields| fields.iter().any(|f| f.is_some()));
+        self.columns = if self.has_header {
+            named
+                .unwrap()
+                .into_iter()
+                .map(|f| f.unwrap_or(Field::Ignore))
+                .collect()
+        } else {
+            vec![Field::Prompt, Field::Response]
+        };
+        self.columns.resize(n_columns, Field::Ignore);
+    }
+
+    pub fn header(&self) -> Option<&[String]> {
+        if self.has_header {
+            self.records.first().map(|r| r.fields.as_slice())
+        } else {
+            None
+        }
+    }
+
+    /// The records that become cards, with the lines where they start
+    pub fn rows(&self) -> impl Iterator<Item = (usize, &[String])> {
+        let skip_comments = self.skip_comments;
+        self.records
+            .iter()
+            .skip(usize::from(self.has_header))
+            .filter(move |r| !(skip_comments && r.fields[0].starts_with('#')))
+            .map(|r| (r.line, r.fields.as_slice()))
+    }
+
+    /// Make cards from the rows according to the column mapping,
+    /// along with the problems in rows that could not be made into cards.
+    ///
+    /// Cards without a deck or an id are left for the caller to assign.
+    pub fn cards(&self) -> Result<(Vec<Card>, Vec<Problem>)> {
+        for required in [Field::Prompt, Field::Response] {
+            if !self.columns.contains(&required) {
+                return Err(anyhow!("No column is mapped to {}", required.label()));
+            }
+        }
+        let mut cards = Vec::new();
+        let mut problems = Vec::new();
+        'rows: for (line, row) in self.rows() {
+            let mut card = Card::new("", "");
+            card.id = 0;
+            card.deck = String::new();
+            for (column, (field, value)) in self.columns.iter().zip(row).enumerate() {
+                let number = match value.trim() {
+                    "" => Some(0),
+                    n => n.parse::<usize>().ok(),
+                };
+                let is_number = matches!(field, Field::Hits | Field::Misses | Field::Id);
+                let message = if is_number && number.is_none() {
+                    Some("expected a whole number")
+                } else if *field == Field::Id && number.unwrap_or_default() as u64 >= CARD_ID_LIMIT
+                {
+                    Some("expected an id below 2^53")
+                } else {
+                    None
+                };
+                if let Some(message) = message {
+                    problems.push(Problem {
+                        file: self.file.clone(),
+                        line: Some(line),
+                        column: Some(column + 1),
+                        field: Some(field.label().to_owned()),
+                        message: message.to_owned(),
+                        snippet: snippet(value),
+                    });
+                    continue 'rows;
+                }
+                let number = number.unwrap_or_default();
+                match field {
+                    Field::Ignore => (),
+                    Field::Prompt => card.prompt = value.clone(),
+                    Field::Response => card.response = value.clone(),
+                    Field::Tags => card.tags = parse_tags(value),
+                    Field::Hits => card.hits = number,
+                    Field::Misses => card.misses = number,
+                    Field::Deck => card.deck = value.trim().to_owned(),
+                    Field::Id => card.id = number as u64,
+                }
+            }
+            if self.html {
+                card.prompt = html_to_text(&card.prompt);
+                card.response = html_to_text(&card.response);
+            }
+            cards.push(card);
+        }
+        Ok((cards, problems))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields_hold_delimiters_quotes_and_line_breaks() {
+        let text = "a,\"b, \"\"c\"\"\"\r\n\n\"multi\nline\",d\ne";
+        let records = parse(text, Delimiter::Comma);
+        let fields = records.iter().map(|r| &r.fields).collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                &vec!["a".to_owned(), "b, \"c\"".to_owned()],
+                &vec!["multi\nline".to_owned(), "d".to_owned()],
+                &vec!["e".to_owned()],
+            ]
+        );
+        let lines = records.iter().map(|r| r.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn exported_cards_import_again() {
+        let mut card = Card::new("bonjour, \"hello\"", "hello\nthere");
+        card.tags = vec!["french".to_owned(), "greetings".to_owned()];
+        card.hits = 3;
+        card.deck = "French".to_owned();
+        for delimiter in [Delimiter::Comma, Delimiter::Tab] {
+            let text = export(&[card.clone()], delimiter);
+            let import = TableImport::new("cards".to_owned(), text, None);
+            assert_eq!(import.delimiter, delimiter);
+            assert!(import.has_header);
+            let (cards, problems) = import.cards().unwrap();
+            assert!(problems.is_empty());
+            assert_eq!(cards, vec![card.clone()]);
+        }
+    }
+
+    #[test]
+    fn bad_rows_are_reported_and_comments_skipped() {
+        let text = "prompt\tresponse\thits\na\tb\t3\nc\td\tmany\n# e\tf\tnone\n".to_owned();
+        let mut import = TableImport::new("cards.tsv".to_owned(), text, None);
+        let (cards, problems) = import.cards().unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].hits, 3);
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].line, problems[0].column), (Some(3), Some(3)));
+        import.columns = vec![Field::Prompt, Field::Ignore, Field::Hits];
+        assert!(import.cards().is_err());
+    }
+
+    #[test]
+    fn ids_too_large_for_javascript_are_reported() {
+        let text = format!("id\tprompt\tresponse\n7\ta\tb\n{CARD_ID_LIMIT}\tc\td\n");
+        let import = TableImport::new("cards.tsv".to_owned(), text, None);
+        let (cards, problems) = import.cards().unwrap();
+        assert_eq!(cards.iter().map(|c| c.id).collect::<Vec<_>>(), vec![7]);
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].line, problems[0].column), (Some(3), Some(1)));
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+const DECAY: f64 = -0.5;
+const FACTOR: f64 = 19.0 / 81.0; // makes retrievability 0.9 after one stability interval
+const MAX_DIFFICULTY: f64 = 10.0;
+const MIN_DIFFICULTY: f64 = 1.0;
+const MIN_STABILITY: f64 = 0.01; // days
+
+// FSRS-4.5 default model weights
+const W: [f64; 17] = [
+    0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072,
+    0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+];
+
+// FSRS ratings run from 1 (again) through 4 (easy)
+pub const RATING_AGAIN: u8 = 1;
+pub const RATING_HARD: u8 = 2;
+pub const RATING_GOOD: u8 = 3;
+pub const RATING_EASY: u8 = 4;
+
+/// Memory state for one direction of a card in the Free Spaced Repetition Scheduler model.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fsrs {
+    pub stability: f64, // days until retrievability falls to 90%
+    pub difficulty: f64,
+    pub last_review: f64, // milliseconds since the epoch
+}
+
+fn initial_difficulty(rating: u8) -> f64 {
+    (W[4] - W[5] * (rating as f64 - 3.0)).clamp(MIN_DIFFICULTY, MAX_DIFFICULTY)
+}
+
+impl Fsrs {
+    /// The memory state after the first review of a card.
+    pub fn new(rating: u8, now: f64) -> Self {
+        let rating = rating.clamp(RATING_AGAIN, RATING_EASY);
+        Self {
+            stability: W[rating as usize - 1],
+            difficulty: initial_difficulty(rating),
+            last_review: now,
+        }
+    }
+
+    /// The probability of recalling the card at the given time.
+    pub fn retrievability(&self, now: f64) -> f64 {
+        let elapsed_days = ((now - self.last_review) / DAY_MS).max(0.0);
+        (1.0 + FACTOR * elapsed_days / self.stability).powf(DECAY)
+    }
+
+    /// The time at which retrievability falls to the target retention.
+    pub fn due(&self, target_retention: f64) -> f64 {
+        let interval = self.stability / FACTOR * (target_retention.powf(1.0 / DECAY) - 1.0);
+        self.last_review + interval * DAY_MS
+    }
+
+    pub fn review(&mut self, rating: u8, now: f64) {
+        let rating = rating.clamp(RATING_AGAIN, RATING_EASY);
+        let r = self.retrievability(now);
+        let d = self.difficulty;
+        let s = self.stability;
+        self.stability = if rating == RATING_AGAIN {
+            W[11] * d.powf(-W[12]) * ((s + 1.0).powf(W[13]) - 1.0) * (W[14] * (1.0 - r)).exp()
+        } else {
+            let hard_penalty = if rating == RATING_HARD { W[15] } else { 1.0 };
+            let easy_bonus = if rating == RATING_EASY { W[16] } else { 1.0 };
+            s * (1.0
+                + W[8].exp()
+                    * (11.0 - d)
+                    * s.powf(-W[9])
+                    * ((W[10] * (1.0 - r)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus)
+        }
+        .max(MIN_STABILITY);
+        // FSRS-4.5 pulls difficulty back toward that of a first "good"
+        let next_d = d - W[6] * (rating as f64 - 3.0);
+        let reverted = W[7] * initial_difficulty(RATING_GOOD) + (1.0 - W[7]) * next_d;
+        self.difficulty = reverted.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY);
+        self.last_review = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(n: f64) -> f64 {
+        n * DAY_MS
+    }
+
+    #[test]
+    fn reviews_follow_the_fsrs_4_5_reference() {
+        // worked through the published FSRS-4.5 formulas with the default weights
+        let mut fsrs = Fsrs::new(RATING_GOOD, day(0.0));
+        assert_eq!((fsrs.stability, fsrs.difficulty), (W[2], W[4]));
+        let expected = [
+            (day(3.0), RATING_GOOD, 12.2624, 5.1618),
+            (day(10.0), RATING_AGAIN, 2.6781, 6.9012),
+            (day(11.0), RATING_HARD, 3.1755, 7.7169),
+            (day(20.0), RATING_EASY, 38.4512, 6.768),
+        ];
+        for (now, rating, stability, difficulty) in expected {
+            fsrs.review(rating, now);
+            assert!((fsrs.stability - stability).abs() < 1e-4, "{fsrs:?}");
+            assert!((fsrs.difficulty - difficulty).abs() < 1e-4, "{fsrs:?}");
+            assert_eq!(fsrs.last_review, now);
+        }
+    }
+
+    #[test]
+    fn retention_falls_to_ninety_percent_after_the_stability() {
+        let fsrs = Fsrs::new(RATING_EASY, day(1.0));
+        let due = fsrs.due(0.9);
+        assert!((due - day(1.0 + W[3])).abs() < 1.0);
+        assert!((fsrs.retrievability(due) - 0.9).abs() < 1e-9);
+        assert_eq!(fsrs.retrievability(day(0.0)), 1.0);
+        assert!(fsrs.due(0.8) > due);
+    }
+
+    #[test]
+    fn first_ratings_set_difficulty_within_bounds() {
+        let difficulties = (RATING_AGAIN..=RATING_EASY)
+            .map(|rating| Fsrs::new(rating, 0.0).difficulty)
+            .collect::<Vec<_>>();
+        assert!(difficulties.windows(2).all(|d| d[0] > d[1]));
+        assert!(difficulties
+            .iter()
+            .all(|d| (MIN_DIFFICULTY..=MAX_DIFFICULTY).contains(d)));
+        assert_eq!(Fsrs::new(0, 0.0), Fsrs::new(RATING_AGAIN, 0.0));
+    }
+}
@@ -0,0 +1,245 @@
+//! Undo and redo of changes to the cards, decks, and review log,
+//! each kept as what it replaced rather than as a whole copy
+
+use std::collections::{HashMap, HashSet};
+
+use crate::reviews::Review;
+use crate::{Card, CardId};
+
+/// How many changes can be undone, the oldest being forgotten first
+const DEPTH: usize = 100;
+
+/// Cards, decks, and the review log at some point
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Version {
+    pub cards: Vec<Card>,
+    pub decks: Vec<String>,
+    pub reviews: Vec<Review>,
+}
+
+/// What it takes to go from one version back to an earlier one
+struct Change {
+    cards: Vec<Card>,           // earlier versions of cards that changed or went
+    added: HashSet<CardId>,     // cards that were not there before
+    order: Option<Vec<CardId>>, // of the cards before, if it was different
+    decks: Option<Vec<String>>, // before, if they were different
+    reviews_kept: usize,        // reviews the same in both
+    reviews: Vec<Review>,       // earlier reviews after those
+}
+
+impl Change {
+    fn between(earlier: &Version, later: &Version) -> Self {
+        let later_cards = later
+            .cards
+            .iter()
+            .map(|c| (c.id, c))
+            .collect::<HashMap<_, _>>();
+        let earlier_ids = earlier.cards.iter().map(|c| c.id).collect::<HashSet<_>>();
+        let reviews_kept = earlier
+            .reviews
+            .iter()
+            .zip(&later.reviews)
+            .take_while(|(a, b)| a == b)
+            .count();
+        Change {
+            cards: earlier
+                .cards
+                .iter()
+                .filter(|c| later_cards.get(&c.id) != Some(c))
+                .cloned()
+                .collect(),
+            added: later_cards
+                .keys()
+                .filter(|id| !earlier_ids.contains(id))
+                .copied()
+                .collect(),
+            order: Some(earlier.cards.iter().map(|c| c.id).collect()),
+            decks: Some(earlier.decks.clone()),
+            reviews_kept,
+            reviews: earlier.reviews[reviews_kept..].to_vec(),
+        }
+    }
+
+    fn revert(&self, later: &Version) -> Version {
+        let mut cards = later
+            .cards
+            .iter()
+            .filter(|c| !self.added.contains(&c.id))
+            .map(|c| (c.id, c.clone()))
+            .collect::<HashMap<_, _>>();
+        cards.extend(self.cards.iter().map(|c| (c.id, c.clone())));
+        let mut reviews = later.reviews[..self.reviews_kept].to_vec();
+        reviews.extend(self.reviews.iter().cloned());
+        let later_order = || later.cards.iter().map(|c| c.id).collect();
+        Version {
+            cards: self
+                .order
+                .clone()
+                .unwrap_or_else(later_order)
+                .iter()
+                .filter_map(|id| cards.remove(id))
+                .collect(),
+            decks: self.decks.clone().unwrap_or_else(|| later.decks.clone()),
+            reviews,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl History {
+    /// Remember a change so that it can be undone, after which nothing undone can be redone.
+    pub fn record(&mut self, before: &Version, after: &Version) {
+        if before == after {
+            return;
+        }
+        self.push(Change::between(before, after));
+    }
+
+    /// Remember a response, given the card as it was before and the length of the log,
+    /// since a response changes only its card and adds to the log,
+    /// and comparing whole versions for each one would take as long as copying them.
+    pub fn record_review(&mut self, before: Card, reviews_kept: usize) {
+        self.push(Change {
+            cards: vec![before],
+            added: HashSet::new(),
+            order: None,
+            decks: None,
+            reviews_kept,
+            reviews: vec![],
+        });
+    }
+
+    fn push(&mut self, change: Change) {
+        self.undo.push(change);
+        if self.undo.len() > DEPTH {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// The version before the last change, if there is one to undo
+    pub fn undo(&mut self, current: &Version) -> Option<Version> {
+        let change = self.undo.pop()?;
+        let earlier = change.revert(current);
+        self.redo.push(Change::between(current, &earlier));
+        Some(earlier)
+    }
+
+    /// The version after the last undone change, if there is one to redo
+    pub fn redo(&mut self, current: &Version) -> Option<Version> {
+        let change = self.redo.pop()?;
+        let later = change.revert(current);
+        self.undo.push(Change::between(current, &later));
+        Some(later)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forget everything, once the versions no longer follow from each other.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reviews::Grade;
+
+    fn version(cards: &[(&str, &str)], decks: &[&str]) -> Version {
+        Version {
+            cards: cards
+                .iter()
+                .enumerate()
+                .map(|(i, (front, back))| Card {
+                    id: i as CardId + 1,
+                    ..Card::new(front, back)
+                })
+                .collect(),
+            decks: decks.iter().map(|d| d.to_string()).collect(),
+            reviews: vec![],
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_walk_back_and_forth() {
+        let v1 = version(&[("a", "b")], &["Default"]);
+        let v2 = version(&[("a", "B"), ("c", "d")], &["Default", "French"]);
+        let v3 = version(&[("c", "d")], &["French"]);
+        let mut history = History::default();
+        history.record(&v1, &v2);
+        history.record(&v2, &v3);
+        assert_eq!(history.undo(&v3), Some(v2.clone()));
+        assert_eq!(history.undo(&v2), Some(v1.clone()));
+        assert_eq!(history.undo(&v1), None);
+        assert_eq!(history.redo(&v1), Some(v2.clone()));
+        assert_eq!(history.redo(&v2), Some(v3.clone()));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn responses_are_undone_from_the_card_they_changed() {
+        let before = version(&[("a", "b"), ("c", "d")], &["Default"]);
+        let mut after = before.clone();
+        let review = Review {
+            card: 2,
+            reverse: false,
+            grade: Grade::Good,
+            timestamp: 1000.0,
+            latency_ms: 500.0,
+            confused_with: None,
+        };
+        after.cards[1].apply_review(&review);
+        after.reviews.push(review);
+        let mut history = History::default();
+        history.record_review(before.cards[1].clone(), before.reviews.len());
+        assert_eq!(history.undo(&after), Some(before.clone()));
+        assert_eq!(history.redo(&before), Some(after));
+    }
+
+    #[test]
+    fn a_new_change_drops_what_was_undone() {
+        let v1 = version(&[("a", "b")], &[]);
+        let v2 = version(&[("a", "c")], &[]);
+        let mut history = History::default();
+        history.record(&v1, &v2);
+        history.undo(&v2);
+        assert!(history.can_redo());
+        history.record(&v1, &v2);
+        assert!(!history.can_redo());
+        history.record(&v2, &v2);
+        history.undo(&v2);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn only_the_deepest_changes_are_kept() {
+        let mut history = History::default();
+        let versions = (0..=DEPTH + 5)
+            .map(|i| version(&[("a", &i.to_string())], &[]))
+            .collect::<Vec<_>>();
+        for pair in versions.windows(2) {
+            history.record(&pair[0], &pair[1]);
+        }
+        let mut current = versions.last().unwrap().clone();
+        let mut n = 0;
+        while let Some(earlier) = history.undo(&current) {
+            current = earlier;
+            n += 1;
+        }
+        assert_eq!(n, DEPTH);
+        assert_eq!(current, versions[5]);
+    }
+}
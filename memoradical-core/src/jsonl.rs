@@ -0,0 +1,46 @@
+//! Cards as JSON Lines, one card to a line, for tools that work a record at a time
+
+use crate::schema::{self, LEGACY_VERSION};
+use crate::validate::{self, Problem};
+use crate::Card;
+
+/// Read a card from each line, upgrading cards saved before the envelope,
+/// and keeping the good cards while reporting each bad one.
+pub fn read(file: &str, text: &str) -> (Vec<Card>, Vec<Problem>) {
+    let upgrade = |card| schema::upgrade_card(card, LEGACY_VERSION);
+    validate::upgraded_json_lines(file, text, &upgrade)
+}
+
+pub fn export(cards: &[Card]) -> String {
+    cards
+        .iter()
+        .map(|card| serde_json::to_string(card).unwrap_or_default() + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_cards_read_back() {
+        let mut card = Card::new("line\nbreak", "\"quoted\"");
+        card.tags = vec!["a".to_owned()];
+        card.reverse_hits = 2;
+        let cards = vec![card, Card::new("second", "card")];
+        let (read_cards, problems) = read("cards.jsonl", &export(&cards));
+        assert!(problems.is_empty());
+        assert_eq!(read_cards, cards);
+    }
+
+    #[test]
+    fn bad_lines_are_reported_where_they_are() {
+        let good = serde_json::to_string(&Card::new("good", "card")).unwrap();
+        let text = format!("{good}\n\n{{\"prompt\": \"no response\"}}\r\n{good}\n");
+        let (cards, problems) = read("cards.jsonl", &text);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(3));
+        assert_eq!(problems[0].field.as_deref(), Some("response"));
+    }
+}
@@ -0,0 +1,327 @@
+//! Cards, how they are scheduled and scored, and the file formats they are kept in,
+//! with nothing that needs a browser
+
+use std::fmt;
+
+use serde::de::{self, Unexpected};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use fsrs::Fsrs;
+use reviews::{GradeCounts, Review};
+use sm2::Sm2;
+
+pub mod anki;
+pub mod answer;
+pub mod cardfile;
+pub mod delimited;
+pub mod fsrs;
+pub mod history;
+pub mod jsonl;
+pub mod merge;
+pub mod quiz;
+pub mod restore;
+pub mod reviews;
+pub mod schema;
+pub mod select;
+pub mod sm2;
+mod sqlite;
+pub mod stats;
+pub mod sync;
+pub mod tags;
+mod unzip;
+pub mod upload;
+pub mod validate;
+
+pub const DEFAULT_DECK: &str = "Default";
+pub const DEFAULT_TARGET_RETENTION: f64 = 0.9;
+
+pub type CardId = u64;
+
+/// Card IDs stay below 2^53 so that JavaScript can represent them exactly.
+pub const CARD_ID_LIMIT: CardId = 1 << 53;
+
+/// Read an id, refusing any that JavaScript would round,
+/// so that such cards are reported where they are instead of stored under another id
+fn card_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CardId, D::Error> {
+    let id = CardId::deserialize(deserializer)?;
+    if id >= CARD_ID_LIMIT {
+        return Err(de::Error::invalid_value(
+            Unexpected::Unsigned(id),
+            &"an id below 2^53",
+        ));
+    }
+    Ok(id)
+}
+
+pub fn new_card_id() -> CardId {
+    rand::random::<u64>() >> 11
+}
+
+/// Give an ID to any card that lacks one, returning whether any were assigned.
+pub fn assign_missing_ids(cards: &mut [Card]) -> bool {
+    let mut assigned = false;
+    for card in cards.iter_mut().filter(|c| c.id == 0) {
+        card.id = new_card_id();
+        assigned = true;
+    }
+    assigned
+}
+
+/// Put any card that is not in a deck into the given one,
+/// returning whether any were moved.
+pub fn assign_missing_decks(cards: &mut [Card], deck: &str) -> bool {
+    let mut assigned = false;
+    for card in cards.iter_mut().filter(|c| c.deck.is_empty()) {
+        card.deck = deck.to_owned();
+        assigned = true;
+    }
+    assigned
+}
+
+/// How the next card is chosen during study
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheduler {
+    Beta, // random, weighted by hits and misses
+    Sm2,  // due dates from the SuperMemo-2 algorithm
+    Fsrs, // due dates from the Free Spaced Repetition Scheduler memory model
+}
+
+impl Scheduler {
+    pub const ALL: [Scheduler; 3] = [Scheduler::Beta, Scheduler::Sm2, Scheduler::Fsrs];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Scheduler::Beta => "random, prefer missed or neglected",
+            Scheduler::Sm2 => "due dates (SM-2)",
+            Scheduler::Fsrs => "due dates (FSRS)",
+        }
+    }
+}
+
+impl fmt::Display for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dbg = format!("{:?}", self).to_lowercase();
+        write!(f, "{}", dbg)
+    }
+}
+
+/// A card with its counters and schedules, which are kept on the card
+/// rather than counted from the review log, since card files and uploads
+/// carry counts without any log behind them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Card {
+    #[serde(default, deserialize_with = "card_id")]
+    pub id: CardId, // zero until assigned
+    #[serde(default)]
+    pub deck: String, // empty until assigned
+    pub prompt: String,
+    pub response: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>, // the file the card was uploaded from
+    pub hits: usize,
+    pub misses: usize,
+    pub reverse_hits: usize,
+    pub reverse_misses: usize,
+    #[serde(default)]
+    pub grades: GradeCounts,
+    #[serde(default)]
+    pub reverse_grades: GradeCounts,
+    pub sm2: Option<Sm2>,
+    pub reverse_sm2: Option<Sm2>,
+    pub fsrs: Option<Fsrs>,
+    pub reverse_fsrs: Option<Fsrs>,
+}
+
+impl Card {
+    pub fn new(front: &str, back: &str) -> Card {
+        Card {
+            id: new_card_id(),
+            deck: DEFAULT_DECK.to_owned(),
+            prompt: front.to_string(),
+            response: back.to_string(),
+            tags: vec![],
+            source: None,
+            hits: 0,
+            misses: 0,
+            reverse_hits: 0,
+            reverse_misses: 0,
+            grades: GradeCounts::default(),
+            reverse_grades: GradeCounts::default(),
+            sm2: None,
+            reverse_sm2: None,
+            fsrs: None,
+            reverse_fsrs: None,
+        }
+    }
+    /// Count a logged response and update the card's schedules.
+    pub fn apply_review(&mut self, review: &Review) {
+        let (hits, misses, grades) = if review.reverse {
+            (
+                &mut self.reverse_hits,
+                &mut self.reverse_misses,
+                &mut self.reverse_grades,
+            )
+        } else {
+            (&mut self.hits, &mut self.misses, &mut self.grades)
+        };
+        if review.grade.is_hit() {
+            *hits += 1;
+        } else {
+            *misses += 1;
+        }
+        grades.add(review.grade);
+        self.review_sm2(review.reverse, review.grade.sm2_quality(), review.timestamp);
+        self.review_fsrs(review.reverse, review.grade.fsrs_rating(), review.timestamp);
+    }
+    pub fn hits_misses(&self, reverse_mode: bool) -> (usize, usize) {
+        if reverse_mode {
+            (self.reverse_hits, self.reverse_misses)
+        } else {
+            (self.hits, self.misses)
+        }
+    }
+    /// Hits and misses adjusted for how hard or easy the hits were
+    pub fn weighted_hits_misses(&self, reverse_mode: bool) -> (f64, f64) {
+        let (hits, misses) = self.hits_misses(reverse_mode);
+        let grades = if reverse_mode {
+            &self.reverse_grades
+        } else {
+            &self.grades
+        };
+        grades.weigh(hits, misses)
+    }
+    pub fn sm2(&self, reverse_mode: bool) -> Option<&Sm2> {
+        if reverse_mode {
+            self.reverse_sm2.as_ref()
+        } else {
+            self.sm2.as_ref()
+        }
+    }
+    pub fn review_sm2(&mut self, reverse_mode: bool, quality: u8, now: f64) {
+        let sm2 = if reverse_mode {
+            &mut self.reverse_sm2
+        } else {
+            &mut self.sm2
+        };
+        sm2.get_or_insert_with(Sm2::default).review(quality, now);
+    }
+    pub fn fsrs(&self, reverse_mode: bool) -> Option<&Fsrs> {
+        if reverse_mode {
+            self.reverse_fsrs.as_ref()
+        } else {
+            self.fsrs.as_ref()
+        }
+    }
+    pub fn review_fsrs(&mut self, reverse_mode: bool, rating: u8, now: f64) {
+        let fsrs = if reverse_mode {
+            &mut self.reverse_fsrs
+        } else {
+            &mut self.fsrs
+        };
+        match fsrs {
+            Some(state) => state.review(rating, now),
+            None => *fsrs = Some(Fsrs::new(rating, now)),
+        }
+    }
+    /// Clear the hits, misses, and grades of one direction, leaving its schedules.
+    pub fn clear_counts(&mut self, reverse_mode: bool) {
+        if reverse_mode {
+            self.reverse_hits = 0;
+            self.reverse_misses = 0;
+            self.reverse_grades = GradeCounts::default();
+        } else {
+            self.hits = 0;
+            self.misses = 0;
+            self.grades = GradeCounts::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reviews::Grade;
+
+    fn review(grade: Grade, reverse: bool) -> Review {
+        Review {
+            card: 1,
+            reverse,
+            grade,
+            timestamp: 1000.0,
+            latency_ms: 500.0,
+            confused_with: None,
+        }
+    }
+
+    #[test]
+    fn clear_counts_leaves_the_other_direction() {
+        let mut card = Card::new("front", "back");
+        card.hits = 3;
+        card.misses = 2;
+        card.reverse_hits = 5;
+        card.reverse_misses = 4;
+        card.grades.hard = 2;
+        card.reverse_grades.easy = 1;
+        card.clear_counts(false);
+        assert_eq!(card.hits_misses(false), (0, 0));
+        assert_eq!(card.weighted_hits_misses(false), (0.0, 0.0));
+        assert_eq!(card.hits_misses(true), (5, 4));
+        assert_eq!(card.reverse_grades.easy, 1);
+        card.clear_counts(true);
+        assert_eq!(card.hits_misses(true), (0, 0));
+        assert_eq!(card.reverse_grades, GradeCounts::default());
+    }
+
+    #[test]
+    fn apply_review_counts_and_schedules_one_direction() {
+        let mut card = Card::new("front", "back");
+        card.apply_review(&review(Grade::Good, false));
+        card.apply_review(&review(Grade::Again, false));
+        card.apply_review(&review(Grade::Easy, true));
+        assert_eq!(card.hits_misses(false), (1, 1));
+        assert_eq!(card.hits_misses(true), (1, 0));
+        assert_eq!(card.grades.good, 1);
+        assert_eq!(card.grades.again, 1);
+        assert_eq!(card.reverse_grades.easy, 1);
+        assert_eq!(card.sm2(false).map(|s| s.repetitions), Some(0));
+        assert_eq!(card.sm2(true).map(|s| s.repetitions), Some(1));
+        assert!(card.fsrs(false).is_some() && card.fsrs(true).is_some());
+    }
+
+    #[test]
+    fn assign_missing_ids_and_decks() {
+        let mut cards = vec![Card::new("a", "b"), Card::new("c", "d")];
+        cards[0].id = 0;
+        cards[1].deck.clear();
+        let id = cards[1].id;
+        assert!(assign_missing_ids(&mut cards));
+        assert!(cards[0].id != 0 && cards[0].id < CARD_ID_LIMIT);
+        assert_eq!(cards[1].id, id);
+        assert!(!assign_missing_ids(&mut cards));
+        assert!(assign_missing_decks(&mut cards, "French"));
+        assert_eq!(cards[0].deck, DEFAULT_DECK);
+        assert_eq!(cards[1].deck, "French");
+    }
+
+    #[test]
+    fn ids_too_large_for_javascript_are_refused() {
+        let card = |id: CardId| {
+            let mut card = serde_json::to_value(Card::new("a", "b")).unwrap();
+            card["id"] = id.into();
+            card.to_string()
+        };
+        let below = serde_json::from_str::<Card>(&card(CARD_ID_LIMIT - 1)).unwrap();
+        assert_eq!(below.id, CARD_ID_LIMIT - 1);
+        let error = serde_json::from_str::<Card>(&card(CARD_ID_LIMIT)).unwrap_err();
+        assert!(error.to_string().contains("expected an id below 2^53"));
+    }
+
+    #[test]
+    fn scheduler_names() {
+        assert_eq!(Scheduler::Fsrs.to_string(), "fsrs");
+        assert_eq!(serde_json::to_string(&Scheduler::Sm2).unwrap(), "\"sm2\"");
+    }
+}
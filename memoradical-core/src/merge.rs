@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{Card, CardId};
+
+/// How an uploaded card differs from the existing card it matches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change {
+    New,
+    Identical,
+    Text,  // prompt, response, or tags
+    Stats, // only counters or schedules
+}
+
+impl Change {
+    pub const ALL: [Change; 4] = [Change::New, Change::Identical, Change::Text, Change::Stats];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Change::New => "new",
+            Change::Identical => "identical",
+            Change::Text => "changed text",
+            Change::Stats => "changed stats",
+        }
+    }
+
+    /// The strategies that make sense for this kind of change
+    pub fn strategies(&self) -> &'static [Strategy] {
+        match self {
+            Change::New => &[Strategy::TakeIncoming, Strategy::KeepExisting],
+            _ => &[
+                Strategy::KeepExisting,
+                Strategy::TakeIncoming,
+                Strategy::SumCounts,
+            ],
+        }
+    }
+}
+
+/// What to do with an uploaded card that matches an existing one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    KeepExisting,
+    TakeIncoming,
+    SumCounts, // take the incoming text and add up both cards' counters
+}
+
+impl Strategy {
+    pub fn label(&self, change: Change) -> &'static str {
+        match (self, change) {
+            (Strategy::TakeIncoming, Change::New) => "add",
+            (Strategy::KeepExisting, Change::New) => "skip",
+            (Strategy::KeepExisting, _) => "keep existing",
+            (Strategy::TakeIncoming, _) => "take incoming",
+            (Strategy::SumCounts, _) => "take incoming, sum counters",
+        }
+    }
+}
+
+/// The chosen strategy for each kind of change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Strategies([Strategy; 4]);
+
+impl Default for Strategies {
+    /// Add new cards and update text, without losing any progress.
+    fn default() -> Self {
+        Strategies([
+            Strategy::TakeIncoming,
+            Strategy::KeepExisting,
+            Strategy::SumCounts,
+            Strategy::KeepExisting,
+        ])
+    }
+}
+
+impl Strategies {
+    pub fn get(&self, change: Change) -> Strategy {
+        self.0[change as usize]
+    }
+
+    pub fn set(&mut self, change: Change, strategy: Strategy) {
+        self.0[change as usize] = strategy;
+    }
+}
+
+/// An uploaded card and how it compares with the existing cards
+#[derive(Clone, Debug)]
+pub struct Incoming {
+    pub card: Card,
+    pub existing: Option<CardId>, // or the earlier uploaded card that this one repeats
+    pub change: Change,
+}
+
+/// Fold case and collapse whitespace, so that "  Café" and "café" match.
+///
+/// Unlike typed answers, accents are kept, since "si" and "sí" are different cards.
+fn prompt_key(prompt: &str) -> String {
+    let folded = prompt.nfc().collect::<String>().to_lowercase();
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn same_text(a: &Card, b: &Card) -> bool {
+    a.prompt == b.prompt && a.response == b.response && a.tags == b.tags
+}
+
+fn same_stats(a: &Card, b: &Card) -> bool {
+    a.hits_misses(false) == b.hits_misses(false)
+        && a.hits_misses(true) == b.hits_misses(true)
+        && a.grades == b.grades
+        && a.reverse_grades == b.reverse_grades
+        && a.sm2 == b.sm2
+        && a.reverse_sm2 == b.reverse_sm2
+        && a.fsrs == b.fsrs
+        && a.reverse_fsrs == b.reverse_fsrs
+}
+
+/// Match each uploaded card to an existing one by id or else by prompt,
+/// ignoring case and spacing but not accents, and classify the difference.
+///
+/// A card that repeats an earlier uploaded card, as when two files share cards,
+/// is matched to that card instead and compared with its earlier copy,
+/// so that merging never adds the same card twice.
+pub fn classify(existing: &[Card], incoming: Vec<Card>) -> Vec<Incoming> {
+    let existing_cards = existing
+        .iter()
+        .map(|c| (c.id, c))
+        .collect::<HashMap<_, _>>();
+    // the ids and prompts seen so far, and the card each one is merged into
+    let mut by_id = existing
+        .iter()
+        .map(|c| (c.id, c.id))
+        .collect::<HashMap<_, _>>();
+    let mut by_prompt = HashMap::new();
+    for card in existing {
+        by_prompt.entry(prompt_key(&card.prompt)).or_insert(card.id);
+    }
+    // the latest uploaded copy of each card
+    let mut uploaded = HashMap::<CardId, Card>::new();
+    incoming
+        .into_iter()
+        .map(|card| {
+            let prompt = prompt_key(&card.prompt);
+            let target = by_id
+                .get(&card.id)
+                .or_else(|| by_prompt.get(&prompt))
+                .copied();
+            let matched = target.and_then(|id| {
+                uploaded
+                    .get(&id)
+                    .or_else(|| existing_cards.get(&id).copied())
+            });
+            let change = match matched {
+                None => Change::New,
+                Some(old) if !same_text(old, &card) => Change::Text,
+                Some(old) if !same_stats(old, &card) => Change::Stats,
+                Some(_) => Change::Identical,
+            };
+            let merged_into = target.unwrap_or(card.id);
+            by_id.insert(card.id, merged_into);
+            by_prompt.entry(prompt).or_insert(merged_into);
+            uploaded.insert(merged_into, card.clone());
+            Incoming {
+                card,
+                existing: target,
+                change,
+            }
+        })
+        .collect()
+}
+
+/// Apply the chosen strategies, adding to or updating `cards`.
+///
+/// Updated cards keep their existing ids so that the review log still refers to them,
+/// and summed cards keep their existing schedules.
+pub fn merge(cards: &mut Vec<Card>, incoming: Vec<Incoming>, strategies: &Strategies) {
+    for Incoming {
+        mut card,
+        existing,
+        change,
+    } in incoming
+    {
+        let strategy = strategies.get(change);
+        let Some(old) = existing.and_then(|id| cards.iter_mut().find(|c| c.id == id)) else {
+            // new, or repeating an uploaded card that was skipped
+            if strategies.get(Change::New) == Strategy::TakeIncoming {
+                cards.push(card);
+            }
+            continue;
+        };
+        match strategy {
+            Strategy::KeepExisting => (),
+            Strategy::TakeIncoming => {
+                card.id = old.id;
+                *old = card;
+            }
+            Strategy::SumCounts => {
+                old.prompt = card.prompt;
+                old.response = card.response;
+                old.tags = card.tags;
+                old.source = card.source;
+                old.hits += card.hits;
+                old.misses += card.misses;
+                old.reverse_hits += card.reverse_hits;
+                old.reverse_misses += card.reverse_misses;
+                old.grades.add_counts(&card.grades);
+                old.reverse_grades.add_counts(&card.reverse_grades);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sm2::Sm2;
+
+    fn card(id: CardId, prompt: &str, response: &str, hits: usize) -> Card {
+        Card {
+            id,
+            hits,
+            ..Card::new(prompt, response)
+        }
+    }
+
+    fn ids(cards: &[Card]) -> Vec<CardId> {
+        cards.iter().map(|c| c.id).collect()
+    }
+
+    #[test]
+    fn cards_repeated_across_uploaded_files_are_merged_once() {
+        let existing = vec![card(1, "hola", "hello", 2)];
+        let first_file = vec![
+            card(2, "adiós", "goodbye", 0),
+            card(3, "gracias", "thanks", 1),
+        ];
+        let second_file = vec![
+            card(2, "adiós", "goodbye", 0),
+            card(4, "Gracias ", "thank you", 3),
+            card(5, "hola", "hello", 2),
+        ];
+        let upload = first_file.into_iter().chain(second_file).collect();
+        let incoming = classify(&existing, upload);
+        let changes = incoming.iter().map(|i| i.change).collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                Change::New,
+                Change::New,
+                Change::Identical,
+                Change::Text,
+                Change::Identical
+            ]
+        );
+        let matched = incoming.iter().map(|i| i.existing).collect::<Vec<_>>();
+        assert_eq!(matched, vec![None, None, Some(2), Some(3), Some(1)]);
+
+        let mut cards = existing;
+        merge(&mut cards, incoming, &Strategies::default());
+        assert_eq!(ids(&cards), vec![1, 2, 3]);
+        assert_eq!(cards[2].response, "thank you");
+        assert_eq!(cards[2].hits, 4);
+    }
+
+    #[test]
+    fn a_file_uploaded_twice_adds_each_card_once() {
+        let file = vec![card(7, "uno", "one", 1), card(8, "dos", "two", 0)];
+        let upload = file.iter().chain(file.iter()).cloned().collect();
+        let mut cards = vec![];
+        merge(&mut cards, classify(&[], upload), &Strategies::default());
+        assert_eq!(cards, file);
+    }
+
+    #[test]
+    fn a_repeat_of_a_skipped_card_is_skipped_too() {
+        let upload = vec![card(1, "uno", "one", 0), card(2, "UNO", "one!", 5)];
+        let mut strategies = Strategies::default();
+        strategies.set(Change::New, Strategy::KeepExisting);
+        let mut cards = vec![];
+        merge(&mut cards, classify(&[], upload), &strategies);
+        assert!(cards.is_empty());
+    }
+
+    fn merged(strategy: Strategy, change: Change) -> Card {
+        let mut existing = card(1, "hola", "hello", 2);
+        existing.misses = 1;
+        existing.sm2 = Some(Sm2::default());
+        let incoming = match change {
+            Change::Text => card(1, "hola", "hi", 3),
+            _ => card(1, "hola", "hello", 3),
+        };
+        let mut strategies = Strategies::default();
+        strategies.set(change, strategy);
+        let classified = classify(&[existing.clone()], vec![incoming]);
+        assert_eq!(classified[0].change, change);
+        let mut cards = vec![existing];
+        merge(&mut cards, classified, &strategies);
+        assert_eq!(cards.len(), 1);
+        cards.remove(0)
+    }
+
+    #[test]
+    fn changed_text_is_kept_taken_or_taken_with_the_counts_summed() {
+        let kept = merged(Strategy::KeepExisting, Change::Text);
+        assert_eq!(
+            (kept.response.as_str(), kept.hits, kept.misses),
+            ("hello", 2, 1)
+        );
+        let taken = merged(Strategy::TakeIncoming, Change::Text);
+        assert_eq!(
+            (taken.response.as_str(), taken.hits, taken.misses),
+            ("hi", 3, 0)
+        );
+        assert_eq!(taken.sm2, None);
+        let summed = merged(Strategy::SumCounts, Change::Text);
+        assert_eq!(
+            (summed.response.as_str(), summed.hits, summed.misses),
+            ("hi", 5, 1)
+        );
+        assert_eq!(summed.sm2, Some(Sm2::default()));
+    }
+
+    #[test]
+    fn changed_stats_are_kept_taken_or_summed() {
+        assert_eq!(merged(Strategy::KeepExisting, Change::Stats).hits, 2);
+        assert_eq!(merged(Strategy::TakeIncoming, Change::Stats).hits, 3);
+        assert_eq!(merged(Strategy::SumCounts, Change::Stats).hits, 5);
+    }
+
+    #[test]
+    fn cards_match_by_id_or_else_by_prompt() {
+        let existing = vec![card(1, "Café", "coffee", 0), card(2, "té", "tea", 0)];
+        let upload = vec![
+            card(1, "café con leche", "coffee with milk", 0),
+            card(9, "  CAFE\u{301}", "coffee", 0),
+            card(3, "agua", "water", 0),
+        ];
+        let incoming = classify(&existing, upload);
+        let matched = incoming.iter().map(|i| i.existing).collect::<Vec<_>>();
+        assert_eq!(matched, vec![Some(1), Some(1), None]);
+        let mut strategies = Strategies::default();
+        strategies.set(Change::Text, Strategy::TakeIncoming);
+        strategies.set(Change::New, Strategy::KeepExisting);
+        let mut cards = existing;
+        merge(&mut cards, incoming, &strategies);
+        let prompts = cards.iter().map(|c| c.prompt.as_str()).collect::<Vec<_>>();
+        assert_eq!(prompts, vec!["  CAFE\u{301}", "té"]);
+        assert_eq!(ids(&cards), vec![1, 2]);
+    }
+
+    #[test]
+    fn prompts_that_differ_only_in_accents_are_different_cards() {
+        let existing = vec![card(1, "sí", "yes", 4), card(2, "où", "where", 1)];
+        let upload = vec![
+            card(3, "si", "if", 2),
+            card(4, "ou", "or", 0),
+            card(5, "SÍ", "yes", 1),
+        ];
+        let incoming = classify(&existing, upload);
+        let matched = incoming.iter().map(|i| i.existing).collect::<Vec<_>>();
+        assert_eq!(matched, vec![None, None, Some(1)]);
+        let mut cards = existing;
+        merge(&mut cards, incoming, &Strategies::default());
+        let summary = cards
+            .iter()
+            .map(|c| (c.prompt.as_str(), c.hits))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![("SÍ", 5), ("où", 1), ("si", 2), ("ou", 0)]);
+    }
+}
@@ -0,0 +1,117 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::answer::normalize;
+
+pub const N_CHOICES: usize = 4;
+
+// how much more likely a past mix-up is to be offered again than a random answer
+const CONFUSION_WEIGHT: f64 = 5.0;
+const LENGTH_WEIGHT: f64 = 3.0;
+
+/// Another card's answer that could be offered as a wrong choice
+pub struct Candidate<'a> {
+    pub id: u64,
+    pub answer: &'a str,
+    pub confusions: usize, // times it was chosen instead of the right answer
+}
+
+/// Draw up to `n` wrong answers, favoring ones that were confused with the right answer before
+/// and ones of about the same length.
+/// Answers that look the same as the right answer or as each other are skipped.
+pub fn choose_distractors<R: Rng>(
+    answer: &str,
+    candidates: &[Candidate],
+    n: usize,
+    rng: &mut R,
+) -> Vec<u64> {
+    let answer = normalize(answer);
+    let answer_len = answer.chars().count() as f64;
+    let mut seen = vec![answer];
+    let mut pool = Vec::new();
+    for candidate in candidates {
+        let text = normalize(candidate.answer);
+        if seen.contains(&text) {
+            continue;
+        }
+        let length_diff = (text.chars().count() as f64 - answer_len).abs();
+        let weight = 1.0
+            + CONFUSION_WEIGHT * candidate.confusions as f64
+            + LENGTH_WEIGHT / (1.0 + length_diff);
+        seen.push(text);
+        pool.push((candidate.id, weight));
+    }
+    let mut chosen = Vec::new();
+    while chosen.len() < n && !pool.is_empty() {
+        let dist = WeightedIndex::new(pool.iter().map(|(_, w)| *w)).unwrap();
+        chosen.push(pool.swap_remove(dist.sample(rng)).0);
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn candidate(id: u64, answer: &str, confusions: usize) -> Candidate<'_> {
+        Candidate {
+            id,
+            answer,
+            confusions,
+        }
+    }
+
+    #[test]
+    fn answers_that_look_the_same_are_offered_once() {
+        let candidates = [
+            candidate(1, "Gato", 0),
+            candidate(2, "perro", 0),
+            candidate(3, " PERRO", 0),
+            candidate(4, "pájaro", 0),
+            candidate(5, "pajaro", 0),
+        ];
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut chosen = choose_distractors("gató", &candidates, N_CHOICES, &mut rng);
+        chosen.sort();
+        assert_eq!(chosen, vec![2, 4]);
+    }
+
+    #[test]
+    fn no_more_than_asked_for_are_chosen() {
+        let answers = ["a", "b", "c", "d", "e", "f"];
+        let candidates = answers
+            .iter()
+            .enumerate()
+            .map(|(i, a)| candidate(i as u64, a, 0))
+            .collect::<Vec<_>>();
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut chosen = choose_distractors("z", &candidates, 3, &mut rng);
+            assert_eq!(chosen.len(), 3);
+            chosen.sort();
+            chosen.dedup();
+            assert_eq!(chosen.len(), 3);
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(choose_distractors("z", &[], 3, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn past_mix_ups_are_offered_more_often() {
+        let candidates = [
+            candidate(1, "uno", 0),
+            candidate(2, "dos", 3),
+            candidate(3, "tre", 0),
+            candidate(4, "cua", 0),
+        ];
+        let mut rng = StdRng::seed_from_u64(7);
+        let n_mixed_up = (0..1000)
+            .filter(|_| choose_distractors("six", &candidates, 1, &mut rng) == vec![2])
+            .count();
+        // a weight of 19 against three of 4
+        assert!((550..=750).contains(&n_mixed_up), "{n_mixed_up}");
+    }
+}
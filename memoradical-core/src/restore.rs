@@ -0,0 +1,183 @@
+//! Copies of the cards kept before anything that replaces or clears many of them,
+//! and once a day, so that a mistake can be taken back
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{self, Envelope, Settings};
+use crate::validate::Problem;
+use crate::Card;
+
+/// How many restore points are kept, the oldest being forgotten first
+pub const KEPT: usize = 20;
+
+/// What was about to happen when a restore point was kept
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Reason {
+    ClearCounts,
+    Daily,
+    DeleteDeck,
+    Overwrite,
+    Restore,
+}
+
+impl Reason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Reason::ClearCounts => "before clearing counts",
+            Reason::Daily => "daily",
+            Reason::DeleteDeck => "before deleting a deck",
+            Reason::Overwrite => "before overwriting with uploaded cards",
+            Reason::Restore => "before restoring",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RestorePoint {
+    pub timestamp: f64, // ms, which also identifies the point
+    pub reason: Reason,
+    pub decks: Vec<String>,
+    pub cards: String, // an envelope, so that cards kept by older versions are upgraded
+}
+
+impl RestorePoint {
+    pub fn new(
+        timestamp: f64,
+        reason: Reason,
+        settings: &Settings,
+        cards: &[Card],
+        decks: &[String],
+    ) -> Result<Self> {
+        let cards =
+            serde_json::to_string(&Envelope::new(settings, cards)).context("serializing cards")?;
+        Ok(RestorePoint {
+            timestamp,
+            reason,
+            decks: decks.to_vec(),
+            cards,
+        })
+    }
+
+    /// Bytes taken by the kept cards
+    pub fn size(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn read_cards(&self) -> (Vec<Card>, Vec<Problem>) {
+        let (_, cards, problems) = schema::read("restore point", &self.cards);
+        (cards, problems)
+    }
+}
+
+/// Sort restore points oldest first, forgetting the oldest beyond the limit.
+pub fn prune(points: &mut Vec<RestorePoint>) {
+    points.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    let excess = points.len().saturating_sub(KEPT);
+    points.drain(..excess);
+}
+
+/// What restoring a point would do to the current cards
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub same: usize,
+    pub back: usize,    // only at the restore point
+    pub gone: usize,    // only in the current cards
+    pub text: usize,    // with a different deck, prompt, response, or tags
+    pub history: usize, // with the same text but different counts or schedules
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} unchanged, {} back, {} gone, {} with other text, {} with other history",
+            self.same, self.back, self.gone, self.text, self.history
+        )
+    }
+}
+
+pub fn compare(current: &[Card], restored: &[Card]) -> Summary {
+    let mut current = current.iter().map(|c| (c.id, c)).collect::<HashMap<_, _>>();
+    let mut summary = Summary::default();
+    for card in restored {
+        match current.remove(&card.id) {
+            None => summary.back += 1,
+            Some(now) if now == card => summary.same += 1,
+            Some(now)
+                if (&now.deck, &now.prompt, &now.response, &now.tags)
+                    != (&card.deck, &card.prompt, &card.response, &card.tags) =>
+            {
+                summary.text += 1
+            }
+            Some(_) => summary.history += 1,
+        }
+    }
+    summary.gone = current.len();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(id: u64, prompt: &str) -> Card {
+        Card {
+            id,
+            ..Card::new(prompt, "response")
+        }
+    }
+
+    fn point(timestamp: f64) -> RestorePoint {
+        let cards = [card(1, "a")];
+        RestorePoint::new(timestamp, Reason::Daily, &Settings::default(), &cards, &[]).unwrap()
+    }
+
+    #[test]
+    fn kept_cards_read_back() {
+        let cards = vec![card(1, "a"), card(2, "b")];
+        let decks = vec!["Default".to_owned()];
+        let settings = Settings::default();
+        let point = RestorePoint::new(5.0, Reason::Overwrite, &settings, &cards, &decks).unwrap();
+        assert_eq!(point.read_cards(), (cards, vec![]));
+        assert_eq!(point.decks, decks);
+        assert_eq!(point.size(), point.cards.len());
+    }
+
+    #[test]
+    fn the_oldest_points_beyond_the_limit_are_forgotten() {
+        let mut points = (0..KEPT + 3).rev().map(|t| point(t as f64)).collect();
+        prune(&mut points);
+        let times = points.iter().map(|p| p.timestamp).collect::<Vec<_>>();
+        let newest = (3..KEPT + 3).map(|t| t as f64).collect::<Vec<_>>();
+        assert_eq!(times, newest);
+    }
+
+    #[test]
+    fn restoring_is_summarized_card_by_card() {
+        let mut studied = card(2, "b");
+        studied.hits = 1;
+        let mut moved = card(3, "c");
+        moved.deck = "French".to_owned();
+        let current = vec![card(1, "a"), studied, moved, card(4, "d")];
+        let restored = vec![card(1, "a"), card(2, "b"), card(3, "c"), card(5, "e")];
+        let summary = compare(&current, &restored);
+        assert_eq!(
+            summary,
+            Summary {
+                same: 1,
+                back: 1,
+                gone: 1,
+                text: 1,
+                history: 1,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "1 unchanged, 1 back, 1 gone, 1 with other text, 1 with other history"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fsrs;
+
+/// How well a card was recalled, from worst to best.
+///
+/// Binary study records only `Again` (a miss) and `Good` (a hit),
+/// which is also how responses logged before grading was added are read.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grade {
+    #[serde(alias = "miss")]
+    Again,
+    Hard,
+    #[serde(alias = "hit")]
+    Good,
+    Easy,
+}
+
+impl Grade {
+    pub const ALL: [Grade; 4] = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
+
+    pub fn is_hit(&self) -> bool {
+        *self != Grade::Again
+    }
+
+    pub fn sm2_quality(&self) -> u8 {
+        match self {
+            Grade::Again => 1,
+            Grade::Hard => 3,
+            Grade::Good => 4,
+            Grade::Easy => 5,
+        }
+    }
+
+    pub fn fsrs_rating(&self) -> u8 {
+        match self {
+            Grade::Again => fsrs::RATING_AGAIN,
+            Grade::Hard => fsrs::RATING_HARD,
+            Grade::Good => fsrs::RATING_GOOD,
+            Grade::Easy => fsrs::RATING_EASY,
+        }
+    }
+
+    /// The study key for this grade
+    pub fn key(&self) -> &'static str {
+        match self {
+            Grade::Again => "1",
+            Grade::Hard => "2",
+            Grade::Good => "3",
+            Grade::Easy => "4",
+        }
+    }
+}
+
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// How many times each grade was given for one direction of a card.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GradeCounts {
+    pub again: usize,
+    pub hard: usize,
+    pub good: usize,
+    pub easy: usize,
+}
+
+impl GradeCounts {
+    pub fn add(&mut self, grade: Grade) {
+        match grade {
+            Grade::Again => self.again += 1,
+            Grade::Hard => self.hard += 1,
+            Grade::Good => self.good += 1,
+            Grade::Easy => self.easy += 1,
+        }
+    }
+
+    pub fn add_counts(&mut self, other: &GradeCounts) {
+        self.again += other.again;
+        self.hard += other.hard;
+        self.good += other.good;
+        self.easy += other.easy;
+    }
+
+    /// Adjust plain hit and miss counts for graded responses.
+    ///
+    /// A "hard" hit counts as half a hit and half a miss,
+    /// and an "easy" hit counts as one and a half hits.
+    pub fn weigh(&self, hits: usize, misses: usize) -> (f64, f64) {
+        let hard = self.hard as f64 / 2.0;
+        let easy = self.easy as f64 / 2.0;
+        ((hits as f64 - hard + easy).max(0.0), misses as f64 + hard)
+    }
+}
+
+/// One response to one card, as recorded in the append-only review log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Review {
+    pub card: u64,
+    pub reverse: bool,
+    #[serde(alias = "outcome")]
+    pub grade: Grade,
+    pub timestamp: f64,  // milliseconds since the epoch
+    pub latency_ms: f64, // from showing the card to the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confused_with: Option<u64>, // the card whose answer was wrongly chosen in a quiz
+}
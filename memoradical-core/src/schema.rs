@@ -0,0 +1,262 @@
+//! The versioned envelope that settings and cards are saved and downloaded in,
+//! and the migrations that bring older payloads up to date
+//!
+//! A new card field gets a migration that fills it in for older cards,
+//! instead of having to be optional forever.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::validate::{self, Problem};
+use crate::{Card, Scheduler, DEFAULT_TARGET_RETENTION};
+
+/// A bare array of cards, as saved before there was an envelope
+pub const LEGACY_VERSION: u32 = 1;
+
+/// Each migration upgrades a card from one schema version to the next,
+/// starting from the legacy version.
+const CARD_MIGRATIONS: [fn(&mut Value); 1] = [count_reverse_from_zero];
+
+pub const SCHEMA_VERSION: u32 = LEGACY_VERSION + CARD_MIGRATIONS.len() as u32;
+
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Study options that are kept with the cards
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub choose_missed: bool,
+    pub choose_neglected: bool,
+    pub graded_mode: bool,
+    pub reverse_mode: bool,
+    pub scheduler: Scheduler,
+    pub session_seed: Option<u64>, // None for a different random session each time
+    pub target_retention: f64,
+    pub typed_mode: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            choose_missed: true,
+            choose_neglected: false,
+            graded_mode: false,
+            reverse_mode: false,
+            scheduler: Scheduler::Beta,
+            session_seed: None,
+            target_retention: DEFAULT_TARGET_RETENTION,
+            typed_mode: false,
+        }
+    }
+}
+
+/// Settings and cards as they are saved, noting the schema and the app that saved them
+#[derive(Serialize)]
+pub struct Envelope<'a> {
+    schema_version: u32,
+    app_version: &'a str,
+    settings: &'a Settings,
+    cards: &'a [Card],
+}
+
+impl<'a> Envelope<'a> {
+    pub fn new(settings: &'a Settings, cards: &'a [Card]) -> Self {
+        Envelope {
+            schema_version: SCHEMA_VERSION,
+            app_version: APP_VERSION,
+            settings,
+            cards,
+        }
+    }
+}
+
+/// The envelope without its cards, which are read one by one
+#[derive(Deserialize)]
+struct Header {
+    schema_version: u32,
+    #[serde(default)]
+    app_version: String,
+    #[serde(default)]
+    settings: Settings,
+}
+
+/// Envelope text put together from parts that are stored separately,
+/// each already JSON
+pub fn envelope_text(
+    schema_version: &str,
+    app_version: &str,
+    settings: &str,
+    cards: &str,
+) -> String {
+    format!(
+        "{{\"schema_version\":{schema_version},\"app_version\":{app_version},\
+         \"settings\":{settings},\"cards\":{cards}}}"
+    )
+}
+
+/// Version 1 left the reverse counts null until a card was first studied in reverse.
+fn count_reverse_from_zero(card: &mut Value) {
+    if let Some(card) = card.as_object_mut() {
+        for key in ["reverse_hits", "reverse_misses"] {
+            let count = card.entry(key).or_insert(Value::Null);
+            if count.is_null() {
+                *count = 0.into();
+            }
+        }
+    }
+}
+
+/// A card as saved at an older schema version, brought up to date
+pub fn upgrade_card(mut card: Value, version: u32) -> Value {
+    let done = version.saturating_sub(LEGACY_VERSION) as usize;
+    for migrate in CARD_MIGRATIONS.iter().skip(done) {
+        migrate(&mut card);
+    }
+    card
+}
+
+/// Read settings and cards from an envelope or a bare array of cards,
+/// upgrading them from whatever version saved them,
+/// and keeping the good cards while reporting each bad one.
+pub fn read(file: &str, text: &str) -> (Settings, Vec<Card>, Vec<Problem>) {
+    if !text.trim_start().starts_with('{') {
+        let upgrade = |card| upgrade_card(card, LEGACY_VERSION);
+        let (cards, problems) = validate::upgraded_json_records(file, text, &upgrade);
+        return (Settings::default(), cards, problems);
+    }
+    let (header, header_problem) = match serde_json::from_str::<Header>(text) {
+        Ok(header) => (header, None),
+        Err(e) => {
+            // salvage what cards there are, as if they were current
+            let header = Header {
+                schema_version: SCHEMA_VERSION,
+                app_version: String::new(),
+                settings: Settings::default(),
+            };
+            (header, Some(validate::json_error(file, text, e)))
+        }
+    };
+    if header.schema_version > SCHEMA_VERSION {
+        let message = format!(
+            "saved by Memoradical {} with schema version {}, but this is version {APP_VERSION}, \
+             which reads up to schema version {SCHEMA_VERSION}",
+            header.app_version, header.schema_version
+        );
+        return (
+            header.settings,
+            vec![],
+            vec![Problem::whole_file(file, message)],
+        );
+    }
+    let upgrade = |card| upgrade_card(card, header.schema_version);
+    let Some((cards, mut problems)) =
+        validate::upgraded_json_records_under(file, text, "cards", &upgrade)
+    else {
+        let problem = header_problem
+            .unwrap_or_else(|| Problem::whole_file(file, "there is no array of cards".to_owned()));
+        return (header.settings, vec![], vec![problem]);
+    };
+    if problems.is_empty() {
+        problems.extend(header_problem);
+    }
+    (header.settings, cards, problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_arrays_are_upgraded() {
+        let text = r#"[{"prompt": "a", "response": "b", "hits": 1, "misses": 2,
+            "reverse_hits": null, "reverse_misses": null,
+            "sm2": null, "reverse_sm2": null, "fsrs": null, "reverse_fsrs": null}]"#;
+        let (settings, cards, problems) = read("cards.json", text);
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(settings, Settings::default());
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].hits_misses(true), (0, 0));
+        assert_eq!(cards[0].hits_misses(false), (1, 2));
+    }
+
+    #[test]
+    fn older_envelopes_are_upgraded_even_when_their_cards_parse() {
+        let text = r#"{"schema_version": 1, "app_version": "0.1.0", "settings": {},
+            "cards": [
+                {"prompt": "a", "response": "b", "hits": 1, "misses": 2,
+                 "reverse_hits": 3, "reverse_misses": 4,
+                 "sm2": null, "reverse_sm2": null, "fsrs": null, "reverse_fsrs": null},
+                {"prompt": "c", "response": "d", "hits": 5, "misses": 6,
+                 "reverse_hits": null, "reverse_misses": 7,
+                 "sm2": null, "reverse_sm2": null, "fsrs": null, "reverse_fsrs": null}
+            ]}"#;
+        let (_, cards, problems) = read("cards.json", text);
+        assert!(problems.is_empty(), "{problems:?}");
+        let counts = cards
+            .iter()
+            .map(|c| (c.hits_misses(false), c.hits_misses(true)))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![((1, 2), (3, 4)), ((5, 6), (0, 7))]);
+    }
+
+    #[test]
+    fn current_cards_are_not_migrated() {
+        let card = serde_json::json!({"prompt": "a", "reverse_hits": null});
+        assert_eq!(upgrade_card(card.clone(), SCHEMA_VERSION), card);
+        let upgraded = upgrade_card(card, LEGACY_VERSION);
+        assert_eq!(upgraded["reverse_hits"], 0);
+        assert_eq!(upgraded["reverse_misses"], 0);
+    }
+
+    #[test]
+    fn envelopes_round_trip() {
+        let settings = Settings {
+            reverse_mode: true,
+            scheduler: Scheduler::Fsrs,
+            ..Settings::default()
+        };
+        let cards = vec![Card::new("a", "b"), Card::new("c", "d")];
+        let text = serde_json::to_string(&Envelope::new(&settings, &cards)).unwrap();
+        assert_eq!(read("cards.json", &text), (settings, cards, vec![]));
+    }
+
+    #[test]
+    fn newer_schemas_are_refused() {
+        let text = format!(
+            r#"{{"schema_version": {}, "app_version": "99.0.0", "settings": {{}}, "cards": []}}"#,
+            SCHEMA_VERSION + 1
+        );
+        let (_, cards, problems) = read("cards.json", &text);
+        assert!(cards.is_empty());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("99.0.0"));
+    }
+
+    #[test]
+    fn bad_cards_are_reported_and_the_rest_kept() {
+        let cards = vec![Card::new("a", "b")];
+        let good = serde_json::to_value(&cards[0]).unwrap();
+        let text = format!(
+            r#"{{"schema_version": {SCHEMA_VERSION}, "app_version": "1.0.0", "settings": {{}},
+            "cards": [{good}, {{"prompt": 3}}]}}"#
+        );
+        let (_, read_cards, problems) = read("cards.json", &text);
+        assert_eq!(read_cards, cards);
+        assert_eq!(problems.len(), 1);
+    }
+
+    #[test]
+    fn ids_too_large_for_javascript_are_reported_where_they_are() {
+        let cards = vec![Card::new("a", "b")];
+        let good = serde_json::to_value(&cards[0]).unwrap();
+        let mut large = good.clone();
+        large["id"] = crate::CARD_ID_LIMIT.into();
+        let text = format!("[{good},\n{large}]");
+        let (_, read_cards, problems) = read("cards.json", &text);
+        assert_eq!(read_cards, cards);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(2));
+        assert_eq!(problems[0].field.as_deref(), Some("id"));
+        assert!(problems[0].message.contains("expected an id below 2^53"));
+    }
+}
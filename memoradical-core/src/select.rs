@@ -0,0 +1,234 @@
+//! Choosing the next card to study
+
+use std::collections::LinkedList;
+
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Beta, Distribution};
+
+use crate::schema::Settings;
+use crate::{Card, CardId, Scheduler};
+
+/// The random numbers for a study session,
+/// which repeat from the start of every session with the same seed
+pub fn session_rng(seed: Option<u64>) -> StdRng {
+    seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64)
+}
+
+/// Choose the next card from those being studied,
+/// avoiding the recently displayed ones where there are others to choose.
+/// Due-date schedulers take anything due before `cutoff` as due now.
+pub fn choose_card<R: Rng + ?Sized>(
+    cards: &[&Card],
+    settings: &Settings,
+    recent: &LinkedList<CardId>,
+    now: f64,
+    cutoff: f64,
+    rng: &mut R,
+) -> Option<CardId> {
+    match settings.scheduler {
+        Scheduler::Beta => choose_card_beta(cards, settings, recent, rng),
+        Scheduler::Sm2 | Scheduler::Fsrs => choose_card_due(cards, settings, recent, now, cutoff),
+    }
+}
+
+/// Draw a card at random, weighting each one by a draw from the Beta distribution
+/// of its misses and hits, so that missed cards come up more often,
+/// and adding weight to cards seldom seen if neglected cards are preferred.
+pub fn choose_card_beta<R: Rng + ?Sized>(
+    cards: &[&Card],
+    settings: &Settings,
+    recent: &LinkedList<CardId>,
+    rng: &mut R,
+) -> Option<CardId> {
+    let mut weights: Vec<_> = if settings.choose_missed {
+        cards
+            .iter()
+            .map(|card| {
+                let (hits, misses) = card.weighted_hits_misses(settings.reverse_mode);
+                let shape1 = misses + 1.0;
+                let shape2 = hits + 1.0;
+                Beta::new(shape1, shape2).unwrap().sample(rng)
+            })
+            .collect()
+    } else {
+        vec![if settings.choose_neglected { 0.0 } else { 1.0 }; cards.len()]
+    };
+    if settings.choose_neglected {
+        for (w, c) in weights.iter_mut().zip(cards.iter()) {
+            let n_visits = c.hits + c.misses;
+            *w += if n_visits == 0 {
+                1.0
+            } else {
+                1.0 / n_visits as f64
+            };
+        }
+    }
+    let unseen = weights
+        .iter()
+        .zip(cards.iter())
+        .map(|(w, c)| if recent.contains(&c.id) { 0.0 } else { *w })
+        .collect::<Vec<_>>();
+    // the history can cover a whole small deck after switching decks
+    let dist = WeightedIndex::new(&unseen)
+        .or_else(|_| WeightedIndex::new(&weights))
+        .ok()?;
+    Some(cards[dist.sample(rng)].id)
+}
+
+/// When the card is next due under the chosen due-date scheduler,
+/// or None if it has never been studied.
+pub fn due_time(card: &Card, settings: &Settings) -> Option<f64> {
+    match settings.scheduler {
+        Scheduler::Beta => None,
+        Scheduler::Sm2 => card.sm2(settings.reverse_mode).map(|s| s.due),
+        Scheduler::Fsrs => card
+            .fsrs(settings.reverse_mode)
+            .map(|s| s.due(settings.target_retention)),
+    }
+}
+
+/// Choose the most urgent card among those due before `cutoff`, treating
+/// never-studied cards as just now due.
+/// Returns None when nothing is due.
+pub fn choose_card_due(
+    cards: &[&Card],
+    settings: &Settings,
+    recent: &LinkedList<CardId>,
+    now: f64,
+    cutoff: f64,
+) -> Option<CardId> {
+    let candidates = cards
+        .iter()
+        .copied()
+        .filter(|c| due_time(c, settings).is_none_or(|due| due <= cutoff))
+        .collect::<Vec<_>>();
+    // lower is more urgent
+    let urgency = |card: &Card| match settings.scheduler {
+        Scheduler::Fsrs => card
+            .fsrs(settings.reverse_mode)
+            .map_or(settings.target_retention, |s| s.retrievability(now)),
+        _ => due_time(card, settings).unwrap_or(now),
+    };
+    let most_urgent = |a: &&&Card, b: &&&Card| urgency(a).partial_cmp(&urgency(b)).unwrap();
+    candidates
+        .iter()
+        .filter(|c| !recent.contains(&c.id))
+        .min_by(most_urgent)
+        .or_else(|| candidates.iter().min_by(most_urgent))
+        .map(|c| c.id)
+}
+
+/// How many of the cards are due before `cutoff`, counting never-studied ones
+pub fn n_due(cards: &[&Card], settings: &Settings, cutoff: f64) -> usize {
+    cards
+        .iter()
+        .filter(|c| due_time(c, settings).is_none_or(|due| due <= cutoff))
+        .count()
+}
+
+/// The earliest due time among the cards that have been studied
+pub fn next_due(cards: &[&Card], settings: &Settings) -> Option<f64> {
+    cards
+        .iter()
+        .filter_map(|c| due_time(c, settings))
+        .min_by(|a, b| a.partial_cmp(b).unwrap())
+}
+
+/// Remember a displayed card, keeping about log2 of the session size so that
+/// small sessions do not run out of cards to choose.
+pub fn record_display(recent: &mut LinkedList<CardId>, card: CardId, n_cards: usize) {
+    let n = (n_cards as f64).log2().round() as usize;
+    recent.push_back(card);
+    if recent.len() > n {
+        recent.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sm2::Sm2;
+
+    fn cards(n: usize) -> Vec<Card> {
+        (0..n)
+            .map(|i| {
+                let mut card = Card::new(&format!("front {i}"), &format!("back {i}"));
+                card.id = i as CardId + 1;
+                card
+            })
+            .collect()
+    }
+
+    #[test]
+    fn record_display_keeps_about_log2_of_the_session() {
+        let mut recent = LinkedList::new();
+        for id in 1..=10 {
+            record_display(&mut recent, id, 16);
+        }
+        assert_eq!(recent.into_iter().collect::<Vec<_>>(), vec![7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn record_display_forgets_everything_for_a_single_card() {
+        let mut recent = LinkedList::new();
+        record_display(&mut recent, 1, 1);
+        assert!(recent.is_empty());
+    }
+
+    #[test]
+    fn beta_avoids_recent_cards() {
+        let cards = cards(4);
+        let refs = cards.iter().collect::<Vec<_>>();
+        let recent = LinkedList::from([1, 2, 3]);
+        let mut rng = session_rng(Some(1));
+        for _ in 0..50 {
+            let chosen = choose_card_beta(&refs, &Settings::default(), &recent, &mut rng);
+            assert_eq!(chosen, Some(4));
+        }
+    }
+
+    #[test]
+    fn beta_falls_back_on_recent_cards() {
+        let cards = cards(2);
+        let refs = cards.iter().collect::<Vec<_>>();
+        let recent = LinkedList::from([1, 2]);
+        let mut rng = session_rng(Some(1));
+        let chosen = choose_card_beta(&refs, &Settings::default(), &recent, &mut rng);
+        assert!(chosen.is_some());
+        assert_eq!(
+            choose_card_beta(&[], &Settings::default(), &recent, &mut rng),
+            None
+        );
+    }
+
+    #[test]
+    fn due_prefers_the_most_overdue_card() {
+        let mut cards = cards(3);
+        let settings = Settings {
+            scheduler: Scheduler::Sm2,
+            ..Settings::default()
+        };
+        for (card, due) in cards.iter_mut().zip([300.0, 100.0, 5000.0]) {
+            card.sm2 = Some(Sm2 {
+                due,
+                ..Sm2::default()
+            });
+        }
+        let refs = cards.iter().collect::<Vec<_>>();
+        let none = LinkedList::new();
+        assert_eq!(
+            choose_card_due(&refs, &settings, &none, 200.0, 1000.0),
+            Some(2)
+        );
+        assert_eq!(choose_card_due(&refs, &settings, &none, 200.0, 50.0), None);
+        assert_eq!(n_due(&refs, &settings, 1000.0), 2);
+        assert_eq!(next_due(&refs, &settings), Some(100.0));
+        let recent = LinkedList::from([2]);
+        assert_eq!(
+            choose_card_due(&refs, &settings, &recent, 200.0, 1000.0),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+const INITIAL_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+
+/// Scheduling state for one direction of a card, following SuperMemo-2.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sm2 {
+    pub ease: f64,
+    pub interval: f64, // days
+    pub repetitions: u32,
+    pub due: f64, // milliseconds since the epoch
+}
+
+impl Default for Sm2 {
+    fn default() -> Self {
+        Self {
+            ease: INITIAL_EASE,
+            interval: 0.0,
+            repetitions: 0,
+            due: 0.0,
+        }
+    }
+}
+
+impl Sm2 {
+    /// Update the schedule for a response of the given quality (0 to 5).
+    ///
+    /// A failed recall resets the repetition count and leaves the card due
+    /// right away, so it keeps coming back until it is recalled.
+    pub fn review(&mut self, quality: u8, now: f64) {
+        let q = quality.min(5) as f64;
+        if quality >= 3 {
+            self.interval = match self.repetitions {
+                0 => 1.0,
+                1 => 6.0,
+                _ => (self.interval * self.ease).round(),
+            };
+            self.repetitions += 1;
+            self.due = now + self.interval * DAY_MS;
+        } else {
+            self.repetitions = 0;
+            self.interval = 0.0;
+            self.due = now;
+        }
+        self.ease = (self.ease + 0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02)).max(MIN_EASE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn good_recalls_space_out_by_the_ease() {
+        let mut sm2 = Sm2::default();
+        let mut intervals = Vec::new();
+        for day in [0.0, 1.0, 7.0, 22.0] {
+            sm2.review(4, day * DAY_MS);
+            intervals.push(sm2.interval);
+            assert_eq!(sm2.due, (day + sm2.interval) * DAY_MS);
+        }
+        assert_eq!(intervals, vec![1.0, 6.0, 15.0, 38.0]);
+        assert_eq!(sm2.ease, INITIAL_EASE);
+        assert_eq!(sm2.repetitions, 4);
+    }
+
+    #[test]
+    fn the_ease_follows_the_quality_of_each_recall() {
+        let mut sm2 = Sm2::default();
+        sm2.review(5, 0.0);
+        assert!((sm2.ease - 2.6).abs() < 1e-9);
+        sm2.review(3, 0.0);
+        assert!((sm2.ease - 2.46).abs() < 1e-9);
+        // the interval grows by the ease as it was before the recall
+        sm2.review(5, 0.0);
+        assert_eq!(sm2.interval, 15.0);
+        assert!((sm2.ease - 2.56).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_failed_recall_starts_over_and_is_due_at_once() {
+        let mut sm2 = Sm2::default();
+        sm2.review(4, 0.0);
+        sm2.review(4, DAY_MS);
+        sm2.review(1, 7.0 * DAY_MS);
+        assert_eq!((sm2.repetitions, sm2.interval), (0, 0.0));
+        assert_eq!(sm2.due, 7.0 * DAY_MS);
+        assert!((sm2.ease - 1.96).abs() < 1e-9);
+        sm2.review(4, 8.0 * DAY_MS);
+        assert_eq!(sm2.interval, 1.0);
+        for _ in 0..10 {
+            sm2.review(0, 0.0);
+        }
+        assert_eq!(sm2.ease, MIN_EASE);
+    }
+}
@@ -0,0 +1,334 @@
+//! Just enough of a SQLite reader to pull rows out of the tables of a database file,
+//! as documented at https://www.sqlite.org/fileformat.html.
+//! Indexes, views, and writing are not supported.
+
+use anyhow::{anyhow, Context, Result};
+
+const HEADER: &[u8] = b"SQLite format 3\0";
+const HEADER_LEN: usize = 100;
+const PAGE_INTERIOR_TABLE: u8 = 0x05;
+const PAGE_LEAF_TABLE: u8 = 0x0d;
+const TEXT_ENCODING_UTF8: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+pub struct Database<'a> {
+    data: &'a [u8],
+    page_size: usize,
+    usable_size: usize, // page size less the space reserved for extensions
+}
+
+/// A SQLite variable-length integer and the number of bytes it took
+fn varint(bytes: &[u8]) -> Result<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().take(9).enumerate() {
+        if i == 8 {
+            return Ok(((value << 8) | *byte as u64, 9));
+        }
+        value = (value << 7) | (*byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(anyhow!("truncated varint"))
+}
+
+fn be_int(bytes: &[u8]) -> i64 {
+    // sign-extend from the first byte
+    let init = if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+        -1
+    } else {
+        0
+    };
+    bytes.iter().fold(init, |acc, b| (acc << 8) | *b as i64)
+}
+
+impl<'a> Database<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        if data.len() < HEADER_LEN || !data.starts_with(HEADER) {
+            return Err(anyhow!("not a SQLite database"));
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            n => n as usize,
+        };
+        let encoding = u32::from_be_bytes([data[56], data[57], data[58], data[59]]);
+        if encoding != TEXT_ENCODING_UTF8 {
+            return Err(anyhow!("unsupported SQLite text encoding {encoding}"));
+        }
+        let usable_size = page_size
+            .checked_sub(data[20] as usize)
+            .filter(|n| *n >= 480)
+            .ok_or_else(|| anyhow!("bad SQLite page size {page_size}"))?;
+        Ok(Database {
+            data,
+            page_size,
+            usable_size,
+        })
+    }
+
+    fn bytes(&self, start: usize, len: usize) -> Result<&'a [u8]> {
+        self.data
+            .get(start..start.saturating_add(len))
+            .ok_or_else(|| {
+                anyhow!(
+                    "SQLite data ends before offset {}",
+                    start.saturating_add(len)
+                )
+            })
+    }
+
+    fn page(&self, number: u32) -> Result<&'a [u8]> {
+        let index = (number as usize)
+            .checked_sub(1)
+            .ok_or_else(|| anyhow!("bad SQLite page number 0"))?;
+        let start = index
+            .checked_mul(self.page_size)
+            .ok_or_else(|| anyhow!("bad SQLite page number {number}"))?;
+        self.bytes(start, self.page_size)
+    }
+
+    /// The payload of a leaf table cell, which may spill onto overflow pages
+    fn payload(&self, cell: &'a [u8], size: usize) -> Result<Vec<u8>> {
+        let usable = self.usable_size;
+        let max_local = usable - 35;
+        if size > self.data.len() {
+            // and an overflow chain that loops would never end
+            return Err(anyhow!("SQLite cell is larger than the database"));
+        }
+        if size <= max_local {
+            return Ok(cell.get(..size).context("truncated cell")?.to_vec());
+        }
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        let local = match min_local + (size - min_local) % (usable - 4) {
+            k if k <= max_local => k,
+            _ => min_local,
+        };
+        let mut payload = cell.get(..local).context("truncated cell")?.to_vec();
+        let next = cell.get(local..local + 4).context("truncated cell")?;
+        let mut next = u32::from_be_bytes(next.try_into()?);
+        while payload.len() < size {
+            if next == 0 {
+                return Err(anyhow!("overflow chain ends early"));
+            }
+            let page = self.page(next)?;
+            next = u32::from_be_bytes(page[..4].try_into()?);
+            let wanted = (size - payload.len()).min(usable - 4);
+            payload.extend_from_slice(&page[4..4 + wanted]);
+        }
+        Ok(payload)
+    }
+
+    fn record(payload: &[u8]) -> Result<Vec<Value>> {
+        let (header_len, mut pos) = varint(payload)?;
+        let header_len = header_len as usize;
+        let mut types = Vec::new();
+        while pos < header_len {
+            let (serial_type, n) = varint(payload.get(pos..).context("truncated record")?)?;
+            types.push(serial_type);
+            pos += n;
+        }
+        let mut body = payload.get(header_len..).context("truncated record")?;
+        let mut values = Vec::new();
+        for serial_type in types {
+            let len = match serial_type {
+                0 | 8 | 9 => 0,
+                1..=4 => serial_type as usize,
+                5 => 6,
+                6 | 7 => 8,
+                n if n >= 12 => (n as usize - 12) / 2,
+                n => return Err(anyhow!("reserved SQLite serial type {n}")),
+            };
+            let bytes = body.get(..len).context("truncated record")?;
+            body = &body[len..];
+            values.push(match serial_type {
+                0 => Value::Null,
+                8 => Value::Integer(0),
+                9 => Value::Integer(1),
+                7 => Value::Real(f64::from_be_bytes(bytes.try_into()?)),
+                1..=6 => Value::Integer(be_int(bytes)),
+                n if n % 2 == 0 => Value::Blob(bytes.to_vec()),
+                _ => Value::Text(String::from_utf8_lossy(bytes).into_owned()),
+            });
+        }
+        Ok(values)
+    }
+
+    /// All rows of the table b-tree rooted at the given page, in row id order.
+    ///
+    /// A first column that is NULL is taken to be an INTEGER PRIMARY KEY,
+    /// which SQLite stores as the row id, and is filled in with it.
+    fn rows(&self, root: u32) -> Result<Vec<Vec<Value>>> {
+        let mut rows = Vec::new();
+        let mut stack = vec![root];
+        let mut visited = 0;
+        while let Some(number) = stack.pop() {
+            visited += 1;
+            if visited > self.data.len() / self.page_size {
+                return Err(anyhow!("SQLite b-tree has a cycle"));
+            }
+            let page = self.page(number)?;
+            let start = if number == 1 { HEADER_LEN } else { 0 };
+            let header = page.get(start..start + 12).context("truncated page")?;
+            let n_cells = u16::from_be_bytes([header[3], header[4]]) as usize;
+            let (header_len, interior) = match header[0] {
+                PAGE_LEAF_TABLE => (8, false),
+                PAGE_INTERIOR_TABLE => (12, true),
+                t => return Err(anyhow!("page {number} is not a table page ({t:#x})")),
+            };
+            let pointers = page
+                .get(start + header_len..start + header_len + 2 * n_cells)
+                .context("truncated page")?;
+            let cells = pointers
+                .chunks(2)
+                .map(|p| u16::from_be_bytes([p[0], p[1]]) as usize)
+                .map(|offset| page.get(offset..).context("bad cell pointer"));
+            if interior {
+                // push children so that they pop in order, the right-most one last
+                let mut children = Vec::new();
+                for cell in cells {
+                    let pointer = cell?.get(..4).context("truncated cell")?;
+                    children.push(u32::from_be_bytes(pointer.try_into()?));
+                }
+                stack.push(u32::from_be_bytes(header[8..12].try_into()?));
+                stack.extend(children.into_iter().rev());
+            } else {
+                for cell in cells {
+                    let cell = cell?;
+                    let (size, n) = varint(cell)?;
+                    let (rowid, m) = varint(&cell[n..])?;
+                    let payload = self.payload(&cell[n + m..], size as usize)?;
+                    let mut values = Self::record(&payload)?;
+                    if values.first() == Some(&Value::Null) {
+                        values[0] = Value::Integer(rowid as i64);
+                    }
+                    rows.push(values);
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    fn root_page(&self, table: &str) -> Result<Option<u32>> {
+        // the schema table: type, name, tbl_name, rootpage, sql
+        for row in self.rows(1)? {
+            if row.first().and_then(Value::as_str) == Some("table")
+                && row.get(1).and_then(Value::as_str) == Some(table)
+            {
+                let root = row.get(3).and_then(Value::as_i64);
+                return Ok(root.map(|r| r as u32));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn has_table(&self, name: &str) -> Result<bool> {
+        Ok(self.root_page(name)?.is_some())
+    }
+
+    /// The rows of the named table, with values in column order
+    pub fn table(&self, name: &str) -> Result<Vec<Vec<Value>>> {
+        let root = self
+            .root_page(name)?
+            .ok_or_else(|| anyhow!("no table named {name:?}"))?;
+        self.rows(root)
+            .with_context(|| format!("reading table {name:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // made with Python's sqlite3 module, with 512-byte pages, so that
+    // the second row's body spills onto several overflow pages
+    const DATABASE: &[u8] = include_bytes!("../tests/fixtures/overflow.sqlite");
+
+    fn long_body() -> String {
+        (0..600).map(|i| format!("{i:04} ")).collect()
+    }
+
+    #[test]
+    fn rows_are_read_in_column_order() {
+        let db = Database::new(DATABASE).unwrap();
+        let rows = db.table("things").unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Text("short".to_owned()),
+                    Value::Integer(-5),
+                    Value::Null,
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::Text(long_body()),
+                    Value::Real(2.5),
+                    Value::Blob(vec![0x00, 0xff]),
+                ],
+                vec![
+                    Value::Integer(3),
+                    Value::Text("tiny".to_owned()),
+                    Value::Integer(1 << 40),
+                    Value::Null,
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn tables_are_found_by_name() {
+        let db = Database::new(DATABASE).unwrap();
+        assert!(db.has_table("things").unwrap());
+        assert!(!db.has_table("nothing").unwrap());
+        assert!(db.table("nothing").is_err());
+    }
+
+    #[test]
+    fn other_files_are_not_databases() {
+        assert!(Database::new(b"").is_err());
+        assert!(Database::new(&[b'x'; 1024]).is_err());
+        assert!(Database::new(&DATABASE[..HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn truncated_databases_are_errors() {
+        let db = Database::new(&DATABASE[..DATABASE.len() - 512]).unwrap();
+        assert!(db.table("things").is_err());
+    }
+
+    #[test]
+    fn corrupt_databases_do_not_panic() {
+        for i in 0..DATABASE.len() {
+            let mut data = DATABASE.to_vec();
+            data[i] ^= 0xff;
+            if let Ok(db) = Database::new(&data) {
+                let _ = db.table("things");
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! How well a deck is known, card by card and overall
+
+use std::cmp::Ordering;
+
+use crate::reviews::Review;
+use crate::Card;
+
+pub const GOODNESS_CRITERION: f32 = 0.6; // otherwise it's too hard to make up for a few misses
+
+/// One card's line of the stats table
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row<'a> {
+    pub card: &'a Card,
+    pub hits: usize,
+    pub misses: usize,
+    pub percent_hit: f32,
+    pub goodness: f32,
+}
+
+impl Row<'_> {
+    pub fn visits(&self) -> usize {
+        self.hits + self.misses
+    }
+}
+
+/// The stats for a deck in one direction of study
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats<'a> {
+    pub rows: Vec<Row<'a>>, // best known first
+    pub score: f32,         // mean goodness, as a percentage
+    pub percent_good: f32,
+    pub percent_visited: f32,
+    pub n_responses: usize,
+    pub n_responses_today: usize,
+}
+
+impl<'a> Stats<'a> {
+    /// Count responses since `today_start` from the review log.
+    pub fn new(
+        cards: &[&'a Card],
+        reviews: &[Review],
+        reverse_mode: bool,
+        today_start: f64,
+    ) -> Stats<'a> {
+        let mut rows = cards
+            .iter()
+            .map(|card| {
+                let (hits, misses) = card.hits_misses(reverse_mode);
+                Row {
+                    card,
+                    hits,
+                    misses,
+                    percent_hit: hit_ratio(hits, misses) * 100.0,
+                    goodness: goodness(card, reverse_mode),
+                }
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by(compare);
+        let goodnesses = rows.iter().map(|r| r.goodness).collect::<Vec<_>>();
+        let n_good = rows
+            .iter()
+            .filter(|r| {
+                r.visits() > 1 // just one response isn't enough to "know it well"
+                && r.goodness >= GOODNESS_CRITERION
+            })
+            .count();
+        let n_visited = rows.iter().filter(|r| r.visits() > 0).count();
+        let n_responses_today = reviews
+            .iter()
+            .filter(|r| r.reverse == reverse_mode && r.timestamp >= today_start)
+            .count();
+        Stats {
+            score: 100.0 * mean(&goodnesses),
+            percent_good: percent(n_good, rows.len()),
+            percent_visited: percent(n_visited, rows.len()),
+            n_responses: rows.iter().map(Row::visits).sum(),
+            n_responses_today,
+            rows,
+        }
+    }
+}
+
+pub fn hit_ratio(hits: usize, misses: usize) -> f32 {
+    let total = hits + misses;
+    if total == 0 {
+        0.0
+    } else {
+        hits as f32 / total as f32
+    }
+}
+
+/// (hits - misses) / (hits + misses), adjusted for how hard or easy the hits were
+pub fn goodness(card: &Card, reverse_mode: bool) -> f32 {
+    let (h, m) = card.weighted_hits_misses(reverse_mode);
+    let total = h + m;
+    if total == 0.0 {
+        0.0
+    } else {
+        ((h - m) / total) as f32
+    }
+}
+
+pub fn mean(x: &[f32]) -> f32 {
+    if x.is_empty() {
+        0.0
+    } else {
+        x.iter().sum::<f32>() / x.len() as f32
+    }
+}
+
+fn percent(n: usize, total: usize) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        100.0 * n as f32 / total as f32
+    }
+}
+
+/// Better known cards first, then the most hit among the good and the least missed among the bad
+fn compare(a: &Row, b: &Row) -> Ordering {
+    let cmp = b.goodness.partial_cmp(&a.goodness).unwrap();
+    if !matches!(cmp, Ordering::Equal) {
+        cmp
+    } else if a.goodness >= 0.0 {
+        b.hits.cmp(&a.hits)
+    } else {
+        a.misses.cmp(&b.misses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(hits: usize, misses: usize) -> Card {
+        Card {
+            hits,
+            misses,
+            ..Card::new("front", "back")
+        }
+    }
+
+    #[test]
+    fn rows_are_sorted_best_known_first() {
+        let cards = [card(1, 3), card(4, 0), card(2, 0), card(0, 0), card(0, 1)];
+        let refs = cards.iter().collect::<Vec<_>>();
+        let stats = Stats::new(&refs, &[], false, 0.0);
+        let counts = stats
+            .rows
+            .iter()
+            .map(|r| (r.hits, r.misses))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![(4, 0), (2, 0), (0, 0), (1, 3), (0, 1)]);
+    }
+
+    #[test]
+    fn summary_figures() {
+        let cards = [card(3, 0), card(1, 0), card(1, 1), card(0, 0)];
+        let refs = cards.iter().collect::<Vec<_>>();
+        let stats = Stats::new(&refs, &[], false, 0.0);
+        assert_eq!(stats.n_responses, 6);
+        assert_eq!(stats.percent_visited, 75.0);
+        assert_eq!(stats.percent_good, 25.0); // one hit alone does not count
+        assert_eq!(stats.score, 50.0);
+        let reverse = Stats::new(&refs, &[], true, 0.0);
+        assert_eq!(reverse.n_responses, 0);
+    }
+
+    #[test]
+    fn empty_deck() {
+        let stats = Stats::new(&[], &[], false, 0.0);
+        assert!(stats.rows.is_empty());
+        assert_eq!(stats.percent_visited, 0.0);
+        assert_eq!(stats.score, 0.0);
+    }
+}
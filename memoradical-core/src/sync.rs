@@ -0,0 +1,267 @@
+//! Three-way merges of what this tab has with what another tab saved,
+//! starting from what this tab last loaded or saved
+
+use std::collections::HashMap;
+
+use crate::reviews::{GradeCounts, Review};
+use crate::{Card, CardId};
+
+/// Which tab's version of a card to keep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+/// A card changed differently in this tab and another one,
+/// where None means the card was deleted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub id: CardId,
+    pub ours: Option<Card>,
+    pub theirs: Option<Card>,
+}
+
+impl Conflict {
+    pub fn side(&self, side: Side) -> Option<&Card> {
+        match side {
+            Side::Ours => self.ours.as_ref(),
+            Side::Theirs => self.theirs.as_ref(),
+        }
+    }
+}
+
+/// The value changed on either side, or None if both changed it differently
+fn pick<T: Clone + PartialEq>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == base {
+        Some(theirs.clone())
+    } else if theirs == base || theirs == ours {
+        Some(ours.clone())
+    } else {
+        None
+    }
+}
+
+/// A counter with both sides' changes added up
+fn add(base: usize, ours: usize, theirs: usize) -> usize {
+    (theirs as i64 + ours as i64 - base as i64).max(0) as usize
+}
+
+fn add_grades(base: &GradeCounts, ours: &GradeCounts, theirs: &GradeCounts) -> GradeCounts {
+    GradeCounts {
+        again: add(base.again, ours.again, theirs.again),
+        hard: add(base.hard, ours.hard, theirs.hard),
+        good: add(base.good, ours.good, theirs.good),
+        easy: add(base.easy, ours.easy, theirs.easy),
+    }
+}
+
+/// Whichever side reviewed one direction of the card last, since its schedules are the newest
+fn later_review(ours: &Card, theirs: &Card, reverse: bool) -> Side {
+    let last = |card: &Card| card.fsrs(reverse).map_or(0.0, |f| f.last_review);
+    if last(theirs) > last(ours) {
+        Side::Theirs
+    } else {
+        Side::Ours
+    }
+}
+
+/// Merge a card changed on both sides, adding up counters from both
+/// and taking the schedules of the latest review, unless the text clashes.
+fn merge_card(base: &Card, ours: &Card, theirs: &Card) -> Option<Card> {
+    let forward = if later_review(ours, theirs, false) == Side::Ours {
+        ours
+    } else {
+        theirs
+    };
+    let reverse = if later_review(ours, theirs, true) == Side::Ours {
+        ours
+    } else {
+        theirs
+    };
+    Some(Card {
+        id: ours.id,
+        deck: pick(&base.deck, &ours.deck, &theirs.deck)?,
+        prompt: pick(&base.prompt, &ours.prompt, &theirs.prompt)?,
+        response: pick(&base.response, &ours.response, &theirs.response)?,
+        tags: pick(&base.tags, &ours.tags, &theirs.tags)?,
+        source: pick(&base.source, &ours.source, &theirs.source)?,
+        hits: add(base.hits, ours.hits, theirs.hits),
+        misses: add(base.misses, ours.misses, theirs.misses),
+        reverse_hits: add(base.reverse_hits, ours.reverse_hits, theirs.reverse_hits),
+        reverse_misses: add(
+            base.reverse_misses,
+            ours.reverse_misses,
+            theirs.reverse_misses,
+        ),
+        grades: add_grades(&base.grades, &ours.grades, &theirs.grades),
+        reverse_grades: add_grades(
+            &base.reverse_grades,
+            &ours.reverse_grades,
+            &theirs.reverse_grades,
+        ),
+        sm2: forward.sm2.clone(),
+        reverse_sm2: reverse.reverse_sm2.clone(),
+        fsrs: forward.fsrs.clone(),
+        reverse_fsrs: reverse.reverse_fsrs.clone(),
+    })
+}
+
+/// Merge the cards, keeping this tab's order and version of each conflicting card
+/// until the conflict is resolved.
+pub fn merge_cards(base: &[Card], ours: &[Card], theirs: &[Card]) -> (Vec<Card>, Vec<Conflict>) {
+    let by_id = |cards: &[Card]| {
+        cards
+            .iter()
+            .map(|c| (c.id, c.clone()))
+            .collect::<HashMap<_, _>>()
+    };
+    let base_cards = by_id(base);
+    let mut their_cards = by_id(theirs);
+    let mut merged = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut conflict = |id, ours: Option<&Card>, theirs| {
+        conflicts.push(Conflict {
+            id,
+            ours: ours.cloned(),
+            theirs,
+        });
+    };
+    for card in ours {
+        let theirs = their_cards.remove(&card.id);
+        match (base_cards.get(&card.id), theirs) {
+            (None, None) => merged.push(card.clone()),
+            (Some(base), None) if base == card => (), // deleted there
+            (_, None) => {
+                conflict(card.id, Some(card), None);
+                merged.push(card.clone());
+            }
+            (None, Some(theirs)) if &theirs == card => merged.push(theirs),
+            (None, Some(theirs)) => {
+                conflict(card.id, Some(card), Some(theirs));
+                merged.push(card.clone());
+            }
+            (Some(base), Some(theirs)) if base == card => merged.push(theirs),
+            (Some(base), Some(theirs)) if base == &theirs => merged.push(card.clone()),
+            (Some(base), Some(theirs)) => match merge_card(base, card, &theirs) {
+                Some(card) => merged.push(card),
+                None => {
+                    conflict(card.id, Some(card), Some(theirs));
+                    merged.push(card.clone());
+                }
+            },
+        }
+    }
+    for card in theirs {
+        let Some(card) = their_cards.remove(&card.id) else {
+            continue;
+        };
+        match base_cards.get(&card.id) {
+            None => merged.push(card),                      // added there
+            Some(base) if base == &card => (),              // deleted here
+            Some(_) => conflict(card.id, None, Some(card)), // deleted here, changed there
+        }
+    }
+    (merged, conflicts)
+}
+
+/// Merge the deck lists, keeping this tab's order.
+pub fn merge_decks(base: &[String], ours: &[String], theirs: &[String]) -> Vec<String> {
+    let mut merged = ours
+        .iter()
+        .filter(|deck| !base.contains(deck) || theirs.contains(deck))
+        .cloned()
+        .collect::<Vec<_>>();
+    for deck in theirs {
+        if !base.contains(deck) && !merged.contains(deck) {
+            merged.push(deck.clone());
+        }
+    }
+    merged
+}
+
+fn common_prefix(a: &[Review], b: &[Review]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Merge the review logs, with the reviews added on either side in time order.
+pub fn merge_reviews(base: &[Review], ours: &[Review], theirs: &[Review]) -> Vec<Review> {
+    let shared = common_prefix(base, ours).min(common_prefix(base, theirs));
+    let (base_rest, our_rest, their_rest) = (&base[shared..], &ours[shared..], &theirs[shared..]);
+    // reviews taken back on either side stay gone
+    let mut rest = their_rest
+        .iter()
+        .filter(|r| !base_rest.contains(r) || our_rest.contains(r))
+        .chain(
+            our_rest
+                .iter()
+                .filter(|r| !base_rest.contains(r) && !their_rest.contains(r)),
+        )
+        .cloned()
+        .collect::<Vec<_>>();
+    rest.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    let mut merged = base[..shared].to_vec();
+    merged.extend(rest);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(id: CardId, prompt: &str, hits: usize) -> Card {
+        Card {
+            id,
+            hits,
+            ..Card::new(prompt, "response")
+        }
+    }
+
+    #[test]
+    fn counters_from_both_sides_add_up() {
+        let base = vec![card(1, "a", 2)];
+        let ours = vec![card(1, "a", 3)];
+        let theirs = vec![card(1, "a", 5)];
+        let (merged, conflicts) = merge_cards(&base, &ours, &theirs);
+        assert!(conflicts.is_empty());
+        assert_eq!(merged, vec![card(1, "a", 6)]);
+    }
+
+    #[test]
+    fn text_changed_on_both_sides_conflicts() {
+        let base = vec![card(1, "a", 0), card(2, "b", 0)];
+        let ours = vec![card(1, "ours", 0), card(2, "b", 0), card(3, "new here", 0)];
+        let theirs = vec![card(1, "theirs", 0), card(4, "new there", 0)];
+        let (merged, conflicts) = merge_cards(&base, &ours, &theirs);
+        let ids = merged.iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert_eq!(merged[0].prompt, "ours");
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].side(Side::Theirs).unwrap().prompt, "theirs");
+    }
+
+    #[test]
+    fn decks_and_reviews_keep_both_sides_additions() {
+        let decks = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let merged = merge_decks(
+            &decks(&["a", "b"]),
+            &decks(&["a", "c"]),
+            &decks(&["b", "d"]),
+        );
+        assert_eq!(merged, decks(&["c", "d"]));
+        let review = |timestamp| Review {
+            card: 1,
+            reverse: false,
+            grade: crate::reviews::Grade::Good,
+            timestamp,
+            latency_ms: 0.0,
+            confused_with: None,
+        };
+        let base = vec![review(1.0)];
+        let ours = vec![review(1.0), review(3.0)];
+        let theirs = vec![review(1.0), review(2.0)];
+        let merged = merge_reviews(&base, &ours, &theirs);
+        let times = merged.iter().map(|r| r.timestamp).collect::<Vec<_>>();
+        assert_eq!(times, vec![1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,68 @@
+/// Split user input into tags, which are separated by commas or whitespace.
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text.split(|c: char| c == ',' || c.is_whitespace()) {
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_owned());
+        }
+    }
+    tags
+}
+
+/// Selects cards by tag, as in "chapter1 chapter2 -hard".
+///
+/// A card matches when it has any of the included tags (or there are none)
+/// and none of the excluded ones, which are written with a leading "-".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TagFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn parse(text: &str) -> Self {
+        let mut filter = Self::default();
+        for term in parse_tags(text) {
+            match term.strip_prefix('-') {
+                Some("") => (),
+                Some(tag) => filter.exclude.push(tag.to_owned()),
+                None => filter.include.push(term),
+            }
+        }
+        filter
+    }
+
+    pub fn matches(&self, tags: &[String]) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|t| tags.contains(t)))
+            && !self.exclude.iter().any(|t| tags.contains(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(text: &str) -> Vec<String> {
+        parse_tags(text)
+    }
+
+    #[test]
+    fn tags_are_split_on_commas_and_whitespace_without_repeats() {
+        assert_eq!(
+            tags(" verbs, french  verbs,,irregular"),
+            ["verbs", "french", "irregular"]
+        );
+    }
+
+    #[test]
+    fn filters_include_any_and_exclude_all() {
+        let filter = TagFilter::parse("chapter1 chapter2 -hard");
+        assert!(filter.matches(&tags("chapter1")));
+        assert!(filter.matches(&tags("chapter2 easy")));
+        assert!(!filter.matches(&tags("chapter2 hard")));
+        assert!(!filter.matches(&tags("chapter3")));
+        let filter = TagFilter::parse("-hard -");
+        assert!(filter.matches(&[]));
+        assert!(!filter.matches(&tags("hard")));
+    }
+}
@@ -0,0 +1,160 @@
+//! Reads files out of a zip archive, as documented in PKWARE's APPNOTE.TXT.
+//! Only stored and deflated entries are supported, and not zip64 or encryption.
+
+use anyhow::{anyhow, Context, Result};
+
+const END_OF_DIRECTORY: u32 = 0x06054b50;
+const END_OF_DIRECTORY_LEN: usize = 22;
+const DIRECTORY_ENTRY: u32 = 0x02014b50;
+const DIRECTORY_ENTRY_LEN: usize = 46;
+const LOCAL_HEADER: u32 = 0x04034b50;
+const LOCAL_HEADER_LEN: usize = 30;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+struct Entry {
+    name: String,
+    method: u16,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+pub struct Archive<'a> {
+    data: &'a [u8],
+    entries: Vec<Entry>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..).and_then(|d| d.get(..2));
+    let bytes = bytes.context("truncated zip")?;
+    Ok(u16::from_le_bytes(bytes.try_into()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..).and_then(|d| d.get(..4));
+    let bytes = bytes.context("truncated zip")?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+impl<'a> Archive<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        // the end record is followed by a comment of up to 64 KiB
+        let end = (0..=data.len().saturating_sub(END_OF_DIRECTORY_LEN))
+            .rev()
+            .take(END_OF_DIRECTORY_LEN + u16::MAX as usize)
+            .find(|i| u32_at(data, *i).ok() == Some(END_OF_DIRECTORY))
+            .ok_or_else(|| anyhow!("not a zip file"))?;
+        let n_entries = u16_at(data, end + 10)? as usize;
+        let mut offset = u32_at(data, end + 16)? as usize;
+        let mut entries = Vec::new();
+        for _ in 0..n_entries {
+            if u32_at(data, offset)? != DIRECTORY_ENTRY {
+                return Err(anyhow!("bad zip directory entry at {offset}"));
+            }
+            let name_len = u16_at(data, offset + 28)? as usize;
+            let extra_len = u16_at(data, offset + 30)? as usize;
+            let comment_len = u16_at(data, offset + 32)? as usize;
+            let name_start = offset + DIRECTORY_ENTRY_LEN;
+            let name = data
+                .get(name_start..name_start + name_len)
+                .context("truncated zip")?;
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: u16_at(data, offset + 10)?,
+                compressed_size: u32_at(data, offset + 20)? as usize,
+                size: u32_at(data, offset + 24)? as usize,
+                header_offset: u32_at(data, offset + 42)? as usize,
+            });
+            offset = name_start + name_len + extra_len + comment_len;
+        }
+        Ok(Archive { data, entries })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|e| e.name == name)
+    }
+
+    /// The uncompressed contents of the named file
+    pub fn read(&self, name: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| anyhow!("no {name:?} in zip file"))?;
+        let offset = entry.header_offset;
+        if u32_at(self.data, offset)? != LOCAL_HEADER {
+            return Err(anyhow!("bad zip header for {name:?}"));
+        }
+        let name_len = u16_at(self.data, offset + 26)? as usize;
+        let extra_len = u16_at(self.data, offset + 28)? as usize;
+        let start = offset + LOCAL_HEADER_LEN + name_len + extra_len;
+        let compressed = self
+            .data
+            .get(start..)
+            .and_then(|d| d.get(..entry.compressed_size))
+            .context("truncated zip")?;
+        match entry.method {
+            METHOD_STORED => Ok(compressed.to_vec()),
+            // no bigger than the directory says, however the data inflates
+            METHOD_DEFLATED => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, entry.size)
+                    .map_err(|e| anyhow!("cannot inflate {name:?}: {:?}", e.status))
+            }
+            m => Err(anyhow!(
+                "unsupported zip compression method {m} for {name:?}"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a small Anki package, made with Python's zipfile module
+    const PACKAGE: &[u8] = include_bytes!("../tests/fixtures/deck.apkg");
+
+    #[test]
+    fn deflated_entries_are_read() {
+        let archive = Archive::new(PACKAGE).unwrap();
+        assert!(archive.contains("collection.anki2"));
+        assert!(!archive.contains("collection.anki21"));
+        assert_eq!(archive.read("media").unwrap(), b"{}");
+        let collection = archive.read("collection.anki2").unwrap();
+        assert_eq!(collection.len(), 26624);
+        assert!(collection.starts_with(b"SQLite format 3\0"));
+        assert!(archive.read("collection.anki21").is_err());
+    }
+
+    #[test]
+    fn truncated_archives_are_errors() {
+        let truncated = include_bytes!("../tests/fixtures/truncated.apkg");
+        assert!(Archive::new(truncated).is_err());
+        assert!(Archive::new(b"").is_err());
+    }
+
+    #[test]
+    fn entries_inflate_no_larger_than_the_directory_says() {
+        let mut package = PACKAGE.to_vec();
+        let entry = package
+            .windows(4)
+            .position(|w| w == DIRECTORY_ENTRY.to_le_bytes())
+            .unwrap();
+        package[entry + 24..entry + 28].copy_from_slice(&1000u32.to_le_bytes());
+        let archive = Archive::new(&package).unwrap();
+        assert!(archive.read("collection.anki2").is_err());
+        assert!(archive.read("media").is_ok());
+    }
+
+    #[test]
+    fn corrupt_archives_do_not_panic() {
+        for i in 0..PACKAGE.len() {
+            let mut package = PACKAGE.to_vec();
+            package[i] ^= 0xff;
+            if let Ok(archive) = Archive::new(&package) {
+                let _ = archive.read("collection.anki2");
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+
+use crate::delimited::TableImport;
+use crate::validate::Problem;
+use crate::Card;
+
+/// Uploaded files on their way to becoming one set of new cards
+#[derive(Clone, Debug, Default)]
+pub struct Upload {
+    pub pending: usize, // files still being read
+    pub cards: Vec<Card>,
+    pub problems: Vec<Problem>,
+    pub tables: VecDeque<TableImport>, // waiting for their columns to be mapped
+}
+
+impl Upload {
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+            && self.cards.is_empty()
+            && self.problems.is_empty()
+            && self.tables.is_empty()
+    }
+
+    /// The table whose columns are being mapped, once every file has been read
+    pub fn table(&self) -> Option<&TableImport> {
+        self.tables.front().filter(|_| self.pending == 0)
+    }
+
+    pub fn table_mut(&mut self) -> Option<&mut TableImport> {
+        self.tables.front_mut().filter(|_| self.pending == 0)
+    }
+
+    /// Whether every file has been read and every table mapped
+    pub fn is_done(&self) -> bool {
+        self.pending == 0 && self.tables.is_empty()
+    }
+
+    /// Add cards read from a file, remembering which file they came from.
+    pub fn add(&mut self, file: &str, cards: Vec<Card>, problems: Vec<Problem>) {
+        self.cards.extend(cards.into_iter().map(|mut card| {
+            card.source = Some(file.to_owned());
+            card
+        }));
+        self.problems.extend(problems);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cards_remember_the_file_they_came_from() {
+        let mut upload = Upload::default();
+        assert!(upload.is_empty());
+        let problem = Problem::whole_file("b.json", "no cards".to_owned());
+        upload.add("a.json", vec![Card::new("a", "b")], vec![]);
+        upload.add("b.json", vec![Card::new("c", "d")], vec![problem.clone()]);
+        let sources = upload
+            .cards
+            .iter()
+            .map(|c| c.source.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(sources, vec![Some("a.json"), Some("b.json")]);
+        assert_eq!(upload.problems, vec![problem]);
+        assert!(!upload.is_empty());
+    }
+
+    #[test]
+    fn tables_wait_until_every_file_is_read() {
+        let mut upload = Upload {
+            pending: 1,
+            ..Upload::default()
+        };
+        let table = TableImport::new("a.csv".to_owned(), "a,b\n".to_owned(), None);
+        upload.tables.push_back(table);
+        assert!(upload.table().is_none() && upload.table_mut().is_none());
+        assert!(!upload.is_done());
+        upload.pending = 0;
+        assert_eq!(upload.table().map(|t| t.file.as_str()), Some("a.csv"));
+        assert!(!upload.is_done());
+        upload.tables.pop_front();
+        assert!(upload.is_done());
+    }
+}
@@ -0,0 +1,444 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+const SNIPPET_CHARS: usize = 60;
+
+/// Something wrong with one record of an uploaded file, or with the whole file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub file: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>, // a character in JSON, but a field in delimited text
+    pub field: Option<String>,
+    pub message: String,
+    pub snippet: String,
+}
+
+impl Problem {
+    /// A problem with the file as a whole
+    pub fn whole_file(file: &str, message: String) -> Self {
+        Problem {
+            file: file.to_owned(),
+            line: None,
+            column: None,
+            field: None,
+            message,
+            snippet: String::new(),
+        }
+    }
+
+    pub fn location(&self) -> String {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => format!("line {line}, column {column}"),
+            (Some(line), None) => format!("line {line}"),
+            _ => "file".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.location())?;
+        if let Some(field) = &self.field {
+            write!(f, ", {field}")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if !self.snippet.is_empty() {
+            write!(f, " in {}", self.snippet)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shorten text to a snippet that fits in the report.
+pub fn snippet(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() > SNIPPET_CHARS {
+        let short = text.chars().take(SNIPPET_CHARS).collect::<String>();
+        format!("{short}...")
+    } else {
+        text.to_owned()
+    }
+}
+
+/// 1-based line and column of a byte offset
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Byte offset of a 1-based line and column, where serde_json counts the column in bytes
+fn offset(text: &str, line: usize, column: usize) -> usize {
+    let line_start = text
+        .match_indices('\n')
+        .nth(line.saturating_sub(2))
+        .filter(|_| line > 1)
+        .map_or(0, |(i, _)| i + 1);
+    let mut offset = (line_start + column.saturating_sub(1)).min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+fn line_snippet(text: &str, line: usize) -> String {
+    snippet(text.lines().nth(line - 1).unwrap_or_default())
+}
+
+/// Decode an uploaded file as UTF-8 text, skipping any byte order mark.
+pub fn decode(file: &str, data: Vec<u8>) -> Result<String, Box<Problem>> {
+    let data = match data.strip_prefix(b"\xef\xbb\xbf") {
+        Some(rest) => rest.to_vec(),
+        None => data,
+    };
+    String::from_utf8(data).map_err(|e| {
+        let valid = e.utf8_error().valid_up_to();
+        let text = String::from_utf8_lossy(e.as_bytes());
+        let prefix = String::from_utf8_lossy(&e.as_bytes()[..valid]);
+        let (line, column) = position(&prefix, prefix.len());
+        Box::new(Problem {
+            file: file.to_owned(),
+            line: Some(line),
+            column: Some(column),
+            field: None,
+            message: "the file is not UTF-8 text".to_owned(),
+            snippet: line_snippet(&text, line),
+        })
+    })
+}
+
+/// Byte ranges of the elements of the JSON array that begins at `start`,
+/// and the offset just past its end, or None if there is no array there.
+/// Empty elements, as left by a trailing comma, are skipped.
+fn array_elements(text: &str, start: usize) -> Option<(Vec<(usize, usize)>, usize)> {
+    if !text[start..].starts_with('[') {
+        return None;
+    }
+    let mut elements = Vec::new();
+    let mut element_start = start + 1;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, b) in text.bytes().enumerate().skip(start + 1) {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' if depth > 0 => depth -= 1,
+            b',' | b']' if depth == 0 => {
+                if !text[element_start..i].trim().is_empty() {
+                    elements.push((element_start, i));
+                }
+                element_start = i + 1;
+                if b == b']' {
+                    return Some((elements, i + 1));
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Byte offsets and names of the keys of a JSON object
+fn object_keys(element: &str) -> Vec<(usize, String)> {
+    let mut keys = Vec::new();
+    let mut depth = 0;
+    let mut string_start = None;
+    let mut escaped = false;
+    for (i, b) in element.bytes().enumerate() {
+        if let Some(start) = string_start {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    string_start = None;
+                    let is_key = element[i + 1..].trim_start().starts_with(':');
+                    if depth == 1 && is_key {
+                        keys.push((start, element[start + 1..i].to_owned()));
+                    }
+                }
+                _ => (),
+            }
+            continue;
+        }
+        match b {
+            b'"' => string_start = Some(i),
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            _ => (),
+        }
+    }
+    keys
+}
+
+/// The field an error is about, from "missing field `x`" or from the key before the error
+fn failed_field(message: &str, element: &str, error_offset: usize) -> Option<String> {
+    if let Some(rest) = message.strip_prefix("missing field `") {
+        return rest.split('`').next().map(|f| f.to_owned());
+    }
+    object_keys(element)
+        .into_iter()
+        .take_while(|(start, _)| *start < error_offset)
+        .last()
+        .map(|(_, key)| key)
+}
+
+fn json_problem(
+    file: &str,
+    text: &str,
+    element_start: usize,
+    element: &str,
+    e: serde_json::Error,
+) -> Problem {
+    // serde_json ends its messages with the position, which is reported separately
+    let message = e.to_string();
+    let message = message
+        .rsplit_once(" at line ")
+        .map_or(message.as_str(), |(m, _)| m)
+        .to_owned();
+    let error_offset = offset(element, e.line(), e.column());
+    let (line, column) = position(text, element_start + error_offset.min(element.len()));
+    Problem {
+        file: file.to_owned(),
+        line: Some(line),
+        column: Some(column),
+        field: failed_field(&message, element, error_offset),
+        message,
+        snippet: line_snippet(text, line),
+    }
+}
+
+/// A parse error in a whole file
+pub fn json_error(file: &str, text: &str, e: serde_json::Error) -> Problem {
+    json_problem(file, text, 0, text, e)
+}
+
+/// Parse one record after upgrading it, since a record can parse as it is
+/// and still need upgrading.
+/// Errors are reported against the record as it was written, where they have a position.
+fn parse_record<T: DeserializeOwned>(
+    element: &str,
+    upgrade: &dyn Fn(Value) -> Value,
+) -> Result<T, serde_json::Error> {
+    let value = serde_json::from_str::<Value>(element)?;
+    serde_json::from_value(upgrade(value))
+        .map_err(|e| serde_json::from_str::<T>(element).err().unwrap_or(e))
+}
+
+fn array_records<T: DeserializeOwned>(
+    file: &str,
+    text: &str,
+    elements: Vec<(usize, usize)>,
+    upgrade: &dyn Fn(Value) -> Value,
+) -> (Vec<T>, Vec<Problem>) {
+    let mut records = Vec::new();
+    let mut problems = Vec::new();
+    for (start, end) in elements {
+        let element = &text[start..end];
+        match parse_record(element, upgrade) {
+            Ok(record) => records.push(record),
+            Err(e) => problems.push(json_problem(file, text, start, element, e)),
+        }
+    }
+    (records, problems)
+}
+
+/// Read a JSON array of cards or other records,
+/// keeping the good ones and reporting each bad one.
+pub fn json_records<T: DeserializeOwned>(file: &str, text: &str) -> (Vec<T>, Vec<Problem>) {
+    upgraded_json_records(file, text, &|value| value)
+}
+
+/// Read a JSON array of records like `json_records`,
+/// passing each record through `upgrade` before parsing it.
+pub fn upgraded_json_records<T: DeserializeOwned>(
+    file: &str,
+    text: &str,
+    upgrade: &dyn Fn(Value) -> Value,
+) -> (Vec<T>, Vec<Problem>) {
+    let start = text.find(|c: char| !c.is_whitespace()).unwrap_or_default();
+    match array_elements(text, start) {
+        Some((elements, end)) if text[end..].trim().is_empty() => {
+            array_records(file, text, elements, upgrade)
+        }
+        // not an array that can be split up, so let the parser say where it fails
+        _ => match serde_json::from_str::<Vec<T>>(text) {
+            Ok(records) => (records, vec![]),
+            Err(e) => (vec![], vec![json_error(file, text, e)]),
+        },
+    }
+}
+
+/// Read the array of records under a key of a top-level JSON object
+/// like `upgraded_json_records`, or None if there is no such array.
+pub fn upgraded_json_records_under<T: DeserializeOwned>(
+    file: &str,
+    text: &str,
+    key: &str,
+    upgrade: &dyn Fn(Value) -> Value,
+) -> Option<(Vec<T>, Vec<Problem>)> {
+    let object_start = text.find(|c: char| !c.is_whitespace())?;
+    let (key_start, _) = object_keys(&text[object_start..])
+        .into_iter()
+        .find(|(_, name)| name == key)?;
+    let after_key = object_start + key_start + key.len() + 2;
+    let colon = after_key + text[after_key..].find(':')?;
+    let start = colon + 1 + text[colon + 1..].find(|c: char| !c.is_whitespace())?;
+    let (elements, _) = array_elements(text, start)?;
+    Some(array_records(file, text, elements, upgrade))
+}
+
+/// Read JSON Lines, one record to a line, like `upgraded_json_records`.
+/// Blank lines are skipped.
+pub fn upgraded_json_lines<T: DeserializeOwned>(
+    file: &str,
+    text: &str,
+    upgrade: &dyn Fn(Value) -> Value,
+) -> (Vec<T>, Vec<Problem>) {
+    let mut elements = Vec::new();
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            elements.push((start, start + line.trim_end().len()));
+        }
+        start += line.len();
+    }
+    array_records(file, text, elements, upgrade)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Record {
+        name: String,
+        count: u32,
+    }
+
+    #[test]
+    fn records_are_upgraded_even_when_they_parse_as_they_are() {
+        let double = |mut value: Value| {
+            value["count"] = (value["count"].as_u64().unwrap_or(1) * 2).into();
+            value
+        };
+        let text = r#"[{"name": "a", "count": 1}, {"name": "b"}]"#;
+        let (records, problems) = upgraded_json_records::<Record>("records.json", text, &double);
+        assert!(problems.is_empty(), "{problems:?}");
+        let counts = records.iter().map(|r| r.count).collect::<Vec<_>>();
+        assert_eq!(counts, vec![2, 2]);
+    }
+
+    fn card_problems(text: &str) -> Vec<Problem> {
+        json_records::<crate::Card>("cards.json", text).1
+    }
+
+    fn where_and_what(problem: &Problem) -> (Option<usize>, Option<usize>, Option<&str>) {
+        (problem.line, problem.column, problem.field.as_deref())
+    }
+
+    #[test]
+    fn bad_records_are_reported_where_they_are() {
+        let text = "[\n\
+            {\"prompt\": \"a\", \"response\": \"b\", \"hits\": 0, \"misses\": 0, \"reverse_hits\": 0, \"reverse_misses\": 0, \"sm2\": null, \"reverse_sm2\": null, \"fsrs\": null, \"reverse_fsrs\": null},\n\
+            {\"prompt\": \"c\", \"response\": 3},\n\
+            {\"prompt\": \"d\"}\n\
+            ]";
+        let (records, problems) = json_records::<crate::Card>("cards.json", text);
+        assert_eq!(records.len(), 1);
+        let places = problems.iter().map(where_and_what).collect::<Vec<_>>();
+        assert_eq!(
+            places,
+            vec![
+                (Some(3), Some(29), Some("response")),
+                (Some(4), Some(15), Some("response")),
+            ]
+        );
+        assert_eq!(problems[1].snippet, "{\"prompt\": \"d\"}");
+        assert!(problems[1].message.starts_with("missing field `response`"));
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_where_they_are() {
+        let problems = card_problems("[\n  {\"prompt\": \"a\",\n   \"response\": tru}\n]");
+        let places = problems.iter().map(where_and_what).collect::<Vec<_>>();
+        assert_eq!(places, vec![(Some(3), Some(19), Some("response"))]);
+        let problems = card_problems("{\"prompt\": \"not an array\"}");
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(1));
+    }
+
+    #[test]
+    fn positions_count_characters_not_bytes() {
+        let problems = card_problems("[{\"prompt\": \"ñandú\", \"response\": 1}]");
+        assert_eq!(
+            where_and_what(&problems[0]),
+            (Some(1), Some(34), Some("response"))
+        );
+    }
+
+    #[test]
+    fn text_that_is_not_utf8_is_reported_where_it_stops() {
+        let data = b"\xef\xbb\xbfprompt\nr\xc3\xa9ponse\xff\n".to_vec();
+        let problem = decode("cards.tsv", data).unwrap_err();
+        assert_eq!((problem.line, problem.column), (Some(2), Some(8)));
+        assert_eq!(
+            decode("cards.tsv", b"\xef\xbb\xbfok".to_vec()).unwrap(),
+            "ok"
+        );
+    }
+
+    #[test]
+    fn problems_say_where_and_what() {
+        let problem = Problem {
+            file: "cards.json".to_owned(),
+            line: Some(3),
+            column: Some(7),
+            field: Some("response".to_owned()),
+            message: "expected a string".to_owned(),
+            snippet: snippet(&"x".repeat(100)),
+        };
+        let expected = format!(
+            "cards.json: line 3, column 7, response: expected a string in {}...",
+            "x".repeat(SNIPPET_CHARS)
+        );
+        assert_eq!(problem.to_string(), expected);
+        let whole = Problem::whole_file("cards.json", "empty".to_owned());
+        assert_eq!(whole.to_string(), "cards.json: file: empty");
+    }
+
+    #[test]
+    fn records_under_a_key_are_found_past_other_keys() {
+        let text =
+            r#"{"settings": {"cards": 1}, "cards": [{"name": "a", "count": 1}, {"name": 2}]}"#;
+        let (records, problems) =
+            upgraded_json_records_under::<Record>("cards.json", text, "cards", &|v| v).unwrap();
+        assert_eq!(
+            records,
+            vec![Record {
+                name: "a".to_owned(),
+                count: 1
+            }]
+        );
+        assert_eq!(
+            where_and_what(&problems[0]),
+            (Some(1), Some(74), Some("name"))
+        );
+        assert!(upgraded_json_records_under::<Record>("x", text, "decks", &|v| v).is_none());
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::{HashSet, LinkedList};
+
+use proptest::prelude::*;
+
+use memoradical_core::delimited::{self, Delimiter, TableImport};
+use memoradical_core::history::{History, Version};
+use memoradical_core::reviews::{Grade, GradeCounts, Review};
+use memoradical_core::schema::{self, Envelope, Settings};
+use memoradical_core::stats::Stats;
+use memoradical_core::{select, sync};
+use memoradical_core::{Card, CardId, Scheduler};
+
+/// The cards with the reviews applied to them, in order
+fn studied(base: &[Card], reviews: &[Review]) -> Vec<Card> {
+    let mut cards = base.to_vec();
+    for review in reviews {
+        if let Some(card) = cards.iter_mut().find(|c| c.id == review.card) {
+            card.apply_review(review);
+        }
+    }
+    cards
+}
+
+fn text() -> impl Strategy<Value = String> {
+    prop::string::string_regex("[a-z ,\t\"\n\r#é]{0,12}").unwrap()
+}
+
+fn tag() -> impl Strategy<Value = String> {
+    prop::string::string_regex("[a-z0-9]{1,6}").unwrap()
+}
+
+prop_compose! {
+    fn card()(
+        id in 1..(1u64 << 53),
+        deck in "[A-Za-z]{1,8}",
+        prompt in text(),
+        response in text(),
+        tags in prop::collection::btree_set(tag(), 0..4),
+        hits in 0..1000usize,
+        misses in 0..1000usize,
+        reverse_hits in 0..1000usize,
+        reverse_misses in 0..1000usize,
+    ) -> Card {
+        Card {
+            id,
+            deck,
+            tags: tags.into_iter().collect(),
+            hits,
+            misses,
+            reverse_hits,
+            reverse_misses,
+            ..Card::new(&prompt, &response)
+        }
+    }
+}
+
+fn review() -> impl Strategy<Value = (usize, bool, Grade)> {
+    (
+        any::<usize>(),
+        any::<bool>(),
+        prop::sample::select(Grade::ALL.to_vec()),
+    )
+}
+
+fn cards(max: usize) -> impl Strategy<Value = Vec<Card>> {
+    prop::collection::vec(card(), 0..max).prop_map(|cards| {
+        let mut ids = HashSet::new();
+        cards.into_iter().filter(|c| ids.insert(c.id)).collect()
+    })
+}
+
+prop_compose! {
+    fn settings()(
+        choose_missed: bool,
+        choose_neglected: bool,
+        reverse_mode: bool,
+        scheduler in prop::sample::select(Scheduler::ALL.to_vec()),
+        session_seed: Option<u64>,
+    ) -> Settings {
+        Settings {
+            choose_missed,
+            choose_neglected,
+            reverse_mode,
+            scheduler,
+            session_seed,
+            ..Settings::default()
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn envelopes_round_trip(settings in settings(), cards in cards(8)) {
+        let text = serde_json::to_string(&Envelope::new(&settings, &cards)).unwrap();
+        let (read_settings, read_cards, problems) = schema::read("cards.json", &text);
+        prop_assert!(problems.is_empty());
+        prop_assert_eq!(read_settings, settings);
+        prop_assert_eq!(read_cards, cards);
+    }
+
+    #[test]
+    fn delimited_exports_round_trip(cards in cards(8), tab: bool) {
+        let delimiter = if tab { Delimiter::Tab } else { Delimiter::Comma };
+        let text = delimited::export(&cards, delimiter);
+        let mut import = TableImport::new("cards".to_owned(), text, Some(delimiter));
+        import.skip_comments = false;
+        let (imported, problems) = import.cards().unwrap();
+        prop_assert!(problems.is_empty());
+        let expected = cards
+            .iter()
+            .map(|c| Card {
+                reverse_hits: 0,
+                reverse_misses: 0,
+                ..c.clone()
+            })
+            .collect::<Vec<_>>();
+        prop_assert_eq!(imported, expected);
+    }
+
+    #[test]
+    fn quoted_fields_parse_back(fields in prop::collection::vec(text(), 2..5), tab: bool) {
+        let delimiter = if tab { Delimiter::Tab } else { Delimiter::Comma };
+        let line = fields
+            .iter()
+            .map(|f| delimited::quote(f, delimiter))
+            .collect::<Vec<_>>()
+            .join(&delimiter.char().to_string());
+        let records = delimited::parse(&line, delimiter);
+        prop_assert_eq!(records.len(), 1);
+        prop_assert_eq!(&records[0].fields, &fields);
+    }
+
+    #[test]
+    fn clear_counts_only_clears_one_direction(card in card(), reverse: bool) {
+        let mut cleared = card.clone();
+        cleared.clear_counts(reverse);
+        prop_assert_eq!(cleared.hits_misses(reverse), (0, 0));
+        prop_assert_eq!(cleared.hits_misses(!reverse), card.hits_misses(!reverse));
+        let without_counts = |card: Card| Card {
+            hits: 0,
+            misses: 0,
+            reverse_hits: 0,
+            reverse_misses: 0,
+            grades: GradeCounts::default(),
+            reverse_grades: GradeCounts::default(),
+            ..card
+        };
+        prop_assert_eq!(without_counts(cleared), without_counts(card));
+    }
+
+    #[test]
+    fn chosen_cards_are_in_the_session_and_not_recent(
+        cards in cards(12),
+        settings in settings(),
+        n_recent in 0..12usize,
+        seed: u64,
+    ) {
+        let refs = cards.iter().collect::<Vec<_>>();
+        let recent = cards.iter().take(n_recent).map(|c| c.id).collect::<LinkedList<_>>();
+        let mut rng = select::session_rng(Some(seed));
+        let chosen = select::choose_card(&refs, &settings, &recent, 0.0, f64::MAX, &mut rng);
+        prop_assert_eq!(chosen.is_some(), !cards.is_empty());
+        if let Some(id) = chosen {
+            prop_assert!(cards.iter().any(|c| c.id == id));
+            prop_assert!(n_recent >= cards.len() || !recent.contains(&id));
+        }
+    }
+
+    #[test]
+    fn display_history_stays_short(
+        ids in prop::collection::vec(any::<CardId>(), 0..50),
+        n_cards in 1..1000usize,
+    ) {
+        let mut recent = LinkedList::new();
+        for id in ids.iter() {
+            select::record_display(&mut recent, *id, n_cards);
+        }
+        let n = (n_cards as f64).log2().round() as usize;
+        prop_assert_eq!(recent.len(), ids.len().min(n));
+        prop_assert!(recent.iter().eq(ids.iter().skip(ids.len() - recent.len())));
+    }
+
+    #[test]
+    fn stats_are_in_range(cards in cards(12), reverse: bool) {
+        let refs = cards.iter().collect::<Vec<_>>();
+        let stats = Stats::new(&refs, &[], reverse, 0.0);
+        prop_assert_eq!(stats.rows.len(), cards.len());
+        for figure in [stats.score, stats.percent_good, stats.percent_visited] {
+            prop_assert!((-100.0..=100.0).contains(&figure));
+        }
+        prop_assert!((0.0..=100.0).contains(&stats.percent_visited));
+        for pair in stats.rows.windows(2) {
+            prop_assert!(pair[0].goodness >= pair[1].goodness);
+        }
+        for row in stats.rows.iter() {
+            prop_assert!((-1.0..=1.0).contains(&row.goodness));
+            prop_assert!((0.0..=100.0).contains(&row.percent_hit));
+        }
+    }
+
+    #[test]
+    fn counters_merged_from_two_tabs_match_the_merged_log(
+        base in cards(5),
+        ours in prop::collection::vec(review(), 0..12),
+        theirs in prop::collection::vec(review(), 0..12),
+    ) {
+        prop_assume!(!base.is_empty());
+        // each tab reviews the loaded cards at times of its own
+        let log = |reviews: Vec<(usize, bool, Grade)>, tab: f64| {
+            reviews
+                .into_iter()
+                .enumerate()
+                .map(|(i, (card, reverse, grade))| Review {
+                    card: base[card % base.len()].id,
+                    reverse,
+                    grade,
+                    timestamp: 2.0 * i as f64 + tab,
+                    latency_ms: 0.0,
+                    confused_with: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let (ours, theirs) = (log(ours, 0.0), log(theirs, 1.0));
+        let (merged, conflicts) = sync::merge_cards(
+            &base,
+            &studied(&base, &ours),
+            &studied(&base, &theirs),
+        );
+        prop_assert!(conflicts.is_empty());
+        let rebuilt = studied(&base, &sync::merge_reviews(&[], &ours, &theirs));
+        let counters = |cards: &[Card]| {
+            cards
+                .iter()
+                .map(|c| (c.id, c.hits_misses(false), c.hits_misses(true), c.grades, c.reverse_grades))
+                .collect::<Vec<_>>()
+        };
+        prop_assert_eq!(counters(&merged), counters(&rebuilt));
+    }
+
+    #[test]
+    fn undo_and_redo_retrace_every_step(steps in prop::collection::vec(cards(5), 1..6)) {
+        let versions = steps
+            .into_iter()
+            .map(|cards| Version { cards, ..Version::default() })
+            .collect::<Vec<_>>();
+        let mut history = History::default();
+        for pair in versions.windows(2) {
+            history.record(&pair[0], &pair[1]);
+        }
+        let mut current = versions.last().unwrap().clone();
+        let mut undone = vec![current.clone()];
+        while let Some(earlier) = history.undo(&current) {
+            current = earlier;
+            undone.push(current.clone());
+        }
+        let mut expected = versions.clone();
+        expected.dedup();
+        expected.reverse();
+        prop_assert_eq!(&undone, &expected);
+        while let Some(later) = history.redo(&current) {
+            current = later;
+        }
+        prop_assert_eq!(&current, versions.last().unwrap());
+    }
+}
@@ -0,0 +1,177 @@
+//! Statistical checks of how cards are chosen for study.
+//!
+//! The draws are seeded, so each check comes out the same on every run,
+//! and the bounds leave room for the seeds to change.
+
+use std::collections::LinkedList;
+
+use memoradical_core::schema::Settings;
+use memoradical_core::select::{self, choose_card_beta, record_display};
+use memoradical_core::{Card, CardId};
+
+const DRAWS: usize = 20_000;
+
+// chi-squared values exceeded by chance one time in a thousand, by degrees of freedom
+const CHI_SQUARED_CRITICAL: [f64; 10] = [
+    10.83, 13.82, 16.27, 18.47, 20.52, 22.46, 24.32, 26.12, 27.88, 29.59,
+];
+
+/// Cards with the given hits and misses, numbered from 1
+fn deck(counts: &[(usize, usize)]) -> Vec<Card> {
+    counts
+        .iter()
+        .enumerate()
+        .map(|(i, &(hits, misses))| Card {
+            id: i as CardId + 1,
+            hits,
+            misses,
+            ..Card::new(&format!("front {i}"), &format!("back {i}"))
+        })
+        .collect()
+}
+
+/// How many times each card is chosen, with nothing recently displayed
+fn frequencies(cards: &[Card], settings: &Settings, seed: u64) -> Vec<usize> {
+    let refs = cards.iter().collect::<Vec<_>>();
+    let recent = LinkedList::new();
+    let mut rng = select::session_rng(Some(seed));
+    let mut counts = vec![0; cards.len()];
+    for _ in 0..DRAWS {
+        let id = choose_card_beta(&refs, settings, &recent, &mut rng).unwrap();
+        counts[cards.iter().position(|c| c.id == id).unwrap()] += 1;
+    }
+    counts
+}
+
+/// Whether the counts could well have been drawn in the given proportions
+fn fits(observed: &[usize], weights: &[f64]) -> bool {
+    let total_weight = weights.iter().sum::<f64>();
+    let n = observed.iter().sum::<usize>() as f64;
+    let chi_squared = observed
+        .iter()
+        .zip(weights)
+        .map(|(&o, w)| {
+            let expected = n * w / total_weight;
+            (o as f64 - expected).powi(2) / expected
+        })
+        .sum::<f64>();
+    chi_squared < CHI_SQUARED_CRITICAL[observed.len() - 2]
+}
+
+#[test]
+fn missed_cards_come_up_more_often() {
+    let cards = deck(&[(5, 0), (4, 1), (3, 2), (2, 3), (1, 4), (0, 5)]);
+    let counts = frequencies(&cards, &Settings::default(), 1);
+    assert!(
+        counts.windows(2).all(|pair| pair[0] < pair[1]),
+        "{counts:?}"
+    );
+    // against a fair share of a sixth each
+    assert!(2 * counts[5] * cards.len() > 3 * DRAWS, "{counts:?}");
+    assert!(2 * counts[0] * cards.len() < DRAWS, "{counts:?}");
+}
+
+#[test]
+fn cards_with_the_same_record_are_equally_likely() {
+    let cards = deck(&[(2, 2); 10]);
+    let counts = frequencies(&cards, &Settings::default(), 2);
+    assert!(fits(&counts, &[1.0; 10]), "{counts:?}");
+}
+
+#[test]
+fn without_preferences_every_card_is_equally_likely() {
+    let cards = deck(&[(9, 0), (0, 9), (0, 0), (3, 3)]);
+    let settings = Settings {
+        choose_missed: false,
+        ..Settings::default()
+    };
+    let counts = frequencies(&cards, &settings, 3);
+    assert!(fits(&counts, &[1.0; 4]), "{counts:?}");
+}
+
+#[test]
+fn neglected_cards_come_up_in_proportion_to_how_seldom_they_were_seen() {
+    let cards = deck(&[(0, 0), (1, 0), (2, 2), (5, 5)]);
+    let settings = Settings {
+        choose_missed: false,
+        choose_neglected: true,
+        ..Settings::default()
+    };
+    let counts = frequencies(&cards, &settings, 4);
+    assert!(fits(&counts, &[1.0, 1.0, 0.25, 0.1]), "{counts:?}");
+}
+
+#[test]
+fn recently_displayed_cards_are_not_chosen_again() {
+    let counts = (0..16).map(|i| (i % 5, i % 3)).collect::<Vec<_>>();
+    let cards = deck(&counts);
+    let refs = cards.iter().collect::<Vec<_>>();
+    for (seed, choose_neglected) in [(5, false), (6, true)] {
+        let settings = Settings {
+            choose_neglected,
+            ..Settings::default()
+        };
+        let mut rng = select::session_rng(Some(seed));
+        let mut recent = LinkedList::new();
+        let mut shown = vec![0; cards.len()];
+        for _ in 0..DRAWS {
+            let id = choose_card_beta(&refs, &settings, &recent, &mut rng).unwrap();
+            assert!(!recent.contains(&id), "{id} is in {recent:?}");
+            record_display(&mut recent, id, cards.len());
+            assert!(recent.len() <= 4);
+            shown[id as usize - 1] += 1;
+        }
+        assert!(shown.iter().all(|&n| n > 0), "{shown:?}");
+    }
+}
+
+#[test]
+fn recently_displayed_cards_are_chosen_when_there_is_nothing_else() {
+    let cards = deck(&[(1, 0), (0, 1)]);
+    let refs = cards.iter().collect::<Vec<_>>();
+    let recent = LinkedList::from([1, 2]);
+    let mut rng = select::session_rng(Some(7));
+    let chosen = (0..100)
+        .map(|_| choose_card_beta(&refs, &Settings::default(), &recent, &mut rng).unwrap())
+        .collect::<Vec<_>>();
+    assert!(chosen.contains(&1) && chosen.contains(&2));
+}
+
+/// The cards shown in a session where every card with an even id is hit and every other one missed
+fn session(seed: Option<u64>) -> Vec<CardId> {
+    let mut cards = deck(&[(0, 0); 12]);
+    let settings = Settings {
+        session_seed: seed,
+        ..Settings::default()
+    };
+    let mut rng = select::session_rng(settings.session_seed);
+    let mut recent = LinkedList::new();
+    let mut shown = Vec::new();
+    for _ in 0..200 {
+        let refs = cards.iter().collect::<Vec<_>>();
+        let id = select::choose_card(&refs, &settings, &recent, 0.0, 0.0, &mut rng).unwrap();
+        record_display(&mut recent, id, cards.len());
+        let card = cards.iter_mut().find(|c| c.id == id).unwrap();
+        if id % 2 == 0 {
+            card.hits += 1;
+        } else {
+            card.misses += 1;
+        }
+        shown.push(id);
+    }
+    shown
+}
+
+#[test]
+fn a_seeded_session_plays_out_the_same_way_again() {
+    assert_eq!(session(Some(42)), session(Some(42)));
+    assert_ne!(session(Some(42)), session(Some(43)));
+    assert_ne!(session(None), session(None));
+}
+
+#[test]
+fn a_seeded_session_still_favours_missed_cards() {
+    let shown = session(Some(8));
+    let missed = shown.iter().skip(100).filter(|id| *id % 2 == 1).count();
+    assert!(missed > 75, "{missed} of the last 100 were missed cards");
+}
@@ -0,0 +1,417 @@
+//! A study session and how keys change it
+
+use std::collections::LinkedList;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::rngs::StdRng;
+use ratatui::crossterm::event::KeyCode;
+
+use memoradical_core::reviews::{Grade, Review};
+use memoradical_core::schema::Settings;
+use memoradical_core::stats::Stats;
+use memoradical_core::tags::TagFilter;
+use memoradical_core::{select, Card, CardId};
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+pub fn now_ms() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_millis() as f64)
+}
+
+/// The start of the day, in UTC since the terminal has no time zone to go by
+pub fn day_start(ms: f64) -> f64 {
+    (ms / DAY_MS).floor() * DAY_MS
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Face {
+    Prompt,
+    Response,
+}
+
+/// Which side of a card is being edited
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Prompt,
+    Response,
+}
+
+/// A card being edited, with the text typed so far
+#[derive(Clone, Debug, PartialEq)]
+pub struct Editor {
+    pub id: CardId,
+    pub prompt: String,
+    pub response: String,
+    pub side: Side,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum View {
+    Edit(Editor),
+    Stats,
+    Study,
+}
+
+/// What to do after a key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Continue,
+    Save, // the cards or settings changed
+    Quit,
+}
+
+pub struct App {
+    pub cards: Vec<Card>,
+    pub current_card: Option<CardId>,
+    pub deck: Option<String>, // None for all decks
+    display_history: LinkedList<CardId>,
+    pub face: Face,
+    pub message: Option<String>,
+    reviews: Vec<Review>, // only this session's, since card files have no review log
+    rng: StdRng,
+    pub settings: Settings,
+    shown_at: f64,
+    pub stats_offset: usize, // rows scrolled past
+    tag_filter: TagFilter,
+    pub view: View,
+}
+
+fn in_session(deck: &Option<String>, tag_filter: &TagFilter, card: &Card) -> bool {
+    deck.as_ref().is_none_or(|d| *d == card.deck) && tag_filter.matches(&card.tags)
+}
+
+impl App {
+    pub fn new(
+        settings: Settings,
+        cards: Vec<Card>,
+        deck: Option<String>,
+        tag_filter: TagFilter,
+    ) -> Self {
+        let mut app = App {
+            cards,
+            current_card: None,
+            deck,
+            display_history: LinkedList::new(),
+            face: Face::Prompt,
+            message: None,
+            reviews: vec![],
+            rng: select::session_rng(settings.session_seed),
+            settings,
+            shown_at: now_ms(),
+            stats_offset: 0,
+            tag_filter,
+            view: View::Study,
+        };
+        app.current_card = app.choose_card();
+        app
+    }
+
+    pub fn card(&self, id: CardId) -> Option<&Card> {
+        self.cards.iter().find(|c| c.id == id)
+    }
+
+    fn card_mut(&mut self, id: CardId) -> Option<&mut Card> {
+        self.cards.iter_mut().find(|c| c.id == id)
+    }
+
+    /// The cards in the deck, whatever their tags, as the stats cover
+    pub fn deck_cards(&self) -> Vec<&Card> {
+        self.cards
+            .iter()
+            .filter(|c| self.deck.as_ref().is_none_or(|d| *d == c.deck))
+            .collect()
+    }
+
+    fn n_session_cards(&self) -> usize {
+        self.cards
+            .iter()
+            .filter(|c| in_session(&self.deck, &self.tag_filter, c))
+            .count()
+    }
+
+    fn choose_card(&mut self) -> Option<CardId> {
+        let cards = self
+            .cards
+            .iter()
+            .filter(|c| in_session(&self.deck, &self.tag_filter, c))
+            .collect::<Vec<_>>();
+        let now = now_ms();
+        let cutoff = day_start(now) + DAY_MS;
+        select::choose_card(
+            &cards,
+            &self.settings,
+            &self.display_history,
+            now,
+            cutoff,
+            &mut self.rng,
+        )
+    }
+
+    /// The text on the visible face of the card
+    pub fn face_text<'a>(&self, card: &'a Card) -> &'a str {
+        if (self.face == Face::Prompt) != self.settings.reverse_mode {
+            &card.prompt
+        } else {
+            &card.response
+        }
+    }
+
+    pub fn stats(&self) -> Stats<'_> {
+        let cards = self.deck_cards();
+        let start = day_start(now_ms());
+        Stats::new(&cards, &self.reviews, self.settings.reverse_mode, start)
+    }
+
+    fn next(&mut self) {
+        if let Some(id) = self.current_card {
+            let n_cards = self.n_session_cards();
+            select::record_display(&mut self.display_history, id, n_cards);
+        }
+        self.current_card = self.choose_card();
+        self.show_prompt();
+    }
+
+    /// Skips any cards that have been deleted since they were displayed.
+    fn prev(&mut self) {
+        while let Some(id) = self.display_history.pop_back() {
+            if self.card(id).is_some() {
+                self.current_card = Some(id);
+                self.show_prompt();
+                return;
+            }
+        }
+    }
+
+    fn show_prompt(&mut self) {
+        self.face = Face::Prompt;
+        self.shown_at = now_ms();
+    }
+
+    /// Log a response to the current card and move on to the next one.
+    fn respond(&mut self, grade: Grade) -> Outcome {
+        let Some(id) = self.current_card else {
+            return Outcome::Continue;
+        };
+        let now = now_ms();
+        let review = Review {
+            card: id,
+            reverse: self.settings.reverse_mode,
+            grade,
+            timestamp: now,
+            latency_ms: now - self.shown_at,
+            confused_with: None,
+        };
+        let Some(card) = self.card_mut(id) else {
+            return Outcome::Continue;
+        };
+        card.apply_review(&review);
+        self.reviews.push(review);
+        self.next();
+        Outcome::Save
+    }
+
+    pub fn key(&mut self, key: KeyCode) -> Outcome {
+        match &mut self.view {
+            View::Edit(editor) => match key {
+                KeyCode::Char(c) => {
+                    editor.text_mut().push(c);
+                    Outcome::Continue
+                }
+                KeyCode::Backspace => {
+                    editor.text_mut().pop();
+                    Outcome::Continue
+                }
+                KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                    editor.side = match editor.side {
+                        Side::Prompt => Side::Response,
+                        Side::Response => Side::Prompt,
+                    };
+                    Outcome::Continue
+                }
+                KeyCode::Enter => {
+                    let editor = editor.clone();
+                    self.view = View::Study;
+                    let Some(card) = self.card_mut(editor.id) else {
+                        return Outcome::Continue;
+                    };
+                    card.prompt = editor.prompt;
+                    card.response = editor.response;
+                    Outcome::Save
+                }
+                KeyCode::Esc => {
+                    self.view = View::Study;
+                    Outcome::Continue
+                }
+                _ => Outcome::Continue,
+            },
+            View::Stats => {
+                match key {
+                    KeyCode::Char('q') => return Outcome::Quit,
+                    KeyCode::Char('s') | KeyCode::Esc => self.view = View::Study,
+                    KeyCode::Down | KeyCode::Char('j') => self.stats_offset += 1,
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        self.stats_offset = self.stats_offset.saturating_sub(1)
+                    }
+                    KeyCode::PageDown => self.stats_offset += 20,
+                    KeyCode::PageUp => self.stats_offset = self.stats_offset.saturating_sub(20),
+                    KeyCode::Char('r') => {
+                        self.settings.reverse_mode = !self.settings.reverse_mode;
+                        return Outcome::Save;
+                    }
+                    _ => (),
+                }
+                Outcome::Continue
+            }
+            View::Study => self.study_key(key),
+        }
+    }
+
+    fn study_key(&mut self, key: KeyCode) -> Outcome {
+        let KeyCode::Char(c) = key else {
+            return if key == KeyCode::Esc {
+                Outcome::Quit
+            } else {
+                Outcome::Continue
+            };
+        };
+        let k = c.to_string();
+        let grade = Grade::ALL.into_iter().find(|g| g.key() == k);
+        if let Some(grade) = grade.filter(|_| self.settings.graded_mode) {
+            return self.respond(grade);
+        }
+        match c {
+            'f' => {
+                self.face = match self.face {
+                    Face::Prompt => Face::Response,
+                    Face::Response => Face::Prompt,
+                };
+                Outcome::Continue
+            }
+            'h' => self.respond(Grade::Good),
+            'm' => self.respond(Grade::Again),
+            'n' => {
+                self.next();
+                Outcome::Continue
+            }
+            'p' => {
+                self.prev();
+                Outcome::Continue
+            }
+            'e' => {
+                if let Some(card) = self.current_card.and_then(|id| self.card(id)) {
+                    self.view = View::Edit(Editor {
+                        id: card.id,
+                        prompt: card.prompt.clone(),
+                        response: card.response.clone(),
+                        side: Side::Prompt,
+                    });
+                }
+                Outcome::Continue
+            }
+            'r' => {
+                self.settings.reverse_mode = !self.settings.reverse_mode;
+                self.show_prompt();
+                Outcome::Save
+            }
+            's' => {
+                self.stats_offset = 0;
+                self.view = View::Stats;
+                Outcome::Continue
+            }
+            'q' => Outcome::Quit,
+            _ => Outcome::Continue,
+        }
+    }
+}
+
+impl Editor {
+    fn text_mut(&mut self) -> &mut String {
+        match self.side {
+            Side::Prompt => &mut self.prompt,
+            Side::Response => &mut self.response,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(n: usize) -> App {
+        let cards = (0..n)
+            .map(|i| Card {
+                id: i as CardId + 1,
+                ..Card::new(&format!("front {i}"), &format!("back {i}"))
+            })
+            .collect();
+        let settings = Settings {
+            session_seed: Some(1),
+            ..Settings::default()
+        };
+        App::new(settings, cards, None, TagFilter::default())
+    }
+
+    fn current(app: &App) -> &Card {
+        app.card(app.current_card.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn hits_and_misses_are_counted_and_saved() {
+        let mut app = app(8);
+        let first = app.current_card.unwrap();
+        assert_eq!(app.key(KeyCode::Char('h')), Outcome::Save);
+        assert_eq!(app.card(first).unwrap().hits_misses(false), (1, 0));
+        assert_ne!(app.current_card, Some(first));
+        let second = app.current_card.unwrap();
+        app.key(KeyCode::Char('m'));
+        assert_eq!(app.card(second).unwrap().hits_misses(false), (0, 1));
+        assert_eq!(app.stats().n_responses_today, 2);
+    }
+
+    #[test]
+    fn previous_cards_come_back_in_reverse_order() {
+        let mut app = app(16);
+        let mut shown = vec![];
+        for _ in 0..3 {
+            shown.push(app.current_card.unwrap());
+            app.key(KeyCode::Char('n'));
+        }
+        for id in shown.into_iter().rev() {
+            app.key(KeyCode::Char('p'));
+            assert_eq!(app.current_card, Some(id));
+        }
+    }
+
+    #[test]
+    fn reverse_mode_shows_and_counts_the_other_side() {
+        let mut app = app(4);
+        assert_eq!(app.key(KeyCode::Char('r')), Outcome::Save);
+        let card = current(&app).clone();
+        assert_eq!(app.face_text(&card), card.response);
+        app.key(KeyCode::Char('f'));
+        assert_eq!(app.face_text(&card), card.prompt);
+        app.key(KeyCode::Char('h'));
+        assert_eq!(app.card(card.id).unwrap().hits_misses(true), (1, 0));
+    }
+
+    #[test]
+    fn edits_are_saved_on_enter_and_dropped_on_escape() {
+        let mut app = app(2);
+        let id = app.current_card.unwrap();
+        app.key(KeyCode::Char('e'));
+        app.key(KeyCode::Backspace);
+        app.key(KeyCode::Char('X'));
+        app.key(KeyCode::Tab);
+        app.key(KeyCode::Char('!'));
+        assert_eq!(app.key(KeyCode::Enter), Outcome::Save);
+        let card = app.card(id).unwrap();
+        assert!(card.prompt.ends_with('X') && card.response.ends_with('!'));
+        app.key(KeyCode::Char('e'));
+        app.key(KeyCode::Char('?'));
+        assert_eq!(app.key(KeyCode::Esc), Outcome::Continue);
+        assert!(!app.card(id).unwrap().prompt.contains('?'));
+        assert_eq!(app.view, View::Study);
+    }
+}
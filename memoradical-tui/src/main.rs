@@ -0,0 +1,76 @@
+//! Study a Memoradical card file in the terminal
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Parser;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::DefaultTerminal;
+
+use memoradical_core::cardfile;
+use memoradical_core::tags::TagFilter;
+
+use app::{App, Outcome};
+
+mod app;
+mod ui;
+
+/// Study a card file, as downloaded from the web app, in the terminal.
+///
+/// Counts are written back to the file after every response.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// The card file
+    file: PathBuf,
+
+    /// Study only the cards in this deck
+    #[arg(long)]
+    deck: Option<String>,
+
+    /// Study only cards with these tags, as in "chapter1 chapter2 -hard"
+    #[arg(long, default_value = "")]
+    tags: String,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let (settings, cards) = cardfile::read(&args.file)?;
+    let mut app = App::new(settings, cards, args.deck, TagFilter::parse(&args.tags));
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut app, &args.file);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App, path: &Path) -> Result<()> {
+    let mut unsaved = false;
+    loop {
+        terminal.draw(|frame| ui::draw(frame, app, path))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let interrupted =
+            key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+        let outcome = if interrupted {
+            Outcome::Quit
+        } else {
+            app.key(key.code)
+        };
+        match outcome {
+            Outcome::Continue => (),
+            Outcome::Save => {
+                let saved = cardfile::write(path, &app.settings, &app.cards);
+                unsaved = saved.is_err();
+                app.message = saved.err().map(|e| format!("{e:#}"));
+            }
+            Outcome::Quit if unsaved => {
+                return cardfile::write(path, &app.settings, &app.cards);
+            }
+            Outcome::Quit => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Drawing the session in the terminal
+
+use std::path::Path;
+
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, Paragraph, Row, Table, Wrap};
+use ratatui::Frame;
+
+use memoradical_core::stats::GOODNESS_CRITERION;
+use memoradical_core::Scheduler;
+
+use crate::app::{App, Editor, Face, Side, View};
+
+const STUDY_KEYS: &str =
+    "f flip  h hit  m miss  n next  p previous  e edit  r reverse  s stats  q quit";
+const GRADE_KEYS: &str = "1 again  2 hard  3 good  4 easy";
+const STATS_KEYS: &str = "j/k scroll  r reverse  s study  q quit";
+const EDIT_KEYS: &str = "tab other side  enter save  esc cancel";
+
+pub fn draw(frame: &mut Frame, app: &App, path: &Path) {
+    let [title_area, main_area, keys_area, message_area] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Fill(1),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    frame.render_widget(title(app, path).bold(), title_area);
+    let keys = match &app.view {
+        View::Edit(editor) => {
+            draw_editor(frame, editor, main_area);
+            EDIT_KEYS.to_owned()
+        }
+        View::Stats => {
+            draw_stats(frame, app, main_area);
+            STATS_KEYS.to_owned()
+        }
+        View::Study => {
+            draw_card(frame, app, main_area);
+            if app.settings.graded_mode {
+                format!("{GRADE_KEYS}  {STUDY_KEYS}")
+            } else {
+                STUDY_KEYS.to_owned()
+            }
+        }
+    };
+    frame.render_widget(Line::from(keys).dim(), keys_area);
+    if let Some(message) = &app.message {
+        frame.render_widget(Line::from(message.as_str()).red(), message_area);
+    }
+}
+
+fn title(app: &App, path: &Path) -> Line<'static> {
+    let mut parts = vec![
+        path.display().to_string(),
+        app.deck.clone().unwrap_or_else(|| "All decks".to_owned()),
+        app.settings.scheduler.label().to_owned(),
+    ];
+    if app.settings.reverse_mode {
+        parts.push("reverse".to_owned());
+    }
+    Line::from(parts.join(" | "))
+}
+
+fn draw_card(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(card) = app.current_card.and_then(|id| app.card(id)) else {
+        let text = if app.settings.scheduler == Scheduler::Beta {
+            "There are no cards to study."
+        } else {
+            "No cards are due today."
+        };
+        frame.render_widget(Paragraph::new(text).block(Block::bordered()), area);
+        return;
+    };
+    let label = match app.face {
+        Face::Prompt => "prompt",
+        Face::Response => "response",
+    };
+    let paragraph = Paragraph::new(app.face_text(card))
+        .wrap(Wrap { trim: false })
+        .centered()
+        .block(Block::bordered().title(label));
+    frame.render_widget(paragraph, area);
+}
+
+fn draw_editor(frame: &mut Frame, editor: &Editor, area: Rect) {
+    let [prompt_area, response_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Fill(1)]).areas(area);
+    for (side, text, area) in [
+        (Side::Prompt, &editor.prompt, prompt_area),
+        (Side::Response, &editor.response, response_area),
+    ] {
+        let (label, style) = if side == editor.side {
+            (format!("{side:?} (editing)"), Style::new().bold())
+        } else {
+            (format!("{side:?}"), Style::new())
+        };
+        let paragraph = Paragraph::new(text.as_str())
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::bordered()
+                    .title(label.to_lowercase())
+                    .border_style(style),
+            );
+        frame.render_widget(paragraph, area);
+    }
+}
+
+/// The figures and table of the web app's Stats view
+fn draw_stats(frame: &mut Frame, app: &App, area: Rect) {
+    let stats = app.stats();
+    if stats.rows.is_empty() {
+        frame.render_widget(Paragraph::new("There are no cards."), area);
+        return;
+    }
+    let summary = Text::from(vec![
+        Line::from(format!("Overall score: {:.2}", stats.score)),
+        Line::from(format!(
+            "Cards known well: {:.2}% (visited more than once and with goodness > {:.2})",
+            stats.percent_good, GOODNESS_CRITERION
+        )),
+        Line::from(format!(
+            "Cards visited: {:.2}% of {}",
+            stats.percent_visited,
+            stats.rows.len()
+        )),
+        Line::from(format!("Number of responses: {}", stats.n_responses)),
+        Line::from(format!(
+            "Responses today: {} (in this session)",
+            stats.n_responses_today
+        )),
+    ]);
+    let [summary_area, table_area] = Layout::vertical([
+        Constraint::Length(summary.height() as u16 + 1),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    frame.render_widget(Paragraph::new(summary), summary_area);
+    let prefix = if app.settings.reverse_mode {
+        "reverse "
+    } else {
+        ""
+    };
+    let header = Row::new(vec![
+        "prompt".to_owned(),
+        "response".to_owned(),
+        format!("{prefix}hits"),
+        format!("{prefix}misses"),
+        format!("{prefix}percent hit"),
+        format!("{prefix}goodness"),
+    ])
+    .style(Style::new().add_modifier(Modifier::BOLD));
+    let offset = app.stats_offset.min(stats.rows.len() - 1);
+    let rows = stats.rows.iter().skip(offset).enumerate().map(|(i, row)| {
+        let cells = vec![
+            row.card.prompt.clone(),
+            row.card.response.clone(),
+            format!("{:>5}", row.hits),
+            format!("{:>6}", row.misses),
+            format!("{:>11.2}", row.percent_hit),
+            format!("{:>8.2}", row.goodness),
+        ];
+        let striped = if (offset + i) % 2 == 1 {
+            Style::new().add_modifier(Modifier::DIM)
+        } else {
+            Style::new()
+        };
+        Row::new(cells).style(striped)
+    });
+    let widths = [
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+        Constraint::Length(prefix.len() as u16 + 5),
+        Constraint::Length(prefix.len() as u16 + 6),
+        Constraint::Length(prefix.len() as u16 + 11),
+        Constraint::Length(prefix.len() as u16 + 8),
+    ];
+    frame.render_widget(Table::new(rows, widths).header(header), table_area);
+}
@@ -0,0 +1,41 @@
+use anyhow::{anyhow, Result};
+use gloo_file::Blob;
+use gloo_timers::callback::Timeout;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlAnchorElement, Url};
+
+// give the browser time to start the download before the URL is revoked
+const REVOKE_URL_DELAY_MS: u32 = 10_000;
+
+/// A file name like "memoradical-cards-2024-03-09.json" with today's local date.
+pub fn dated_filename(stem: &str, extension: &str) -> String {
+    let date = js_sys::Date::new_0();
+    format!(
+        "{stem}-{:04}-{:02}-{:02}.{extension}",
+        date.get_full_year(),
+        date.get_month() + 1,
+        date.get_date()
+    )
+}
+
+/// Have the browser save `contents` as a file, without needing clipboard permission.
+pub fn download(filename: &str, mime_type: &str, contents: &str) -> Result<()> {
+    let blob = Blob::new_with_options(contents, Some(mime_type));
+    let url = Url::create_object_url_with_blob(blob.as_ref())
+        .map_err(|e| anyhow!("Cannot create download URL: {:?}", e))?;
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or_else(|| anyhow!("Cannot obtain document from browser"))?;
+    let anchor = document
+        .create_element("a")
+        .map_err(|e| anyhow!("Cannot create download link: {:?}", e))?
+        .unchecked_into::<HtmlAnchorElement>();
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    anchor.click();
+    Timeout::new(REVOKE_URL_DELAY_MS, move || {
+        let _ = Url::revoke_object_url(&url);
+    })
+    .forget();
+    Ok(())
+}
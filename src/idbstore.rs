@@ -0,0 +1,805 @@
+//! Cards and review events kept as separate IndexedDB records,
+//! so that a save writes only the records that changed
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::future::{ready, Future};
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context, Result};
+use js_sys::{Array, Promise};
+use serde::de::DeserializeOwned;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    BroadcastChannel, Event, IdbDatabase, IdbKeyRange, IdbRequest, IdbTransaction,
+    IdbTransactionMode, MessageEvent,
+};
+
+use memoradical_core::restore::{self, RestorePoint};
+use memoradical_core::reviews::Review;
+use memoradical_core::schema::{self, Settings, LEGACY_VERSION, SCHEMA_VERSION};
+use memoradical_core::{Card, CardId};
+
+use crate::storage::{
+    ChangedElsewhere, Listing, Loaded, Raw, Reloaded, Reloading, Saved, Saving, Store,
+};
+
+const DB_NAME: &str = "net.noserose.memoradical";
+const DB_VERSION: u32 = 2;
+const CARDS: &str = "cards"; // card JSON by card id
+const REVIEWS: &str = "reviews"; // review JSON by position in the log
+const META: &str = "meta"; // other JSON by name
+const RESTORE_POINTS: &str = "restore_points"; // restore point JSON by timestamp, since version 2
+const DECKS: &str = "decks";
+const ORDER: &str = "order"; // card ids in the order the cards are listed
+const ORIGIN: &str = "origin"; // where the first cards came from
+const REVISION: &str = "revision"; // counts saves, so that saves from other tabs are noticed
+const SCHEMA_VERSION_KEY: &str = "schema_version"; // of the cards, missing before there was one
+const APP_VERSION: &str = "app_version"; // that last saved
+const SETTINGS: &str = "settings";
+const UNREADABLE: &str = "unreadable"; // a backup of data that was replaced because it could not be read
+
+/// Records to put and delete in one transaction, by object store and key
+#[derive(Default)]
+struct Writes {
+    puts: Vec<(&'static str, JsValue, String)>,
+    deletes: Vec<(&'static str, JsValue)>,
+}
+
+fn js_error(e: JsValue) -> anyhow::Error {
+    anyhow!("IndexedDB: {e:?}")
+}
+
+fn card_key(id: CardId) -> JsValue {
+    JsValue::from_f64(id as f64)
+}
+
+/// Wait for a request's result, watching for it from now on.
+fn finished(request: &IdbRequest) -> impl Future<Output = Result<JsValue>> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let done = request.clone();
+        let callback = Closure::once_into_js(move |_: Event| {
+            let _ = match done.error() {
+                Ok(Some(error)) => reject.call1(&JsValue::NULL, &error),
+                _ => resolve.call1(&JsValue::NULL, &done.result().unwrap_or(JsValue::UNDEFINED)),
+            };
+        });
+        request.set_onsuccess(Some(callback.unchecked_ref()));
+        request.set_onerror(Some(callback.unchecked_ref()));
+    });
+    async move { JsFuture::from(promise).await.map_err(js_error) }
+}
+
+/// Wait for a transaction to commit, watching for it from now on.
+fn committed(tx: &IdbTransaction) -> impl Future<Output = Result<()>> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let ended = tx.clone();
+        let callback = Closure::once_into_js(move |event: Event| {
+            let _ = if event.type_() == "complete" {
+                resolve.call0(&JsValue::NULL)
+            } else {
+                let error = ended
+                    .error()
+                    .map_or_else(|| JsValue::from_str("transaction aborted"), JsValue::from);
+                reject.call1(&JsValue::NULL, &error)
+            };
+        });
+        tx.set_oncomplete(Some(callback.unchecked_ref()));
+        tx.set_onabort(Some(callback.unchecked_ref()));
+    });
+    async move { JsFuture::from(promise).await.map(|_| ()).map_err(js_error) }
+}
+
+fn get(tx: &IdbTransaction, key: &str) -> Result<IdbRequest> {
+    tx.object_store(META)
+        .and_then(|store| store.get(&key.into()))
+        .map_err(js_error)
+}
+
+fn get_all_keys(tx: &IdbTransaction, store: &str) -> Result<IdbRequest> {
+    tx.object_store(store)
+        .and_then(|store| store.get_all_keys())
+        .map_err(js_error)
+}
+
+fn get_all(tx: &IdbTransaction, store: &str) -> Result<IdbRequest> {
+    tx.object_store(store)
+        .and_then(|store| store.get_all())
+        .map_err(js_error)
+}
+
+fn parse<T: DeserializeOwned>(value: &JsValue) -> Result<T> {
+    let json = value.as_string().context("stored record is not text")?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Parse a record that may not have been saved yet.
+fn parse_or_default<T: DeserializeOwned + Default>(value: &JsValue) -> Result<T> {
+    if value.is_undefined() {
+        Ok(T::default())
+    } else {
+        parse(value)
+    }
+}
+
+/// A record as the JSON text it was stored as
+fn record_text(value: &JsValue) -> String {
+    value
+        .as_string()
+        .or_else(|| js_sys::JSON::stringify(value).ok().map(String::from))
+        .unwrap_or_default()
+}
+
+/// Records as a JSON array, one per line
+fn array_text(records: impl Iterator<Item = String>) -> String {
+    format!("[\n{}\n]", records.collect::<Vec<_>>().join(",\n"))
+}
+
+fn apply(tx: &IdbTransaction, writes: &Writes, revision: u64) -> Result<(), JsValue> {
+    for (store, key) in &writes.deletes {
+        tx.object_store(store)?.delete(key)?;
+    }
+    for (store, key, json) in &writes.puts {
+        tx.object_store(store)?
+            .put_with_key(&JsValue::from_str(json), key)?;
+    }
+    tx.object_store(META)?
+        .put_with_key(&revision.to_string().into(), &REVISION.into())?;
+    Ok(())
+}
+
+/// What is in the database, as of this tab's last load or save
+#[derive(Clone, Default)]
+struct Snapshot {
+    settings: Settings,
+    cards: HashMap<CardId, Card>,
+    decks: Vec<String>,
+    order: Vec<CardId>,
+    reviews: Vec<Review>,
+    revision: u64,
+}
+
+/// What a save changes, to be made in the database and then in the snapshot
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Update {
+    replace: bool, // everything, instead of changing what is there
+    settings: Option<Settings>,
+    cards: Vec<Card>,           // new or changed
+    order: Option<Vec<CardId>>, // and the cards that are no longer in it are deleted
+    decks: Option<Vec<String>>,
+    reviews_kept: usize, // the rest are taken back
+    reviews_added: Vec<Review>,
+}
+
+impl Update {
+    /// An update that puts every record, whatever is stored,
+    /// so that none of the records it replaces are left behind
+    fn replacing(saved: &Saved) -> Self {
+        Update {
+            replace: true,
+            settings: Some(saved.settings.clone()),
+            cards: saved.cards.clone(),
+            order: Some(saved.cards.iter().map(|c| c.id).collect()),
+            decks: Some(saved.decks.clone()),
+            reviews_kept: 0,
+            reviews_added: saved.reviews.clone(),
+        }
+    }
+
+    /// The records to put and delete, given the snapshot the update was made against
+    fn writes(&self, snapshot: &Snapshot) -> Result<Writes> {
+        let mut writes = Writes::default();
+        if let Some(settings) = &self.settings {
+            let json = serde_json::to_string(settings).context("serializing settings")?;
+            writes.puts.push((META, SETTINGS.into(), json));
+        }
+        for card in self.cards.iter() {
+            let json = serde_json::to_string(card).context("serializing card")?;
+            writes.puts.push((CARDS, card_key(card.id), json));
+        }
+        if let Some(order) = &self.order {
+            let kept = order.iter().collect::<HashSet<_>>();
+            for id in snapshot.order.iter().filter(|id| !kept.contains(id)) {
+                writes.deletes.push((CARDS, card_key(*id)));
+            }
+            let json = serde_json::to_string(order).context("serializing card order")?;
+            writes.puts.push((META, ORDER.into(), json));
+        }
+        if let Some(decks) = &self.decks {
+            let json = serde_json::to_string(decks).context("serializing decks")?;
+            writes.puts.push((META, DECKS.into(), json));
+        }
+        if self.reviews_kept < snapshot.reviews.len() {
+            let rest =
+                IdbKeyRange::lower_bound(&(self.reviews_kept as f64).into()).map_err(js_error)?;
+            writes.deletes.push((REVIEWS, rest.into()));
+        }
+        for (i, review) in self.reviews_added.iter().enumerate() {
+            let json = serde_json::to_string(review).context("serializing review")?;
+            let key = ((self.reviews_kept + i) as f64).into();
+            writes.puts.push((REVIEWS, key, json));
+        }
+        Ok(writes)
+    }
+}
+
+impl Snapshot {
+    /// Take what was loaded as saved, except for cards saved at an older schema version,
+    /// which are saved again on the next save.
+    fn reset(&mut self, saved: &Saved, revision: u64, schema_version: u32) {
+        self.settings = saved.settings.clone();
+        self.cards = if schema_version == SCHEMA_VERSION {
+            saved.cards.iter().map(|c| (c.id, c.clone())).collect()
+        } else {
+            HashMap::new()
+        };
+        self.decks = saved.decks.clone();
+        self.order = saved.cards.iter().map(|c| c.id).collect();
+        self.reviews = saved.reviews.clone();
+        self.revision = revision;
+    }
+
+    fn saved(&self) -> Saved {
+        Saved {
+            settings: self.settings.clone(),
+            cards: self
+                .order
+                .iter()
+                .filter_map(|id| self.cards.get(id))
+                .cloned()
+                .collect(),
+            decks: self.decks.clone(),
+            reviews: self.reviews.clone(),
+        }
+    }
+
+    /// What differs from the snapshot
+    fn update(
+        &self,
+        settings: &Settings,
+        cards: &[Card],
+        decks: &[String],
+        reviews: &[Review],
+    ) -> Update {
+        let order = cards.iter().map(|c| c.id).collect::<Vec<_>>();
+        // the log only grows, unless something is taken back
+        let reviews_kept = self
+            .reviews
+            .iter()
+            .zip(reviews)
+            .take_while(|(a, b)| a == b)
+            .count();
+        Update {
+            replace: false,
+            settings: Some(settings).filter(|s| **s != self.settings).cloned(),
+            cards: cards
+                .iter()
+                .filter(|card| self.cards.get(&card.id) != Some(card))
+                .cloned()
+                .collect(),
+            order: Some(order).filter(|order| *order != self.order),
+            decks: Some(decks).filter(|d| *d != self.decks).map(|d| d.to_vec()),
+            reviews_kept,
+            reviews_added: reviews[reviews_kept..].to_vec(),
+        }
+    }
+
+    fn apply(&mut self, update: &Update, revision: u64) {
+        if update.replace {
+            *self = Snapshot::default();
+        }
+        if let Some(settings) = &update.settings {
+            self.settings = settings.clone();
+        }
+        for card in update.cards.iter() {
+            self.cards.insert(card.id, card.clone());
+        }
+        if let Some(order) = &update.order {
+            let kept = order.iter().collect::<HashSet<_>>();
+            self.cards.retain(|id, _| kept.contains(id));
+            self.order = order.clone();
+        }
+        if let Some(decks) = &update.decks {
+            self.decks = decks.clone();
+        }
+        self.reviews.truncate(update.reviews_kept);
+        self.reviews.extend(update.reviews_added.iter().cloned());
+        self.revision = revision;
+    }
+}
+
+/// What is in the database, and what will be once the saves in progress are made
+#[derive(Default)]
+struct Snapshots {
+    saved: Snapshot,
+    latest: Snapshot, // which saves are made against, so that they can follow each other
+}
+
+impl Snapshots {
+    fn reset(&mut self, saved: &Saved, revision: u64, schema_version: u32) {
+        self.saved.reset(saved, revision, schema_version);
+        self.latest = self.saved.clone();
+    }
+
+    /// Take an update as made for the saves that follow it,
+    /// returning the revision it expects and the one it makes.
+    fn start(&mut self, update: &Update) -> (u64, u64) {
+        let expected = self.latest.revision;
+        self.latest.apply(update, expected + 1);
+        (expected, expected + 1)
+    }
+
+    fn finish(&mut self, update: &Update, revision: u64, result: &Result<()>) {
+        match result {
+            Ok(()) => self.saved.apply(update, revision),
+            // saves already started after this one will fail too
+            Err(_) => self.latest = self.saved.clone(),
+        }
+    }
+}
+
+pub struct IdbStore {
+    db: IdbDatabase,
+    channel: Option<BroadcastChannel>, // tells other tabs about saves, where supported
+    on_message: Option<Closure<dyn Fn(MessageEvent)>>,
+    snapshots: Rc<RefCell<Snapshots>>,
+}
+
+fn transaction(db: &IdbDatabase, mode: IdbTransactionMode) -> Result<IdbTransaction> {
+    let names = Array::of3(&CARDS.into(), &REVIEWS.into(), &META.into());
+    db.transaction_with_str_sequence_and_mode(&names, mode)
+        .map_err(js_error)
+}
+
+/// Everything saved as JSON text, the revision it was saved at,
+/// and the schema version of the cards, or None if nothing has been saved yet
+async fn read(db: &IdbDatabase) -> Result<Option<(Raw, u64, u32)>> {
+    let tx = transaction(db, IdbTransactionMode::Readonly)?;
+    let origin = finished(&get(&tx, ORIGIN)?);
+    let card_ids = finished(&get_all_keys(&tx, CARDS)?);
+    let cards = finished(&get_all(&tx, CARDS)?);
+    let reviews = finished(&get_all(&tx, REVIEWS)?);
+    let decks = finished(&get(&tx, DECKS)?);
+    let order = finished(&get(&tx, ORDER)?);
+    let revision = finished(&get(&tx, REVISION)?);
+    let schema_version = finished(&get(&tx, SCHEMA_VERSION_KEY)?);
+    let app_version = finished(&get(&tx, APP_VERSION)?);
+    let settings = finished(&get(&tx, SETTINGS)?);
+    if origin.await?.is_undefined() {
+        return Ok(None);
+    }
+    // the order only affects how cards are listed, so it need not be readable
+    let order: Vec<CardId> = parse_or_default(&order.await?).unwrap_or_default();
+    let position = order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i))
+        .collect::<HashMap<_, _>>();
+    let ids = Array::from(&card_ids.await?)
+        .iter()
+        .map(|id| id.as_f64().unwrap_or_default() as CardId)
+        .collect::<Vec<_>>();
+    let mut cards = ids
+        .into_iter()
+        .zip(Array::from(&cards.await?).iter().map(|v| record_text(&v)))
+        .collect::<Vec<_>>();
+    cards.sort_by_key(|(id, _)| position.get(id).copied().unwrap_or(usize::MAX));
+    let text_or = |value: JsValue, default: &str| {
+        if value.is_undefined() {
+            default.to_owned()
+        } else {
+            record_text(&value)
+        }
+    };
+    let version_text = text_or(schema_version.await?, &LEGACY_VERSION.to_string());
+    let raw = Raw {
+        cards: schema::envelope_text(
+            &version_text,
+            &text_or(app_version.await?, "\"\""),
+            &text_or(settings.await?, "{}"),
+            &array_text(cards.into_iter().map(|(_, card)| card)),
+        ),
+        decks: text_or(decks.await?, "[]"),
+        reviews: array_text(Array::from(&reviews.await?).iter().map(|v| record_text(&v))),
+    };
+    let revision = parse_or_default(&revision.await?).context("parsing stored revision")?;
+    // an unreadable version shows up when the cards are parsed
+    let schema_version = version_text.parse().unwrap_or_default();
+    Ok(Some((raw, revision, schema_version)))
+}
+
+impl IdbStore {
+    pub async fn open() -> Result<Self> {
+        let factory = web_sys::window()
+            .context("no window")?
+            .indexed_db()
+            .map_err(js_error)?
+            .context("no IndexedDB in this browser")?;
+        let request = factory
+            .open_with_u32(DB_NAME, DB_VERSION)
+            .map_err(js_error)?;
+        let upgrading = request.clone();
+        let on_upgrade = Closure::once_into_js(move |_: Event| {
+            if let Ok(db) = upgrading.result() {
+                let db = db.unchecked_into::<IdbDatabase>();
+                // stores made for an earlier version are already there
+                for name in [CARDS, REVIEWS, META, RESTORE_POINTS] {
+                    let _ = db.create_object_store(name);
+                }
+            }
+        });
+        request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+        let db = finished(&request).await?.unchecked_into::<IdbDatabase>();
+        Ok(Self {
+            db,
+            channel: BroadcastChannel::new(DB_NAME).ok(),
+            on_message: None,
+            snapshots: Rc::default(),
+        })
+    }
+
+    /// Everything saved, or None if nothing has been saved yet
+    pub async fn load(&mut self) -> Result<Option<Loaded>> {
+        let Some((raw, revision, schema_version)) = read(&self.db).await? else {
+            return Ok(None);
+        };
+        let loaded = raw.parse();
+        let mut snapshots = self.snapshots.borrow_mut();
+        match &loaded {
+            Ok(saved) => snapshots.reset(saved, revision, schema_version),
+            Err(_) => {
+                snapshots.saved.revision = revision;
+                snapshots.latest.revision = revision;
+            }
+        }
+        Ok(Some(loaded))
+    }
+
+    /// Save the first cards, noting where they came from.
+    pub fn initialize(&mut self, saved: &Saved, origin: &str) -> Saving {
+        let update = self.snapshots.borrow().latest.update(
+            &saved.settings,
+            &saved.cards,
+            &saved.decks,
+            &saved.reviews,
+        );
+        let writes = self.writes(&update).and_then(|mut writes| {
+            let origin = serde_json::to_string(origin)?;
+            writes.puts.push((META, ORIGIN.into(), origin));
+            Ok(writes)
+        });
+        match writes {
+            Ok(writes) => self.commit(update, writes),
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+
+    fn writes(&self, update: &Update) -> Result<Writes> {
+        update.writes(&self.snapshots.borrow().latest)
+    }
+
+    /// Writes that replace every record, keeping the unreadable text under its own key
+    fn replacement(saved: &Saved, unreadable: &Raw) -> Result<(Update, Writes)> {
+        let backup = serde_json::to_string(unreadable).context("serializing backup")?;
+        let everything = IdbKeyRange::lower_bound(&0.into()).map_err(js_error)?;
+        let update = Update::replacing(saved);
+        let mut writes = update.writes(&Snapshot::default())?;
+        writes.deletes.push((CARDS, everything.clone().into()));
+        writes.deletes.push((REVIEWS, everything.into()));
+        writes.puts.push((META, UNREADABLE.into(), backup));
+        Ok((update, writes))
+    }
+
+    /// Make the writes in one transaction, unless another tab has saved
+    /// since this one last loaded or saved, and then tell the other tabs.
+    ///
+    /// The snapshot of what is saved moves on only once the transaction has committed,
+    /// so that a failed save leaves it as the base for merging with the other tab's save.
+    fn commit(&mut self, update: Update, mut writes: Writes) -> Saving {
+        // whatever the records were saved at, they are current after this
+        let app_version = serde_json::Value::from(schema::APP_VERSION).to_string();
+        writes.puts.push((META, APP_VERSION.into(), app_version));
+        let version = SCHEMA_VERSION.to_string();
+        writes.puts.push((META, SCHEMA_VERSION_KEY.into(), version));
+        let (expected, revision) = self.snapshots.borrow_mut().start(&update);
+        let snapshots = self.snapshots.clone();
+        let finish = move |result: Result<()>| {
+            snapshots.borrow_mut().finish(&update, revision, &result);
+            result
+        };
+        let tx = match transaction(&self.db, IdbTransactionMode::Readwrite) {
+            Ok(tx) => tx,
+            Err(e) => return Box::pin(ready(finish(Err(e)))),
+        };
+        let check = match get(&tx, REVISION) {
+            Ok(check) => check,
+            Err(e) => return Box::pin(ready(finish(Err(e)))),
+        };
+        let done = committed(&tx);
+        let failure = Rc::new(RefCell::new(None));
+        let failed = failure.clone();
+        let checked = check.clone();
+        // the writes must be made while the transaction is still active
+        let on_check = Closure::once_into_js(move |_: Event| {
+            let stored = checked
+                .result()
+                .map_err(js_error)
+                .and_then(|v| parse_or_default::<u64>(&v));
+            let error = match stored {
+                Ok(stored) if stored == expected => {
+                    apply(&tx, &writes, revision).err().map(js_error)
+                }
+                Ok(_) => Some(ChangedElsewhere.into()),
+                Err(e) => Some(e),
+            };
+            if let Some(e) = error {
+                *failed.borrow_mut() = Some(e);
+                let _ = tx.abort();
+            }
+        });
+        check.set_onsuccess(Some(on_check.unchecked_ref()));
+        let channel = self.channel.clone();
+        Box::pin(async move {
+            finish(
+                done.await
+                    .map_err(|e| failure.borrow_mut().take().unwrap_or(e)),
+            )?;
+            if let Some(channel) = channel {
+                channel
+                    .post_message(&JsValue::from_f64(revision as f64))
+                    .map_err(js_error)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Store for IdbStore {
+    fn save(
+        &mut self,
+        settings: &Settings,
+        cards: &[Card],
+        decks: &[String],
+        reviews: &[Review],
+    ) -> Saving {
+        let update = self
+            .snapshots
+            .borrow()
+            .latest
+            .update(settings, cards, decks, reviews);
+        match self.writes(&update) {
+            Ok(writes) if writes.puts.is_empty() && writes.deletes.is_empty() => {
+                Box::pin(ready(Ok(())))
+            }
+            Ok(writes) => self.commit(update, writes),
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+
+    fn reload(&mut self) -> Reloading {
+        // only what has been committed, so that this tab's failed saves are merged as its own
+        let base = self.snapshots.borrow().saved.saved();
+        let db = self.db.clone();
+        let snapshots = self.snapshots.clone();
+        Box::pin(async move {
+            let (raw, revision, schema_version) =
+                read(&db).await?.context("stored cards have gone")?;
+            let theirs = raw.parse()?;
+            snapshots
+                .borrow_mut()
+                .reset(&theirs, revision, schema_version);
+            Ok(Reloaded { base, theirs })
+        })
+    }
+
+    fn replace(&mut self, saved: &Saved, unreadable: &Raw) -> Saving {
+        match Self::replacement(saved, unreadable) {
+            Ok((update, writes)) => self.commit(update, writes),
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+
+    /// Restore points are kept apart from the cards, and another tab keeping one is not a save.
+    fn keep(&mut self, point: &RestorePoint) -> Saving {
+        let db = self.db.clone();
+        let json = serde_json::to_string(point).context("serializing restore point");
+        let key = JsValue::from_f64(point.timestamp);
+        Box::pin(async move {
+            let json = json?;
+            let tx = db
+                .transaction_with_str_and_mode(RESTORE_POINTS, IdbTransactionMode::Readwrite)
+                .map_err(js_error)?;
+            let store = tx.object_store(RESTORE_POINTS).map_err(js_error)?;
+            let done = committed(&tx);
+            store
+                .put_with_key(&JsValue::from_str(&json), &key)
+                .map_err(js_error)?;
+            let keys = finished(&store.get_all_keys().map_err(js_error)?);
+            done.await?;
+            // keys come in order, so the oldest points are first
+            let keys = Array::from(&keys.await?);
+            let excess = (keys.length() as usize).saturating_sub(restore::KEPT);
+            if excess > 0 {
+                let tx = db
+                    .transaction_with_str_and_mode(RESTORE_POINTS, IdbTransactionMode::Readwrite)
+                    .map_err(js_error)?;
+                let oldest =
+                    IdbKeyRange::upper_bound(&keys.get(excess as u32 - 1)).map_err(js_error)?;
+                let done = committed(&tx);
+                tx.object_store(RESTORE_POINTS)
+                    .and_then(|store| store.delete(&oldest))
+                    .map_err(js_error)?;
+                done.await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn restore_points(&mut self) -> Listing {
+        let db = self.db.clone();
+        Box::pin(async move {
+            let tx = db.transaction_with_str(RESTORE_POINTS).map_err(js_error)?;
+            let points = finished(&get_all(&tx, RESTORE_POINTS)?).await?;
+            Array::from(&points)
+                .iter()
+                .map(|point| parse(&point).context("parsing restore point"))
+                .collect()
+        })
+    }
+
+    /// Other tabs announce their saves on a broadcast channel.
+    fn watch(&mut self, on_change: Box<dyn Fn()>) {
+        let Some(channel) = &self.channel else {
+            return;
+        };
+        let on_message = Closure::wrap(
+            Box::new(move |_: MessageEvent| on_change()) as Box<dyn Fn(MessageEvent)>
+        );
+        channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        self.on_message = Some(on_message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use memoradical_core::reviews::Grade;
+    use memoradical_core::sync;
+
+    use crate::storage::Part;
+
+    use super::*;
+
+    fn loaded() -> Saved {
+        let cards = (1..=3)
+            .map(|id| Card {
+                id,
+                ..Card::new(&format!("front {id}"), &format!("back {id}"))
+            })
+            .collect();
+        Saved {
+            cards,
+            decks: vec![memoradical_core::DEFAULT_DECK.to_owned()],
+            ..Saved::default()
+        }
+    }
+
+    fn grade(saved: &mut Saved, id: CardId, grade: Grade) {
+        let review = Review {
+            card: id,
+            reverse: false,
+            grade,
+            timestamp: saved.reviews.len() as f64,
+            latency_ms: 1000.0,
+            confused_with: None,
+        };
+        let card = saved.cards.iter_mut().find(|c| c.id == id).unwrap();
+        card.apply_review(&review);
+        saved.reviews.push(review);
+    }
+
+    fn update(snapshot: &Snapshot, saved: &Saved) -> Update {
+        snapshot.update(&saved.settings, &saved.cards, &saved.decks, &saved.reviews)
+    }
+
+    #[test]
+    fn a_failed_save_is_merged_as_this_tabs_own_after_reloading() {
+        let mut snapshots = Snapshots::default();
+        snapshots.reset(&loaded(), 7, SCHEMA_VERSION);
+        let mut ours = loaded();
+        grade(&mut ours, 1, Grade::Good);
+        let mut theirs = loaded();
+        grade(&mut theirs, 2, Grade::Again);
+        // the other tab saves first, so this one's save fails
+        let ours_update = update(&snapshots.latest, &ours);
+        let (expected, revision) = snapshots.start(&ours_update);
+        assert_eq!((expected, revision), (7, 8));
+        let failed = Err(ChangedElsewhere.into());
+        snapshots.finish(&ours_update, revision, &failed);
+
+        let base = snapshots.saved.saved();
+        assert_eq!(base.cards, loaded().cards);
+        assert!(base.reviews.is_empty());
+        let (cards, conflicts) = sync::merge_cards(&base.cards, &ours.cards, &theirs.cards);
+        assert!(conflicts.is_empty());
+        let counts = cards
+            .iter()
+            .map(|c| c.hits_misses(false))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![(1, 0), (0, 1), (0, 0)]);
+        let reviews = sync::merge_reviews(&base.reviews, &ours.reviews, &theirs.reviews);
+        let graded = reviews.iter().map(|r| r.card).collect::<Vec<_>>();
+        assert_eq!(graded, vec![2, 1]);
+
+        // the merge is then saved against what the other tab saved
+        snapshots.reset(&theirs, 8, SCHEMA_VERSION);
+        let merged = Saved {
+            cards,
+            reviews,
+            ..theirs.clone()
+        };
+        let merge_update = update(&snapshots.latest, &merged);
+        assert_eq!(snapshots.start(&merge_update), (8, 9));
+        let ids = merge_update.cards.iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1]);
+        assert_eq!(merge_update.reviews_kept, 1);
+        assert_eq!(merge_update.reviews_added.len(), 1);
+    }
+
+    #[test]
+    fn saves_in_progress_follow_each_other() {
+        let mut snapshots = Snapshots::default();
+        snapshots.reset(&loaded(), 1, SCHEMA_VERSION);
+        let mut saved = loaded();
+        grade(&mut saved, 1, Grade::Good);
+        let first = update(&snapshots.latest, &saved);
+        let (_, first_revision) = snapshots.start(&first);
+        grade(&mut saved, 3, Grade::Hard);
+        let second = update(&snapshots.latest, &saved);
+        assert_eq!(second.cards.len(), 1);
+        assert_eq!(second.reviews_kept, 1);
+        let (expected, second_revision) = snapshots.start(&second);
+        assert_eq!(expected, first_revision);
+
+        snapshots.finish(&first, first_revision, &Ok(()));
+        assert_eq!(snapshots.saved.reviews.len(), 1);
+        snapshots.finish(&second, second_revision, &Ok(()));
+        assert_eq!(snapshots.saved.saved().cards, saved.cards);
+        assert_eq!(snapshots.saved.revision, 3);
+        assert!(update(&snapshots.latest, &saved).cards.is_empty());
+    }
+
+    #[test]
+    fn a_replacement_forgets_what_was_there() {
+        let mut snapshot = Snapshot::default();
+        snapshot.reset(&loaded(), 4, SCHEMA_VERSION);
+        let mut replacement = loaded();
+        replacement.cards.truncate(1);
+        snapshot.apply(&Update::replacing(&replacement), 5);
+        assert_eq!(snapshot.saved().cards, replacement.cards);
+        assert_eq!(snapshot.cards.len(), 1);
+        assert_eq!(snapshot.revision, 5);
+    }
+
+    #[test]
+    fn recovery_puts_every_record_over_unreadable_ones() {
+        let raw = Raw {
+            cards: "[]".to_owned(),
+            decks: "[\"Spanish\", 3]".to_owned(),
+            reviews: "[]".to_owned(),
+        };
+        assert_eq!(raw.parse().unwrap_err().part, Part::Decks);
+        // nothing was taken as saved, so defaults would not differ from the snapshot
+        let snapshot = Snapshot::default();
+        let reset = Saved::default();
+        assert_eq!(update(&snapshot, &reset).decks, None);
+        let update = Update::replacing(&reset);
+        assert_eq!(update.decks, Some(vec![]));
+        assert_eq!(update.settings, Some(Settings::default()));
+        assert_eq!(update.order, Some(vec![]));
+
+        let (salvaged, _) = raw.salvage();
+        let update = Update::replacing(&salvaged);
+        assert_eq!(update.decks, Some(vec!["Spanish".to_owned()]));
+    }
+}
@@ -1,19 +1,72 @@
 use anyhow::{anyhow, Context, Result};
 use gloo_storage::{LocalStorage, Storage};
 use sha2::{Digest, Sha256};
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::StorageEvent;
 
-pub struct LocalStore {
+use memoradical_core::restore::{self, RestorePoint};
+use memoradical_core::reviews::Review;
+use memoradical_core::schema::{Envelope, Settings};
+use memoradical_core::Card;
+
+use crate::storage::{
+    ChangedElsewhere, Listing, Raw, Reloaded, Reloading, Saved, Saving, Store, Unreadable,
+};
+
+pub const STORAGE_KEY_CARDS: &str = "net.noserose.memoradical:cards";
+const STORAGE_KEY_DECKS: &str = "net.noserose.memoradical:decks";
+const STORAGE_KEY_REVIEWS: &str = "net.noserose.memoradical:reviews";
+const STORAGE_KEY_RESTORE_POINTS: &str = "net.noserose.memoradical:restore-points";
+const BACKUP_SUFFIX: &str = ".unreadable";
+const QUOTA: usize = 5_000_000; // characters, which is what browsers give each site
+const LIVE_HEADROOM: usize = 2; // times the size of the cards, decks, and log
+
+/// The length of text as local storage holds it, encoded as a JSON string
+fn stored_len(text: &str) -> usize {
+    serde_json::to_string(text).map_or(text.len(), |json| json.len())
+}
+
+/// How much room restore points may take, leaving the rest for the cards, decks, and log
+/// to grow into, since running out of room for those stops the app
+fn restore_budget(live_len: usize) -> usize {
+    QUOTA.saturating_sub(LIVE_HEADROOM * live_len)
+}
+
+/// The JSON of as many of the newest restore points as fit in the budget,
+/// and how many of them that is
+fn newest_that_fit(points: &[RestorePoint], budget: usize) -> Result<(usize, String)> {
+    let mut start = 0;
+    loop {
+        let json = serde_json::to_string(&points[start..]).context("serializing restore points")?;
+        if start == points.len() || stored_len(&json) <= budget {
+            return Ok((points.len() - start, json));
+        }
+        start += 1;
+    }
+}
+
+/// One key in local storage, which refuses to save over changes made by another tab
+struct Entry {
     checksum: String,
     key: String,
     value: String,
 }
 
-impl LocalStore {
-    pub fn new(key: &str, default_data: &str) -> Result<Self> {
+/// The text stored under a key, which is normally itself encoded as a JSON string,
+/// but is taken as it is if it has been mangled
+fn stored(key: &str) -> Result<Option<String>> {
+    let text = LocalStorage::raw()
+        .get_item(key)
+        .map_err(|e| anyhow!("reading local storage: {e:?}"))?;
+    Ok(text.map(|text| serde_json::from_str(&text).unwrap_or(text)))
+}
+
+impl Entry {
+    fn new(key: &str, default_data: &str) -> Result<Self> {
         let key = key.to_string();
-        let data = match LocalStorage::get(&key) {
-            Ok(data) => data,
-            Err(_) => {
+        let data = match stored(&key)? {
+            Some(data) => data,
+            None => {
                 LocalStorage::set(&key, default_data.to_string())?;
                 default_data.to_string()
             }
@@ -25,29 +78,271 @@ impl LocalStore {
             value: data,
         })
     }
-    pub fn value(&self) -> String {
-        self.value.clone()
-    }
     fn hash(value: &str) -> String {
         let mut hasher = Sha256::new();
 
         hasher.update(value.as_bytes());
         format!("{:x}", hasher.finalize())
     }
-    pub fn save(&mut self, value: &str) -> Result<()> {
-        let data: String = LocalStorage::get(&self.key)?;
-        let stored_checksum = Self::hash(&data);
-        if stored_checksum == self.checksum {
+    fn is_current(&self) -> Result<bool> {
+        let data = stored(&self.key)?.unwrap_or_default();
+        Ok(Self::hash(&data) == self.checksum)
+    }
+    fn save(&mut self, value: &str) -> Result<()> {
+        if self.is_current()? {
             LocalStorage::set(&self.key, value.to_string()).context("storing local data")?;
             self.value = value.to_string();
             self.checksum = Self::hash(value);
             Ok(())
         } else {
-            Err(anyhow!(
-                "local storage has been changed since last loaded (from:{} to:{})",
-                self.checksum,
-                stored_checksum
-            ))
+            Err(ChangedElsewhere.into())
         }
     }
+    fn reload(&mut self) -> Result<()> {
+        self.value = stored(&self.key)?.unwrap_or_default();
+        self.checksum = Self::hash(&self.value);
+        Ok(())
+    }
+}
+
+/// Settings and cards in an envelope, decks, and the review log
+/// as three JSON strings in local storage,
+/// each rewritten whole when it changes
+pub struct LocalStore {
+    cards: Entry,
+    decks: Entry,
+    reviews: Entry,
+    reviews_stored: usize,
+    on_storage: Option<Closure<dyn Fn(StorageEvent)>>,
+}
+
+impl LocalStore {
+    pub fn new(default_cards: &str) -> Result<Self> {
+        let reviews = Entry::new(STORAGE_KEY_REVIEWS, "[]")?;
+        let reviews_stored =
+            serde_json::from_str::<Vec<Review>>(&reviews.value).map_or(0, |reviews| reviews.len());
+        Ok(Self {
+            cards: Entry::new(STORAGE_KEY_CARDS, default_cards)?,
+            decks: Entry::new(STORAGE_KEY_DECKS, "[]")?,
+            reviews,
+            reviews_stored,
+            on_storage: None,
+        })
+    }
+
+    /// Whether local storage holds cards saved by an earlier visit
+    pub fn exists() -> bool {
+        LocalStorage::raw()
+            .get_item(STORAGE_KEY_CARDS)
+            .ok()
+            .flatten()
+            .is_some()
+    }
+
+    /// Forget the cards, decks, and review log, once they have been moved elsewhere.
+    pub fn remove() {
+        for key in [STORAGE_KEY_CARDS, STORAGE_KEY_DECKS, STORAGE_KEY_REVIEWS] {
+            LocalStorage::delete(key);
+        }
+    }
+
+    /// Forget the restore points, once they have been moved elsewhere.
+    pub fn remove_restore_points() {
+        LocalStorage::delete(STORAGE_KEY_RESTORE_POINTS);
+    }
+
+    fn raw(&self) -> Raw {
+        Raw {
+            cards: self.cards.value.clone(),
+            decks: self.decks.value.clone(),
+            reviews: self.reviews.value.clone(),
+        }
+    }
+
+    pub fn load(&self) -> Result<Saved, Unreadable> {
+        self.raw().parse()
+    }
+
+    fn save_all(
+        &mut self,
+        settings: &Settings,
+        cards: &[Card],
+        decks: &[String],
+        reviews: &[Review],
+    ) -> Result<()> {
+        // check every key first, so as not to save some of them and then stop
+        for entry in [&self.cards, &self.decks, &self.reviews] {
+            if !entry.is_current()? {
+                return Err(ChangedElsewhere.into());
+            }
+        }
+        let cards =
+            serde_json::to_string(&Envelope::new(settings, cards)).context("serializing cards")?;
+        let decks = serde_json::to_string(decks).context("serializing decks")?;
+        let log = if reviews.len() != self.reviews_stored {
+            Some(serde_json::to_string(reviews).context("serializing review log")?)
+        } else {
+            None
+        };
+        let log_len = stored_len(log.as_ref().unwrap_or(&self.reviews.value));
+        Self::make_room(stored_len(&cards) + stored_len(&decks) + log_len);
+        self.cards.save(&cards).context("storing existing cards")?;
+        self.decks.save(&decks).context("storing decks")?;
+        if let Some(log) = log {
+            self.reviews.save(&log).context("storing review log")?;
+            self.reviews_stored = reviews.len();
+        }
+        Ok(())
+    }
+
+    fn replace_all(&mut self, saved: &Saved, unreadable: &Raw) -> Result<()> {
+        for (entry, text) in [
+            (&self.cards, &unreadable.cards),
+            (&self.decks, &unreadable.decks),
+            (&self.reviews, &unreadable.reviews),
+        ] {
+            let key = format!("{}{BACKUP_SUFFIX}", entry.key);
+            LocalStorage::set(&key, text).context("keeping a backup of unreadable data")?;
+        }
+        self.reviews_stored = usize::MAX; // so that the log is saved too
+        self.save_all(&saved.settings, &saved.cards, &saved.decks, &saved.reviews)
+    }
+
+    fn reload_all(&mut self) -> Result<Reloaded> {
+        let base = self.load()?;
+        for entry in [&mut self.cards, &mut self.decks, &mut self.reviews] {
+            entry.reload()?;
+        }
+        let theirs = self.load()?;
+        self.reviews_stored = theirs.reviews.len();
+        Ok(Reloaded { base, theirs })
+    }
+
+    /// The restore points, oldest first
+    pub fn read_restore_points() -> Result<Vec<RestorePoint>> {
+        match stored(STORAGE_KEY_RESTORE_POINTS)? {
+            None => Ok(vec![]),
+            Some(json) => serde_json::from_str(&json).context("parsing restore points"),
+        }
+    }
+
+    fn live_len(&self) -> usize {
+        [&self.cards, &self.decks, &self.reviews]
+            .iter()
+            .map(|entry| stored_len(&entry.value))
+            .sum()
+    }
+
+    /// Add the point to the others, forgetting the oldest ones
+    /// until they all fit in their share of local storage.
+    fn keep_point(&self, point: &RestorePoint) -> Result<()> {
+        let mut points = Self::read_restore_points().unwrap_or_default();
+        points.push(point.clone());
+        restore::prune(&mut points);
+        let (n_kept, json) = newest_that_fit(&points, restore_budget(self.live_len()))?;
+        LocalStorage::set(STORAGE_KEY_RESTORE_POINTS, json).context("storing restore point")?;
+        if n_kept == 0 {
+            return Err(anyhow!(
+                "there is no room in local storage for a restore point"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Forget the oldest restore points until what is left fits beside live data
+    /// of the given length, before saving it rather than after running out of room.
+    fn make_room(live_len: usize) {
+        let Ok(points) = Self::read_restore_points() else {
+            return;
+        };
+        let budget = restore_budget(live_len);
+        if let Ok((n_kept, json)) = newest_that_fit(&points, budget) {
+            if n_kept < points.len() {
+                // if this fails, the save will say why
+                let _ = LocalStorage::set(STORAGE_KEY_RESTORE_POINTS, json);
+            }
+        }
+    }
+}
+
+impl Store for LocalStore {
+    fn save(
+        &mut self,
+        settings: &Settings,
+        cards: &[Card],
+        decks: &[String],
+        reviews: &[Review],
+    ) -> Saving {
+        Box::pin(std::future::ready(
+            self.save_all(settings, cards, decks, reviews),
+        ))
+    }
+
+    fn reload(&mut self) -> Reloading {
+        Box::pin(std::future::ready(self.reload_all()))
+    }
+
+    fn replace(&mut self, saved: &Saved, unreadable: &Raw) -> Saving {
+        Box::pin(std::future::ready(self.replace_all(saved, unreadable)))
+    }
+
+    fn keep(&mut self, point: &RestorePoint) -> Saving {
+        Box::pin(std::future::ready(self.keep_point(point)))
+    }
+
+    fn restore_points(&mut self) -> Listing {
+        Box::pin(std::future::ready(Self::read_restore_points()))
+    }
+
+    /// Other tabs' changes to local storage arrive as storage events.
+    fn watch(&mut self, on_change: Box<dyn Fn()>) {
+        let on_storage = Closure::wrap(Box::new(move |event: StorageEvent| {
+            let key = event.key().unwrap_or_default();
+            if [STORAGE_KEY_CARDS, STORAGE_KEY_DECKS, STORAGE_KEY_REVIEWS].contains(&key.as_str()) {
+                on_change();
+            }
+        }) as Box<dyn Fn(StorageEvent)>);
+        if let Some(window) = web_sys::window() {
+            let _ = window
+                .add_event_listener_with_callback("storage", on_storage.as_ref().unchecked_ref());
+        }
+        self.on_storage = Some(on_storage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use memoradical_core::restore::Reason;
+
+    use super::*;
+
+    fn points(n: usize) -> Vec<RestorePoint> {
+        let cards = vec![Card::new("prompt", "response \"quoted\"")];
+        (0..n)
+            .map(|i| {
+                let settings = Settings::default();
+                RestorePoint::new(i as f64, Reason::Daily, &settings, &cards, &[]).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_oldest_points_are_dropped_to_fit_the_budget() {
+        let points = points(5);
+        let (_, all) = newest_that_fit(&points, QUOTA).unwrap();
+        let (_, newest) = newest_that_fit(&points[3..], QUOTA).unwrap();
+        assert_eq!(newest_that_fit(&points, stored_len(&all)).unwrap().0, 5);
+        let (n_kept, json) = newest_that_fit(&points, stored_len(&all) - 1).unwrap();
+        assert_eq!(n_kept, 4);
+        assert!(stored_len(&json) < stored_len(&all));
+        assert_eq!(newest_that_fit(&points, stored_len(&newest)).unwrap().0, 2);
+        assert_eq!(newest_that_fit(&points, 0).unwrap(), (0, "[]".to_owned()));
+    }
+
+    #[test]
+    fn live_data_leaves_less_room_for_restore_points() {
+        assert_eq!(restore_budget(0), QUOTA);
+        assert_eq!(restore_budget(QUOTA / 4), QUOTA / 2);
+        assert_eq!(restore_budget(QUOTA), 0);
+    }
 }
@@ -1,67 +1,151 @@
-use std::cmp::Ordering;
-use std::collections::LinkedList;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, LinkedList};
 use std::fmt;
 use std::string::String;
 
 use anyhow::{anyhow, Context, Result};
 use gloo_console::console_dbg;
 use gloo_file::{
-    callbacks::{read_as_text, FileReader},
+    callbacks::{read_as_bytes, FileReader},
     File,
 };
 use gloo_timers::callback::{Interval, Timeout};
-use rand::distributions::WeightedIndex;
-use rand_distr::{Beta, Distribution};
-use serde::{Deserialize, Serialize};
-use web_sys::{Event, HtmlElement, HtmlInputElement};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{Event, HtmlElement, HtmlInputElement, HtmlSelectElement, KeyboardEvent};
 use yew::prelude::*;
 
-use localstore::LocalStore;
+use memoradical_core::answer::{self, Checked, DiffPart};
+use memoradical_core::delimited::{self, Delimiter, Field, TableImport};
+use memoradical_core::history::{History, Version};
+use memoradical_core::merge::{self, Change, Incoming, Strategies};
+use memoradical_core::restore::{self, Reason, RestorePoint};
+use memoradical_core::reviews::{Grade, Review};
+use memoradical_core::schema::{self, Envelope, Settings};
+use memoradical_core::stats::{Stats, GOODNESS_CRITERION};
+use memoradical_core::sync::{self, Conflict, Side};
+use memoradical_core::tags::TagFilter;
+use memoradical_core::upload::Upload;
+use memoradical_core::validate::Problem;
+use memoradical_core::{
+    anki, assign_missing_decks, assign_missing_ids, quiz, select, tags, validate, Card, CardId,
+    Scheduler, DEFAULT_DECK, DEFAULT_TARGET_RETENTION,
+};
+use storage::{ChangedElsewhere, Loaded, Reloaded, Saved, Store, Unreadable};
 
+mod download;
+mod idbstore;
 mod localstore;
+mod storage;
 
 const COPY_BORDER_FADE_MS: u32 = 50;
-const GOODNESS_CRITERION: f32 = 0.6; // otherwise it's too hard to make up for a few misses
+const IMPORT_PREVIEW_ROWS: usize = 5;
 const ROW_DISPLAY_BREATHER_MS: u32 = 50;
 const ROW_DISPLAY_INITIAL: usize = 50;
-const STORAGE_KEY_CARDS: &str = "net.noserose.memoradical:cards";
 const UPLOAD_ERR_DISPLAY_MS: u32 = 5000;
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
 
 enum Msg {
     AddCard,
     AddMode,
     AddNewCards,
     AllCardsMode,
+    AnkiCountsToggle,
     CancelUpload,
     ChooseMissedToggle,
     ChooseNeglectedToggle,
     ClearCounts(bool),
     CopyCards,
     CopyCardsSuccess,
-    DeleteCard(usize),
+    CreateDeck,
+    DeleteCard(CardId),
+    DeleteDeck(bool),
     DisplayMoreRows,
-    Edit(Option<usize>), // None means self's current card
+    DownloadAnki,
+    DownloadCards,
+    DownloadTable(Delimiter),
+    DownloadUnreadable,
+    Edit(Option<CardId>), // None means self's current card
     FadeCopyBorder,
     Flip,
+    Grade(Grade),
+    GradedModeToggle,
     HelpMode,
     Hit,
-    IngestNewCards(String),
+    ImportCommentsToggle,
+    ImportGoodRecords,
+    ImportHeaderToggle,
+    ImportTable,
+    IngestFile(String, Result<Vec<u8>, String>), // file name and contents
     Miss,
+    MoveCard(CardId, String),
     Next,
     Noop,
     Prev,
+    QuizAnswer(CardId),
+    QuizMode,
+    Redo,
+    Reloaded(Reloaded),
     Render,
+    RenameDeck,
+    ResetStorage,
+    ResolveConflicts(Option<CardId>, Side), // None means all of them
+    Restore(f64),
+    RestoreMode,
+    RestorePoints(Vec<RestorePoint>),
     ReverseModeToggle,
+    SalvageStorage,
+    SelectDeck(Option<String>),
     SetClipboardError(anyhow::Error),
+    SetFatalError(String),
+    SetRestoreError(String),
+    SetImportColumn(usize, Field),
+    SetMergeStrategy(Change, merge::Strategy),
+    SetImportDelimiter(Delimiter),
     SetHelp(String),
+    SetScheduler(Scheduler),
+    SetSessionSeed(Option<u64>),
+    SetTargetRetention(f64),
     SetUploadError(Option<String>),
     StatsMode,
+    StorageChanged,
     StoreCards,
     StoreNewCards,
+    StoreOpened(Box<dyn Store>, Loaded),
     StudyMode,
+    SubmitAnswer,
+    TypedModeToggle,
+    Undo,
+    UpdateDeckNameText(String),
     UpdateNewBackText(String),
     UpdateNewFrontText(String),
+    UpdateNewTagsText(String),
+    UpdateTagFilter(String),
+    UpdateTypedAnswer(String),
     UploadCards(Vec<File>),
+    UseSalvaged,
+}
+
+impl Msg {
+    /// Whether the message can change the cards, decks, or review log in a way that can be undone,
+    /// apart from responses, which are remembered as they are logged
+    fn is_undoable(&self) -> bool {
+        matches!(
+            self,
+            Msg::AddCard
+                | Msg::AddNewCards
+                | Msg::ClearCounts(true)
+                | Msg::CreateDeck
+                | Msg::DeleteCard(_)
+                | Msg::DeleteDeck(true)
+                | Msg::MoveCard(..)
+                | Msg::RenameDeck
+                | Msg::ResolveConflicts(..)
+                | Msg::Restore(_)
+                | Msg::StoreNewCards
+        )
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -70,6 +154,8 @@ enum Mode {
     AllCards,
     Edit,
     Help,
+    Quiz,
+    Restore,
     Stats,
     Study,
 }
@@ -81,38 +167,6 @@ impl fmt::Display for Mode {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct Card {
-    prompt: String,
-    response: String,
-    hits: usize,
-    misses: usize,
-    reverse_hits: Option<usize>,
-    reverse_misses: Option<usize>,
-}
-
-impl Card {
-    fn new(front: &str, back: &str) -> Card {
-        Card {
-            prompt: front.to_string(),
-            response: back.to_string(),
-            hits: 0,
-            misses: 0,
-            reverse_hits: None,
-            reverse_misses: None,
-        }
-    }
-    fn clear_counts(&mut self, reverse_mode: bool) {
-        if reverse_mode {
-            self.reverse_hits = Some(0);
-            self.reverse_misses = Some(0);
-        } else {
-            self.hits = 0;
-            self.misses = 0;
-        }
-    }
-}
-
 #[derive(Clone, Debug, PartialEq)]
 enum Face {
     Prompt,
@@ -128,7 +182,6 @@ impl Face {
     }
 }
 
-
 impl fmt::Display for Face {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let dbg = format!("{:?}", self).to_lowercase();
@@ -136,97 +189,273 @@ impl fmt::Display for Face {
     }
 }
 
-
 struct Model {
+    anki_counts: bool,
     cards: Vec<Card>,
     choose_missed: bool,
     choose_neglected: bool,
     clear_counts_request: bool,
     clipboard_error: Option<String>,
+    conflicts: Vec<Conflict>,
     copy_border_opacity: f32,
     copy_border_fader: Option<Interval>,
-    current_card: Option<usize>,
-    deletion_target: Option<usize>,
-    display_history: LinkedList<usize>,
+    current_card: Option<CardId>,
+    deck_deletion_request: bool,
+    deck_name_text: String,
+    decks: Vec<String>,
+    deletion_target: Option<CardId>,
+    download_error: Option<String>,
+    display_history: LinkedList<CardId>,
     fatal_error: Option<String>,
     focus_node: NodeRef,
+    graded_mode: bool,
     help_html: Option<String>,
     help_node: NodeRef,
+    history: History,
+    last_answer: Option<Checked>,
+    last_daily_point: Option<f64>, // None until the restore points have been listed
+    last_point_kept: f64,          // so that points kept at once get different timestamps
     mode: Mode,
     n_rows_displayed: usize,
     need_key_focus: bool,
-    new_cards: Option<Vec<Card>>,
+    merge_strategies: Strategies,
+    new_cards: Option<Vec<Incoming>>,
     new_front_text: String,
+    new_tags_text: String,
+    quiz_card: Option<CardId>,
+    quiz_choices: Vec<CardId>,
+    quiz_feedback: Option<String>,
     new_back_text: String,
     readers: Vec<FileReader>,
+    reload_pending: bool, // another tab saved during a reload
+    reloading: bool,
     rerender: Option<Timeout>,
+    restore_error: Option<String>,
+    restore_points: Vec<(RestorePoint, Vec<Card>, usize)>, // newest first, with unreadable cards counted
     reverse_mode: bool,
-    local_store: LocalStore,
+    reviews: Vec<Review>,
+    rng: RefCell<StdRng>, // for choosing cards and quiz answers, seeded at the start of a session
+    salvaged: Option<(Saved, Vec<Problem>)>, // what a lenient parse of unreadable data kept
+    scheduler: Scheduler,
+    selected_deck: Option<String>,
+    session_seed: Option<u64>,
+    shown_at: f64,
+    store: Option<Box<dyn Store>>, // None until opened
+    tag_filter: TagFilter,
+    tag_filter_text: String,
+    target_retention: f64,
+    typed_answer: String,
+    typed_mode: bool,
+    undo_keys: Option<Closure<dyn Fn(KeyboardEvent)>>, // listening on the whole window
+    unreadable: Option<Unreadable>, // stored data to recover before anything else
     upload_clearer: Option<Timeout>,
+    upload: Upload,
     upload_error: Option<String>,
     visible_face: Face,
 }
 
-fn mean(x: &[f32]) -> f32 {
-    if x.is_empty() {
-        0.0
-    } else {
-        x.iter().sum::<f32>() / x.len() as f32
-    }
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+fn today_start() -> f64 {
+    let date = js_sys::Date::new_0();
+    date.set_hours(0);
+    date.set_minutes(0);
+    date.set_seconds(0);
+    date.set_milliseconds(0)
+}
+
+/// The time at which today ends, so that anything due before then is due today.
+fn today_cutoff() -> f64 {
+    let date = js_sys::Date::new_0();
+    date.set_hours(24);
+    date.set_minutes(0);
+    date.set_seconds(0);
+    date.set_milliseconds(0)
+}
+
+fn format_time(ms: f64) -> String {
+    let date = js_sys::Date::new(&ms.into());
+    date.to_locale_string("default", &js_sys::Object::new())
+        .into()
 }
 
 impl Model {
     fn change_mode(&mut self, new_mode: Mode) {
-        if new_mode == Mode::Study && self.mode != Mode::Study {
+        if matches!(new_mode, Mode::Study | Mode::Quiz) && self.mode != new_mode {
             self.need_key_focus = true;
         }
         self.clear_counts_request = false;
+        self.deck_deletion_request = false;
         self.n_rows_displayed = ROW_DISPLAY_INITIAL;
+        if new_mode != Mode::Restore {
+            self.restore_points.clear();
+        }
         self.mode = new_mode;
     }
 
-    fn choose_card(&self) -> Option<usize> {
-        if self.cards.is_empty() {
-            return None;
+    fn card(&self, id: CardId) -> Option<&Card> {
+        self.cards.iter().find(|c| c.id == id)
+    }
+
+    fn card_mut(&mut self, id: CardId) -> Option<&mut Card> {
+        self.cards.iter_mut().find(|c| c.id == id)
+    }
+
+    /// The deck for new cards
+    fn current_deck(&self) -> String {
+        self.selected_deck
+            .clone()
+            .unwrap_or_else(|| DEFAULT_DECK.to_owned())
+    }
+
+    /// Make sure every deck holding cards is listed.
+    fn sync_decks(&mut self) {
+        for card in self.cards.iter() {
+            if !self.decks.contains(&card.deck) {
+                self.decks.push(card.deck.clone());
+            }
         }
-        let rng = &mut rand::thread_rng();
-        let mut weights: Vec<_> = if self.choose_missed {
-            self.cards
-                .iter()
-                .map(|card| {
-                    let misses = if self.reverse_mode {
-                        card.reverse_misses.unwrap_or_default()
-                    } else {
-                        card.misses
-                    };
-                    let hits = if self.reverse_mode {
-                        card.reverse_hits.unwrap_or_default()
-                    } else {
-                        card.hits
-                    };
-                    let shape1 = misses + 1;
-                    let shape2 = hits + 1;
-                    Beta::new(shape1 as f64, shape2 as f64).unwrap().sample(rng)
-                })
-                .collect()
+        self.decks.sort();
+        if self
+            .selected_deck
+            .as_ref()
+            .is_some_and(|d| !self.decks.contains(d))
+        {
+            self.selected_deck = None;
+        }
+    }
+
+    fn in_deck(&self, card: &Card) -> bool {
+        self.selected_deck.as_ref().is_none_or(|d| *d == card.deck)
+    }
+
+    /// The cards in the selected deck, or all cards when no deck is selected.
+    fn deck_cards(&self) -> Vec<&Card> {
+        self.cards.iter().filter(|c| self.in_deck(c)).collect()
+    }
+
+    fn in_session(&self, card: &Card) -> bool {
+        self.in_deck(card) && self.tag_filter.matches(&card.tags)
+    }
+
+    /// The cards to study: those in the selected deck that pass the tag filter.
+    fn session_cards(&self) -> Vec<&Card> {
+        self.cards.iter().filter(|c| self.in_session(c)).collect()
+    }
+
+    /// Start choosing cards afresh, so that a seeded session plays out the same way again.
+    fn start_session(&mut self) {
+        self.display_history.clear();
+        *self.rng.get_mut() = select::session_rng(self.session_seed);
+    }
+
+    fn choose_card(&self) -> Option<CardId> {
+        select::choose_card(
+            &self.session_cards(),
+            &self.settings(),
+            &self.display_history,
+            now_ms(),
+            today_cutoff(),
+            &mut *self.rng.borrow_mut(),
+        )
+    }
+
+    fn n_due_today(&self) -> usize {
+        select::n_due(&self.session_cards(), &self.settings(), today_cutoff())
+    }
+
+    fn next_due(&self) -> Option<f64> {
+        select::next_due(&self.session_cards(), &self.settings())
+    }
+
+    /// The side of the card that answers its prompt
+    fn answer_text<'a>(&self, card: &'a Card) -> &'a str {
+        if self.reverse_mode {
+            &card.prompt
         } else {
-            vec![if self.choose_neglected { 0.0 } else { 1.0 }; self.cards.len()]
+            &card.response
+        }
+    }
+
+    /// Offer the current card's answer among answers from other cards in the deck.
+    fn make_quiz_choices(&mut self) {
+        self.quiz_card = self.current_card;
+        let Some(card) = self.current_card.and_then(|id| self.card(id)) else {
+            self.quiz_choices.clear();
+            return;
         };
-        if self.choose_neglected {
-            for (w, c) in weights.iter_mut().zip(self.cards.iter()) {
-                let n_visits = c.hits + c.misses;
-                *w += if n_visits == 0 {
-                    1.0
-                } else {
-                    1.0 / n_visits as f64
-                };
+        let mut confusions = HashMap::new();
+        for review in self.reviews.iter() {
+            if review.card == card.id && review.reverse == self.reverse_mode {
+                if let Some(other) = review.confused_with {
+                    *confusions.entry(other).or_insert(0) += 1;
+                }
             }
         }
-        for i in self.display_history.iter() {
-            weights[*i] = 0.0;
+        let candidates = self
+            .deck_cards()
+            .into_iter()
+            .filter(|c| c.id != card.id)
+            .map(|c| quiz::Candidate {
+                id: c.id,
+                answer: self.answer_text(c),
+                confusions: confusions.get(&c.id).copied().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        let mut rng = self.rng.borrow_mut();
+        let mut choices = quiz::choose_distractors(
+            self.answer_text(card),
+            &candidates,
+            quiz::N_CHOICES - 1,
+            &mut *rng,
+        );
+        choices.push(card.id);
+        choices.shuffle(&mut *rng);
+        self.quiz_choices = choices;
+    }
+
+    /// Respond to the current card and move on to the next one.
+    fn grade_current(
+        &mut self,
+        ctx: &yew::Context<Model>,
+        grade: Grade,
+        confused_with: Option<CardId>,
+    ) -> bool {
+        if self.respond(grade, confused_with) {
+            self.visible_face = Face::Prompt;
+            ctx.link().send_message(Msg::StoreCards);
+            ctx.link().send_message(Msg::Next);
+            true
+        } else {
+            false
         }
-        let dist = WeightedIndex::new(&weights).unwrap();
-        Some(dist.sample(rng))
+    }
+
+    /// Log a response to the current card, returning whether there was one.
+    fn respond(&mut self, grade: Grade, confused_with: Option<CardId>) -> bool {
+        let Some(id) = self.current_card else {
+            return false;
+        };
+        let now = now_ms();
+        let review = Review {
+            card: id,
+            reverse: self.reverse_mode,
+            grade,
+            timestamp: now,
+            latency_ms: now - self.shown_at,
+            confused_with,
+        };
+        let n_reviews = self.reviews.len();
+        let Some(card) = self.card_mut(id) else {
+            return false;
+        };
+        let before = card.clone();
+        card.apply_review(&review);
+        self.history.record_review(before, n_reviews);
+        self.reviews.push(review);
+        true
     }
 
     fn copy_button_style(&self) -> String {
@@ -239,129 +468,65 @@ impl Model {
         )
     }
 
-    fn pop_last_displayed(&mut self) -> Option<usize> {
-        self.display_history.pop_back()
+    /// Drop references to cards that no longer exist.
+    fn forget_missing_cards(&mut self) {
+        let ids = self.cards.iter().map(|c| c.id).collect::<HashSet<_>>();
+        self.display_history = self
+            .display_history
+            .iter()
+            .filter(|id| ids.contains(id))
+            .copied()
+            .collect();
+        if self.current_card.is_some_and(|id| !ids.contains(&id)) {
+            self.current_card = None;
+            self.visible_face = Face::Prompt;
+        }
+        if self.deletion_target.is_some_and(|id| !ids.contains(&id)) {
+            self.deletion_target = None;
+        }
     }
 
-    fn record_display(&mut self, card: usize) {
-        let n = (self.cards.len() as f64).log2().round() as usize;
-        self.display_history.push_back(card);
-        if self.display_history.len() > n {
-            self.display_history.pop_front();
+    /// Skips any cards that have been deleted since they were displayed.
+    fn pop_last_displayed(&mut self) -> Option<CardId> {
+        while let Some(id) = self.display_history.pop_back() {
+            if self.card(id).is_some() {
+                return Some(id);
+            }
         }
+        None
+    }
+
+    fn record_display(&mut self, card: CardId) {
+        let n_cards = self.session_cards().len();
+        select::record_display(&mut self.display_history, card, n_cards);
     }
 
     fn stats_html(&self) -> Html {
-        let mut cards = self.cards.clone();
+        let cards = self.deck_cards();
         if cards.is_empty() {
             return html! {
                 <p>{"There are no cards."}</p>
             };
         }
-        let hits_misses = |card: &Card| {
-            if self.reverse_mode {
-                (
-                    card.reverse_hits.unwrap_or_default(),
-                    card.reverse_misses.unwrap_or_default(),
-                )
-            } else {
-                (card.hits, card.misses)
-            }
-        };
-        let hit_ratio = |h, m| {
-            let total = h + m;
-            if total == 0 {
-                0.0
-            } else {
-                h as f32 / total as f32
-            }
-        };
-        let r = |card: &Card| {
-            let (h, m) = hits_misses(card);
-            hit_ratio(h, m)
-        };
-        let goodness = |card: &Card| {
-            let (h, m) = hits_misses(card);
-            let total = h + m;
-            if total == 0 {
-                0.0
-            } else {
-                let diff = h as isize - m as isize;
-                diff as f32 / total as f32
-            }
-        };
-        let sorting = |a: &Card, b: &Card| {
-            let good_a = goodness(a);
-            let good_b = goodness(b);
-            let cmp = good_b.partial_cmp(&good_a).unwrap();
-            if !matches!(cmp, Ordering::Equal) {
-                cmp
-            } else {
-                let (a_h, a_m) = hits_misses(a);
-                let (b_h, b_m) = hits_misses(b);
-                if good_a >= 0.0 {
-                    b_h.partial_cmp(&a_h).unwrap()
-                } else {
-                    a_m.partial_cmp(&b_m).unwrap()
-                }
-            }
-        };
-        cards.sort_by(sorting);
-        let percent_visited = 100.0
-            * (cards
-                .iter()
-                .filter(|c| {
-                    let (h, m) = hits_misses(c);
-                    h + m > 0
-                })
-                .count() as f32)
-            / cards.len() as f32;
-        let n_responses = cards
-            .iter()
-            .map(|c| {
-                let (h, m) = hits_misses(c);
-                h + m
-            })
-            .sum::<usize>();
-        let percents = cards.iter().map(|c| r(c) * 100.0).collect::<Vec<_>>();
-        let goodnesses = cards.iter().map(|c| goodness(c)).collect::<Vec<_>>();
-        let rows = cards
+        let stats = Stats::new(&cards, &self.reviews, self.reverse_mode, today_start());
+        let rows = stats
+            .rows
             .iter()
             .take(self.n_rows_displayed)
-            .zip(percents.iter())
-            .zip(goodnesses.iter())
-            .map(|((c, percent), good)| {
-                let (h, m) = hits_misses(c);
+            .map(|row| {
                 html! {
                     <tr>
-                        <td>{&c.prompt}</td>
-                        <td>{&c.response}</td>
-                        <td class="number">{h}</td>
-                        <td class="number">{m}</td>
-                        <td class="number">{format!("{:.2}", percent)}</td>
-                        <td class="number">{format!("{:.2}", good)}</td>
+                        <td>{&row.card.prompt}</td>
+                        <td>{&row.card.response}</td>
+                        <td class="number">{row.hits}</td>
+                        <td class="number">{row.misses}</td>
+                        <td class="number">{format!("{:.2}", row.percent_hit)}</td>
+                        <td class="number">{format!("{:.2}", row.goodness)}</td>
                     </tr>
                 }
             })
             .collect::<Vec<_>>();
         let prefix = if self.reverse_mode { "reverse " } else { "" };
-        let percent_good = {
-            let ratio = if goodnesses.is_empty() {
-                0.0
-            } else {
-                let n_good = goodnesses
-                    .iter()
-                    .zip(cards.iter())
-                    .filter(|(g, c)| {
-                        let (h, m) = hits_misses(c);
-                        h + m > 1  // just one response isn't enough to "know it well"
-                        && *g >= &GOODNESS_CRITERION
-                    })
-                    .count();
-                n_good as f32 / goodnesses.len() as f32
-            };
-            100.0 * ratio
-        };
         html! {
             <>
                 <ul>
@@ -372,7 +537,7 @@ impl Model {
                                 <br />
                                 {"(hits - misses) / (hits + misses)"}
                             </span>
-                            {"Overall score: "}{format!("{:.2}", 100.0 * mean(&goodnesses))}
+                            {"Overall score: "}{format!("{:.2}", stats.score)}
                         </span>
                     </li>
                     <li>
@@ -382,14 +547,15 @@ impl Model {
                                 <br />
                                 {format!("(hits - misses) / (hits + misses) > {:.2}", GOODNESS_CRITERION)}
                             </span>
-                            {"Cards known well: "}{format!("{:.2}%", percent_good)}
+                            {"Cards known well: "}{format!("{:.2}%", stats.percent_good)}
                         </span>
                     </li>
                     <li>
                         {"Cards visited: "}
-                        {format!("{:.2}% of {}", percent_visited, cards.len())}
+                        {format!("{:.2}% of {}", stats.percent_visited, cards.len())}
                     </li>
-                    <li>{"Number of responses: "}{format!("{n_responses}")}</li>
+                    <li>{"Number of responses: "}{stats.n_responses}</li>
+                    <li>{"Responses today: "}{stats.n_responses_today}</li>
                 </ul>
                 <table class="striped">
                     <tr>
@@ -406,59 +572,18 @@ impl Model {
         }
     }
     fn default_card_data() -> String {
-        let reverse_hits = None;
-        let reverse_misses = None;
         let cards: Vec<Card> = vec![
-            Card {
-                prompt: "What is the key for flipping a card?".to_owned(),
-                response: "\"f\"".to_owned(),
-                hits: 0,
-                misses: 0,
-                reverse_hits,
-                reverse_misses,
-            },
-            Card {
-                prompt: "What is the key for registering a \"hit\"?".to_owned(),
-                response: "\"h\"".to_owned(),
-                hits: 0,
-                misses: 0,
-                reverse_hits,
-                reverse_misses,
-            },
-            Card {
-                prompt: "What is the key for registering a \"miss\"?".to_owned(),
-                response: "\"m\"".to_owned(),
-                hits: 0,
-                misses: 0,
-                reverse_hits,
-                reverse_misses,
-            },
-            Card {
-                prompt: "What key shows the previous card?".to_owned(),
-                response: "\"p\"".to_owned(),
-                hits: 0,
-                misses: 0,
-                reverse_hits,
-                reverse_misses,
-            },
-            Card {
-                prompt: "What key shows the next card without registering hit or miss?".to_owned(),
-                response: "\"n\"".to_owned(),
-                hits: 0,
-                misses: 0,
-                reverse_hits,
-                reverse_misses,
-            },
-            Card {
-                prompt: "What is the key for editing the current card?".to_owned(),
-                response: "\"e\"".to_owned(),
-                hits: 0,
-                misses: 0,
-                reverse_hits,
-                reverse_misses,
-            },
+            Card::new("What is the key for flipping a card?", "\"f\""),
+            Card::new("What is the key for registering a \"hit\"?", "\"h\""),
+            Card::new("What is the key for registering a \"miss\"?", "\"m\""),
+            Card::new("What key shows the previous card?", "\"p\""),
+            Card::new(
+                "What key shows the next card without registering hit or miss?",
+                "\"n\"",
+            ),
+            Card::new("What is the key for editing the current card?", "\"e\""),
         ];
-        serde_json::to_string(&cards)
+        serde_json::to_string(&Envelope::new(&Settings::default(), &cards))
             .context("serializing cards")
             .unwrap()
     }
@@ -498,40 +623,921 @@ impl Model {
                 </label>
             </div>
         };
+        let cgraded = html! {
+            <div class="form-check">
+                <input
+                    id="graded-mode-checkbox"
+                    class="form-check-input"
+                    type={"checkbox"}
+                    value=""
+                    checked={ self.graded_mode }
+                    autocomplete={"off"}
+                    onclick={link.callback(move |_| Msg::GradedModeToggle)}
+                />
+                <label
+                    class="form-check-label"
+                    for="graded-mode-checkbox">{"graded answers"}
+                </label>
+            </div>
+        };
+        let ctyped = html! {
+            <div class="form-check">
+                <input
+                    id="typed-mode-checkbox"
+                    class="form-check-input"
+                    type={"checkbox"}
+                    value=""
+                    checked={ self.typed_mode }
+                    autocomplete={"off"}
+                    onclick={link.callback(move |_| Msg::TypedModeToggle)}
+                />
+                <label
+                    class="form-check-label"
+                    for="typed-mode-checkbox">{"type answers"}
+                </label>
+            </div>
+        };
+        let options = Scheduler::ALL
+            .iter()
+            .map(|sched| {
+                html! {
+                    <option value={sched.to_string()} selected={*sched == self.scheduler}>
+                        {sched.label()}
+                    </option>
+                }
+            })
+            .collect::<Html>();
+        let scheduler_select = html! {
+            <div class="form-check">
+                <label for="scheduler-select">{"choose cards by "}</label>
+                <select
+                    id="scheduler-select"
+                    onchange={link.callback(|e: Event| {
+                        let select: HtmlSelectElement = e.target_unchecked_into();
+                        let value = select.value();
+                        Scheduler::ALL
+                            .into_iter()
+                            .find(|sched| sched.to_string() == value)
+                            .map_or(Msg::Noop, Msg::SetScheduler)
+                    })}
+                >
+                    {options}
+                </select>
+            </div>
+        };
+        let seed_input = html! {
+            <div class="form-check">
+                <label for="session-seed-input">{"session seed "}</label>
+                <input
+                    id="session-seed-input"
+                    type="text" inputmode="numeric" size="12" placeholder="random"
+                    value={self.session_seed.map(|seed| seed.to_string()).unwrap_or_default()}
+                    onchange={link.callback(|e: Event| {
+                        let input: HtmlInputElement = e.target_unchecked_into();
+                        match input.value().trim() {
+                            "" => Msg::SetSessionSeed(None),
+                            text => text
+                                .parse::<u64>()
+                                .map_or(Msg::Render, |seed| Msg::SetSessionSeed(Some(seed))),
+                        }
+                    })}
+                />
+            </div>
+        };
+        if self.scheduler == Scheduler::Beta {
+            html! {
+                <>
+                    {cgraded}
+                    {ctyped}
+                    {scheduler_select}
+                    {seed_input}
+                    {cmissed}
+                    {cneglected}
+                </>
+            }
+        } else if self.scheduler == Scheduler::Fsrs {
+            html! {
+                <>
+                    {cgraded}
+                    {ctyped}
+                    {scheduler_select}
+                    {seed_input}
+                    <div class="form-check">
+                        <label for="target-retention-input">{"target retention "}</label>
+                        <input
+                            id="target-retention-input"
+                            type="number" min="0.7" max="0.99" step="0.01"
+                            value={self.target_retention.to_string()}
+                            onchange={link.callback(|e: Event| {
+                                let input: HtmlInputElement = e.target_unchecked_into();
+                                match input.value().parse::<f64>() {
+                                    Ok(r) if r > 0.0 && r < 1.0 => Msg::SetTargetRetention(r),
+                                    _ => Msg::Render,
+                                }
+                            })}
+                        />
+                    </div>
+                    <div>{format!("Due today: {}", self.n_due_today())}</div>
+                </>
+            }
+        } else {
+            html! {
+                <>
+                    {cgraded}
+                    {ctyped}
+                    {scheduler_select}
+                    {seed_input}
+                    <div>{format!("Due today: {}", self.n_due_today())}</div>
+                </>
+            }
+        }
+    }
+    fn typed_answer_html(&self, ctx: &yew::Context<Model>) -> Html {
+        let last_answer_html = if let Some(checked) = &self.last_answer {
+            let diff = checked
+                .diff
+                .iter()
+                .map(|part| match part {
+                    DiffPart::Same(s) => html! { <span class="diff-same">{s}</span> },
+                    DiffPart::Missing(s) => html! { <span class="diff-missing">{s}</span> },
+                    DiffPart::Extra(s) => html! { <span class="diff-extra">{s}</span> },
+                })
+                .collect::<Html>();
+            html! {
+                <div class="last-answer">
+                    {if checked.correct { "Hit: " } else { "Miss: " }}
+                    {diff}
+                    <br/>
+                    {format!("(expected \"{}\")", checked.expected)}
+                </div>
+            }
+        } else {
+            html! {}
+        };
+        html! {
+            <>
+                {last_answer_html}
+                <input
+                    id="typed-answer" type="text" placeholder="type the answer and hit Enter"
+                    ref={self.focus_node.clone()}
+                    value={self.typed_answer.clone()}
+                    oninput={ctx.link().callback(|e: InputEvent| {
+                        let input = e.target_unchecked_into::<HtmlInputElement>();
+                        Msg::UpdateTypedAnswer(input.value())
+                    })}
+                    onkeypress={ctx.link().batch_callback(|e: yew::events::KeyboardEvent| {
+                        (e.key() == "Enter").then_some(Msg::SubmitAnswer)
+                    })}
+                />
+                <button onclick={ctx.link().callback(|_| Msg::SubmitAnswer)}>{ "Check" }</button>
+                <button onclick={ctx.link().callback(|_| Msg::Prev)}>{ "Prev" }</button>
+                <button onclick={ctx.link().callback(|_| Msg::Next)}>{ "Next" }</button>
+            </>
+        }
+    }
+    fn deck_select_html(&self, ctx: &yew::Context<Model>) -> Html {
+        let options = self
+            .decks
+            .iter()
+            .map(|deck| {
+                html! {
+                    <option value={deck.clone()} selected={self.selected_deck.as_ref() == Some(deck)}>
+                        {deck}
+                    </option>
+                }
+            })
+            .collect::<Html>();
+        html! {
+            <select
+                class="deck-select"
+                onchange={ctx.link().callback(|e: Event| {
+                    let select: HtmlSelectElement = e.target_unchecked_into();
+                    let value = select.value();
+                    Msg::SelectDeck(if value.is_empty() { None } else { Some(value) })
+                })}
+            >
+                <option value="" selected={self.selected_deck.is_none()}>{"All decks"}</option>
+                {options}
+            </select>
+        }
+    }
+    fn decks_html(&self, ctx: &yew::Context<Model>) -> Html {
+        let selected_html = if let Some(deck) = &self.selected_deck {
+            let delete_html = if self.deck_deletion_request {
+                html! {
+                    <span>
+                        <button class="confirm" onclick={ctx.link().callback(|_| Msg::DeleteDeck(true))}>
+                            {format!("Confirm: Delete \"{deck}\" and Its Cards")}
+                        </button>
+                        <button class="cancel" onclick={ctx.link().callback(|_| Msg::DeleteDeck(false))}>
+                            {"Cancel: Do Not Delete Deck"}
+                        </button>
+                    </span>
+                }
+            } else {
+                html! {
+                    <button onclick={ctx.link().callback(|_| Msg::DeleteDeck(true))}>
+                        {format!("Delete \"{deck}\"")}
+                    </button>
+                }
+            };
+            html! {
+                <>
+                    <button onclick={ctx.link().callback(|_| Msg::RenameDeck)}>
+                        {format!("Rename \"{deck}\"")}
+                    </button>
+                    {delete_html}
+                </>
+            }
+        } else {
+            html! {}
+        };
+        html! {
+            <div class="decks">
+                <input
+                    id="deck-name" type="text" placeholder="deck name"
+                    value={self.deck_name_text.clone()}
+                    oninput={ctx.link().callback(|e: InputEvent| {
+                        let input = e.target_unchecked_into::<HtmlInputElement>();
+                        Msg::UpdateDeckNameText(input.value())
+                    })}
+                />
+                <button onclick={ctx.link().callback(|_| Msg::CreateDeck)}>{"Create Deck"}</button>
+                {selected_html}
+            </div>
+        }
+    }
+    fn move_card_html(&self, ctx: &yew::Context<Model>, card: &Card) -> Html {
+        let id = card.id;
+        let options = self
+            .decks
+            .iter()
+            .map(|deck| {
+                html! {
+                    <option value={deck.clone()} selected={*deck == card.deck}>{deck}</option>
+                }
+            })
+            .collect::<Html>();
+        html! {
+            <select
+                onchange={ctx.link().callback(move |e: Event| {
+                    let select: HtmlSelectElement = e.target_unchecked_into();
+                    Msg::MoveCard(id, select.value())
+                })}
+            >
+                {options}
+            </select>
+        }
+    }
+    /// Make cards from an uploaded file, which is an Anki package or text in one of the
+    /// formats that Memoradical reads.
+    fn ingest(&mut self, name: String, data: Vec<u8>) {
+        if data.starts_with(ZIP_SIGNATURE) {
+            match anki::read_package(&data, self.anki_counts) {
+                Err(e) => {
+                    let problem = Problem::whole_file(&name, format!("{e:#}"));
+                    self.upload.problems.push(problem);
+                }
+                Ok(cards) => self.upload.add(&name, cards, vec![]),
+            }
+            return;
+        }
+        let text = match validate::decode(&name, data) {
+            Err(problem) => {
+                self.upload.problems.push(*problem);
+                return;
+            }
+            Ok(text) => text,
+        };
+        if anki::is_text_export(&text) {
+            self.upload.tables.push_back(anki::text_import(name, text));
+            return;
+        }
+        let delimiter = Delimiter::from_filename(&name);
+        let json = name.to_lowercase().ends_with(".json")
+            || text.trim_start().starts_with('[')
+            || text.trim_start().starts_with('{');
+        if delimiter.is_some() || !json {
+            let import = TableImport::new(name, text, delimiter);
+            self.upload.tables.push_back(import);
+            return;
+        }
+        // uploads bring cards, and the settings in use stay as they are
+        let (_, cards, problems) = schema::read(&name, &text);
+        self.upload.add(&name, cards, problems);
+    }
+    /// Stage the uploaded cards once every file is in,
+    /// unless some records had problems that the user should see first.
+    fn finish_upload(&mut self) {
+        if self.upload.pending == 0 {
+            self.readers.clear();
+        }
+        if self.upload.is_done() && self.upload.problems.is_empty() && !self.upload.is_empty() {
+            let upload = std::mem::take(&mut self.upload);
+            self.stage_new_cards(upload.cards);
+        }
+    }
+    fn validation_html(&self, ctx: &yew::Context<Model>) -> Html {
+        let upload = &self.upload;
+        let n_good = upload.cards.len();
+        html! {
+            <div class="validation-report">
+                <p>
+                    {format!(
+                        "{} good records, {} with problems",
+                        n_good,
+                        upload.problems.len()
+                    )}
+                </p>
+                {problems_table(&upload.problems)}
+                {
+                    if n_good > 0 {
+                        html! {
+                            <button class="confirm" onclick={ctx.link().callback(|_| Msg::ImportGoodRecords)}>
+                                {format!("Import the {n_good} Good Records")}
+                            </button>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                <button onclick={ctx.link().callback(|_| Msg::CancelUpload)}>
+                    {"Cancel"}
+                </button>
+            </div>
+        }
+    }
+    /// Start using loaded cards, giving ids and decks to any that lack them.
+    fn use_saved(&mut self, ctx: &yew::Context<Model>, saved: Saved) {
+        let Saved {
+            settings,
+            mut cards,
+            decks,
+            reviews,
+        } = saved;
+        let assigned = assign_missing_ids(&mut cards);
+        if assign_missing_decks(&mut cards, DEFAULT_DECK) || assigned {
+            ctx.link().send_message(Msg::StoreCards);
+        }
+        self.apply_settings(settings);
+        self.cards = cards;
+        self.decks = decks;
+        self.reviews = reviews;
+        self.sync_decks();
+        self.start_session();
+        self.current_card = self.choose_card();
+        self.list_restore_points(ctx);
+    }
+    fn version(&self) -> Version {
+        Version {
+            cards: self.cards.clone(),
+            decks: self.decks.clone(),
+            reviews: self.reviews.clone(),
+        }
+    }
+    /// Go back or forward to a version of the cards from undo or redo, and save it.
+    fn use_version(&mut self, ctx: &yew::Context<Model>, version: Version) -> bool {
+        let Version {
+            cards,
+            decks,
+            reviews,
+        } = version;
+        self.cards = cards;
+        self.decks = decks;
+        self.reviews = reviews;
+        self.forget_missing_cards();
+        self.sync_decks();
+        if self
+            .selected_deck
+            .as_ref()
+            .is_some_and(|deck| !self.decks.contains(deck))
+        {
+            self.selected_deck = None;
+        }
+        ctx.link().send_message(Msg::StoreCards);
+        true
+    }
+    /// Keep a copy of the cards as they are, so that what is about to be done can be undone.
+    fn keep_restore_point(&mut self, ctx: &yew::Context<Model>, reason: Reason) {
+        let settings = self.settings();
+        self.last_point_kept = now_ms().max(self.last_point_kept + 1.0);
+        let point = RestorePoint::new(
+            self.last_point_kept,
+            reason,
+            &settings,
+            &self.cards,
+            &self.decks,
+        );
+        let Some(store) = self.store.as_mut() else {
+            return;
+        };
+        let keeping = match point {
+            Ok(point) => store.keep(&point),
+            Err(e) => Box::pin(std::future::ready(Err(e))),
+        };
+        ctx.link().send_future(async move {
+            match keeping.await {
+                Ok(()) => Msg::Noop,
+                Err(e) => {
+                    Msg::SetRestoreError(format!("{:#}", e.context("keeping a restore point")))
+                }
+            }
+        });
+    }
+    /// Keep the day's first restore point, once it is known when the last one was kept.
+    fn keep_daily_point(&mut self, ctx: &yew::Context<Model>) {
+        if self.last_daily_point.is_some_and(|t| t < today_start()) {
+            self.last_daily_point = Some(now_ms());
+            self.keep_restore_point(ctx, Reason::Daily);
+        }
+    }
+    fn list_restore_points(&mut self, ctx: &yew::Context<Model>) {
+        if let Some(store) = self.store.as_mut() {
+            let listing = store.restore_points();
+            ctx.link().send_future(async move {
+                match listing.await {
+                    Ok(points) => Msg::RestorePoints(points),
+                    Err(e) => {
+                        Msg::SetRestoreError(format!("{:#}", e.context("reading restore points")))
+                    }
+                }
+            });
+        }
+    }
+    fn restore_html(&self, ctx: &yew::Context<Model>) -> Html {
+        let error_html = match &self.restore_error {
+            Some(e) => html! { <p>{e}</p> },
+            None => html! {},
+        };
+        if self.restore_points.is_empty() {
+            return html! {
+                <>
+                    {error_html}
+                    <p>{"There are no restore points yet."}</p>
+                </>
+            };
+        }
+        let rows = self
+            .restore_points
+            .iter()
+            .map(|(point, cards, n_unreadable)| {
+                let timestamp = point.timestamp;
+                let n_cards = if *n_unreadable > 0 {
+                    format!("{} ({} unreadable)", cards.len(), n_unreadable)
+                } else {
+                    cards.len().to_string()
+                };
+                html! {
+                    <tr>
+                        <td>{format_time(timestamp)}</td>
+                        <td>{point.reason.label()}</td>
+                        <td>{format!("{:.1} KB", point.size() as f64 / 1024.0)}</td>
+                        <td>{n_cards}</td>
+                        <td>{restore::compare(&self.cards, cards).to_string()}</td>
+                        <td>
+                            <button onclick={ctx.link().callback(move |_| Msg::Restore(timestamp))}>
+                                {"Restore"}
+                            </button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
         html! {
             <>
-                {cmissed}
-                {cneglected}
+                {error_html}
+                <table class="striped">
+                    <tr>
+                        <th>{"Time"}</th><th>{"Kept"}</th><th>{"Size"}</th><th>{"Cards"}</th>
+                        <th>{"Compared with Now"}</th><th></th>
+                    </tr>
+                    {rows}
+                </table>
             </>
         }
     }
+    /// The study options that are saved with the cards
+    fn settings(&self) -> Settings {
+        Settings {
+            choose_missed: self.choose_missed,
+            choose_neglected: self.choose_neglected,
+            graded_mode: self.graded_mode,
+            reverse_mode: self.reverse_mode,
+            scheduler: self.scheduler,
+            session_seed: self.session_seed,
+            target_retention: self.target_retention,
+            typed_mode: self.typed_mode,
+        }
+    }
+    fn apply_settings(&mut self, settings: Settings) {
+        self.choose_missed = settings.choose_missed;
+        self.choose_neglected = settings.choose_neglected;
+        self.graded_mode = settings.graded_mode;
+        self.reverse_mode = settings.reverse_mode;
+        self.scheduler = settings.scheduler;
+        self.session_seed = settings.session_seed;
+        self.target_retention = settings.target_retention;
+        self.typed_mode = settings.typed_mode;
+    }
+    /// Replace unreadable stored data at the user's request.
+    /// The store keeps a backup of the unreadable text.
+    fn recover(&mut self, ctx: &yew::Context<Model>, mut saved: Saved) {
+        let (Some(store), Some(unreadable)) = (self.store.as_mut(), self.unreadable.take()) else {
+            return;
+        };
+        // cards are stored by id
+        assign_missing_ids(&mut saved.cards);
+        let saving = store.replace(&saved, &unreadable.raw);
+        ctx.link().send_future(async move {
+            match saving.await {
+                Ok(()) => Msg::Noop,
+                Err(e) => {
+                    Msg::SetFatalError(format!("{:?}", e.context("replacing unreadable data")))
+                }
+            }
+        });
+        self.salvaged = None;
+        self.use_saved(ctx, saved);
+    }
+    fn recovery_html(&self, ctx: &yew::Context<Model>, unreadable: &Unreadable) -> Html {
+        let salvage_html = match &self.salvaged {
+            None => html! {},
+            Some((saved, problems)) => {
+                let n_cards = saved.cards.len();
+                html! {
+                    <div class="validation-report">
+                        <p>
+                            {format!(
+                                "{} cards, {} decks, and {} reviews can be salvaged, and {} records cannot.",
+                                n_cards,
+                                saved.decks.len(),
+                                saved.reviews.len(),
+                                problems.len()
+                            )}
+                        </p>
+                        {problems_table(problems)}
+                        <button class="confirm" onclick={ctx.link().callback(|_| Msg::UseSalvaged)}>
+                            {format!("Use the {n_cards} Salvaged Cards")}
+                        </button>
+                    </div>
+                }
+            }
+        };
+        html! {
+            <div id="memoradical" class="memoradical-recovery">
+                <h2>{"Stored Cards Cannot Be Read"}</h2>
+                <p>{format!("{unreadable}")}</p>
+                <p>
+                    {"Nothing is changed until you choose what to do. "}
+                    {"If you replace the stored data, a backup of the unreadable text is kept in the browser too."}
+                </p>
+                <span class={if self.download_error.is_some() { "tooltip" } else { "" }}>
+                    <span class="tooltiptext">{self.download_error.clone().unwrap_or_default()}</span>
+                    <button onclick={ctx.link().callback(|_| Msg::DownloadUnreadable)}>
+                        {"Download Stored Text"}
+                    </button>
+                </span>
+                <button onclick={ctx.link().callback(|_| Msg::SalvageStorage)}>
+                    {"Salvage What Can Be Read"}
+                </button>
+                <button class="cancel" onclick={ctx.link().callback(|_| Msg::ResetStorage)}>
+                    {"Reset to Default Cards"}
+                </button>
+                {salvage_html}
+                <h3>{format!("Stored {}", unreadable.part.label())}</h3>
+                <pre class="raw-text">{unreadable.text()}</pre>
+            </div>
+        }
+    }
+    /// Hold uploaded cards until the user chooses to add them or overwrite with them.
+    fn stage_new_cards(&mut self, mut cards: Vec<Card>) {
+        assign_missing_ids(&mut cards);
+        assign_missing_decks(&mut cards, &self.current_deck());
+        self.new_cards = Some(merge::classify(&self.cards, cards));
+    }
+    fn table_import_html(&self, ctx: &yew::Context<Model>, import: &TableImport) -> Html {
+        let link = ctx.link().clone();
+        let delimiter_options = Delimiter::ALL
+            .iter()
+            .map(|d| {
+                html! {
+                    <option value={d.extension()} selected={*d == import.delimiter}>
+                        {d.label()}
+                    </option>
+                }
+            })
+            .collect::<Html>();
+        let column_selects = import
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let options = Field::ALL
+                    .iter()
+                    .map(|f| {
+                        html! {
+                            <option value={f.label()} selected={f == field}>{f.label()}</option>
+                        }
+                    })
+                    .collect::<Html>();
+                html! {
+                    <th>
+                        <select onchange={link.callback(move |e: Event| {
+                            let select: HtmlSelectElement = e.target_unchecked_into();
+                            let value = select.value();
+                            Field::ALL
+                                .into_iter()
+                                .find(|f| f.label() == value)
+                                .map_or(Msg::Noop, |f| Msg::SetImportColumn(i, f))
+                        })}>
+                            {options}
+                        </select>
+                    </th>
+                }
+            })
+            .collect::<Html>();
+        let header = import.header().map_or(html! {}, |names| {
+            html! {
+                <tr>{for names.iter().map(|name| html! { <th>{name}</th> })}</tr>
+            }
+        });
+        let rows = import
+            .rows()
+            .take(IMPORT_PREVIEW_ROWS)
+            .map(|(_, row)| {
+                html! {
+                    <tr>{for row.iter().map(|value| html! { <td>{value}</td> })}</tr>
+                }
+            })
+            .collect::<Html>();
+        html! {
+            <div class="table-import">
+                <div class="form-check">
+                    <label for="delimiter-select">{"fields separated by "}</label>
+                    <select
+                        id="delimiter-select"
+                        onchange={link.callback(|e: Event| {
+                            let select: HtmlSelectElement = e.target_unchecked_into();
+                            let value = select.value();
+                            Delimiter::ALL
+                                .into_iter()
+                                .find(|d| d.extension() == value)
+                                .map_or(Msg::Noop, Msg::SetImportDelimiter)
+                        })}
+                    >
+                        {delimiter_options}
+                    </select>
+                </div>
+                <div class="form-check">
+                    <input
+                        id="import-header-checkbox"
+                        class="form-check-input"
+                        type={"checkbox"}
+                        value=""
+                        checked={import.has_header}
+                        autocomplete={"off"}
+                        onclick={link.callback(|_| Msg::ImportHeaderToggle)}
+                    />
+                    <label
+                        class="form-check-label"
+                        for="import-header-checkbox">{"first line is a header"}
+                    </label>
+                </div>
+                <div class="form-check">
+                    <input
+                        id="import-comments-checkbox"
+                        class="form-check-input"
+                        type={"checkbox"}
+                        value=""
+                        checked={import.skip_comments}
+                        autocomplete={"off"}
+                        onclick={link.callback(|_| Msg::ImportCommentsToggle)}
+                    />
+                    <label
+                        class="form-check-label"
+                        for="import-comments-checkbox">{"skip lines starting with #"}
+                    </label>
+                </div>
+                <table>
+                    <thead>
+                        <tr>{column_selects}</tr>
+                        {header}
+                    </thead>
+                    <tbody>{rows}</tbody>
+                </table>
+                <p>{format!("{} cards", import.rows().count())}</p>
+                <button class="confirm" onclick={link.callback(|_| Msg::ImportTable)}>
+                    {"Use These Columns"}
+                </button>
+                <button onclick={link.callback(|_| Msg::CancelUpload)}>
+                    {"Cancel"}
+                </button>
+            </div>
+        }
+    }
+    /// How the uploaded cards compare with the existing ones, and what to do with each kind
+    fn merge_preview_html(&self, ctx: &yew::Context<Model>) -> Html {
+        let incoming = self.new_cards.as_deref().unwrap_or_default();
+        let link = ctx.link().clone();
+        let summary = Change::ALL
+            .into_iter()
+            .map(|change| {
+                let n = incoming.iter().filter(|i| i.change == change).count();
+                let chosen = self.merge_strategies.get(change);
+                let options = change
+                    .strategies()
+                    .iter()
+                    .map(|strategy| {
+                        html! {
+                            <option value={strategy.label(change)} selected={*strategy == chosen}>
+                                {strategy.label(change)}
+                            </option>
+                        }
+                    })
+                    .collect::<Html>();
+                html! {
+                    <tr>
+                        <td>{change.label()}</td>
+                        <td>{n}</td>
+                        <td>
+                            <select disabled={n == 0} onchange={link.callback(move |e: Event| {
+                                let select: HtmlSelectElement = e.target_unchecked_into();
+                                let value = select.value();
+                                change
+                                    .strategies()
+                                    .iter()
+                                    .find(|s| s.label(change) == value)
+                                    .map_or(Msg::Noop, |s| Msg::SetMergeStrategy(change, *s))
+                            })}>
+                                {options}
+                            </select>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+        let changed = incoming
+            .iter()
+            .filter(|i| i.change != Change::Identical)
+            .collect::<Vec<_>>();
+        let rows = changed
+            .iter()
+            .take(ROW_DISPLAY_INITIAL)
+            .map(|i| {
+                let existing = i.existing.and_then(|id| self.card(id));
+                let counts = |c: &Card| {
+                    let (hits, misses) = c.hits_misses(false);
+                    format!("{hits}/{misses}")
+                };
+                html! {
+                    <tr>
+                        <td>{i.change.label()}</td>
+                        <td>{existing.map(|c| c.prompt.clone()).unwrap_or_default()}</td>
+                        <td>{existing.map(|c| c.response.clone()).unwrap_or_default()}</td>
+                        <td>{existing.map(counts).unwrap_or_default()}</td>
+                        <td>{&i.card.prompt}</td>
+                        <td>{&i.card.response}</td>
+                        <td>{counts(&i.card)}</td>
+                        <td>{i.card.source.clone().unwrap_or_default()}</td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+        let more = changed.len().saturating_sub(ROW_DISPLAY_INITIAL);
+        html! {
+            <>
+                <table class="merge-summary">
+                    <tr><th>{"Uploaded cards"}</th><th>{"Count"}</th><th>{"Action"}</th></tr>
+                    {summary}
+                </table>
+                <table class="merge-preview">
+                    <tr>
+                        <th>{"Change"}</th>
+                        <th>{"Existing Front"}</th>
+                        <th>{"Existing Back"}</th>
+                        <th>{"Hits/Misses"}</th>
+                        <th>{"Uploaded Front"}</th>
+                        <th>{"Uploaded Back"}</th>
+                        <th>{"Hits/Misses"}</th>
+                        <th>{"File"}</th>
+                    </tr>
+                    {rows}
+                </table>
+                {
+                    if more > 0 {
+                        html! { <p>{format!("... and {more} more")}</p> }
+                    } else {
+                        html! {}
+                    }
+                }
+            </>
+        }
+    }
+    fn conflicts_html(&self, ctx: &yew::Context<Model>) -> Html {
+        if self.conflicts.is_empty() {
+            return html! {};
+        }
+        let link = ctx.link().clone();
+        let version = |card: Option<&Card>| match card {
+            Some(card) => html! {
+                <>
+                    <td>{&card.prompt}</td>
+                    <td>{&card.response}</td>
+                    <td>{&card.deck}</td>
+                </>
+            },
+            None => html! { <td colspan="3"><em>{"deleted"}</em></td> },
+        };
+        let rows = self
+            .conflicts
+            .iter()
+            .map(|conflict| {
+                let id = conflict.id;
+                html! {
+                    <tr>
+                        {version(conflict.side(Side::Ours))}
+                        <td>
+                            <button onclick={link.callback(move |_| Msg::ResolveConflicts(Some(id), Side::Ours))}>
+                                {"Keep"}
+                            </button>
+                        </td>
+                        {version(conflict.side(Side::Theirs))}
+                        <td>
+                            <button onclick={link.callback(move |_| Msg::ResolveConflicts(Some(id), Side::Theirs))}>
+                                {"Take"}
+                            </button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+        html! {
+            <div class="conflicts">
+                <h3>{"Changed in Another Tab"}</h3>
+                <p>
+                    {"These cards were changed both here and in another tab. "}
+                    {"This tab's version is kept until you choose."}
+                </p>
+                <table class="striped">
+                    <tr>
+                        <th colspan="4">{"This Tab"}</th>
+                        <th colspan="4">{"Other Tab"}</th>
+                    </tr>
+                    {rows}
+                </table>
+                <button onclick={ctx.link().callback(|_| Msg::ResolveConflicts(None, Side::Ours))}>
+                    {"Keep All from This Tab"}
+                </button>
+                <button onclick={ctx.link().callback(|_| Msg::ResolveConflicts(None, Side::Theirs))}>
+                    {"Take All from Other Tab"}
+                </button>
+            </div>
+        }
+    }
     fn upload_button_html(&self, ctx: &yew::Context<Model>) -> Html {
         let upload_button = if let Some(err) = &self.upload_error {
             html! {
                 <button disabled=true>{err}</button>
             }
+        } else if self.upload.pending > 0 {
+            html! {
+                <p>{format!("Reading {} files...", self.upload.pending)}</p>
+            }
+        } else if let Some(import) = self.upload.table() {
+            self.table_import_html(ctx, import)
+        } else if !self.upload.problems.is_empty() {
+            self.validation_html(ctx)
         } else if self.new_cards.is_none() {
             html! {
-                <input type="file" multiple=false
+                <>
+                <input type="file" multiple=true
                     onchange={ctx.link().callback(move |e: Event| {
-                        let mut result = Vec::new();
                         let input: HtmlInputElement = e.target_unchecked_into();
-                        if let Some(files) = input.files() {
-                            let files = js_sys::try_iter(&files)
-                                .unwrap()
-                                .unwrap()
-                                .map(|v| web_sys::File::from(v.unwrap()))
-                                .map(File::from);
-                            result.extend(files);
-                        }
-                        Msg::UploadCards(result)
+                        Msg::UploadCards(file_list(input.files()))
                     })}/>
+                <div class="form-check">
+                    <input
+                        id="anki-counts-checkbox"
+                        class="form-check-input"
+                        type={"checkbox"}
+                        value=""
+                        checked={self.anki_counts}
+                        autocomplete={"off"}
+                        onclick={ctx.link().callback(|_| Msg::AnkiCountsToggle)}
+                    />
+                    <label
+                        class="form-check-label"
+                        for="anki-counts-checkbox">{"count hits and misses from Anki reviews"}
+                    </label>
+                </div>
+                </>
             }
         } else {
             html! {
                 <div>
+                    {self.merge_preview_html(ctx)}
                     <button class="confirm" onclick={ctx.link().callback(|_| Msg::AddNewCards)}>
-                        {"Add New Cards to Existing"}
+                        {"Merge New Cards into Existing"}
                     </button>
                     <button class="cancel" onclick={ctx.link().callback(|_| Msg::StoreNewCards)}>
                         {"Overwrite Existing Cards with New Cards"}
@@ -546,6 +1552,73 @@ impl Model {
     }
 }
 
+fn problems_table(problems: &[Problem]) -> Html {
+    let rows = problems
+        .iter()
+        .map(|p| {
+            html! {
+                <tr>
+                    <td>{&p.file}</td>
+                    <td>{p.location()}</td>
+                    <td>{p.field.clone().unwrap_or_default()}</td>
+                    <td>{&p.message}</td>
+                    <td><code>{&p.snippet}</code></td>
+                </tr>
+            }
+        })
+        .collect::<Html>();
+    html! {
+        <table>
+            <tr><th>{"File"}</th><th>{"Where"}</th><th>{"Field"}</th><th>{"Problem"}</th><th>{"Text"}</th></tr>
+            {rows}
+        </table>
+    }
+}
+
+/// Undo with "u" or Ctrl+Z, and redo with Ctrl+Shift+Z, anywhere but in a text field.
+fn listen_for_undo_keys(ctx: &yew::Context<Model>) -> Option<Closure<dyn Fn(KeyboardEvent)>> {
+    let link = ctx.link().clone();
+    let on_keydown = Closure::wrap(Box::new(move |e: KeyboardEvent| {
+        let typing = e
+            .target()
+            .and_then(|t| t.dyn_into::<HtmlElement>().ok())
+            .is_some_and(|t| {
+                t.is_content_editable()
+                    || ["INPUT", "TEXTAREA", "SELECT"].contains(&t.tag_name().as_str())
+            });
+        let key = e.key().to_lowercase();
+        let control = e.ctrl_key() || e.meta_key();
+        let msg = if typing || e.alt_key() {
+            None
+        } else if control && key == "z" {
+            Some(if e.shift_key() { Msg::Redo } else { Msg::Undo })
+        } else if !control && e.key() == "u" {
+            Some(Msg::Undo)
+        } else {
+            None
+        };
+        if let Some(msg) = msg {
+            e.prevent_default();
+            link.send_message(msg);
+        }
+    }) as Box<dyn Fn(KeyboardEvent)>);
+    let window = web_sys::window()?;
+    window
+        .add_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref())
+        .ok()?;
+    Some(on_keydown)
+}
+
+fn file_list(files: Option<web_sys::FileList>) -> Vec<File> {
+    let Some(files) = files else {
+        return vec![];
+    };
+    (0..files.length())
+        .filter_map(|i| files.get(i))
+        .map(File::from)
+        .collect()
+}
+
 async fn fetch_html(resource: &str) -> Result<String> {
     let html = gloo_net::http::Request::get(resource)
         .send()
@@ -555,8 +1628,9 @@ async fn fetch_html(resource: &str) -> Result<String> {
     Ok(html)
 }
 
-async fn copy_cards_to_clipboard(cards: &[Card]) -> Result<()> {
-    let value = serde_json::to_string_pretty(cards).context("serializing cards")?;
+async fn copy_cards_to_clipboard(settings: &Settings, cards: &[Card]) -> Result<()> {
+    let value = serde_json::to_string_pretty(&Envelope::new(settings, cards))
+        .context("serializing cards")?;
     let navigator: web_sys::Navigator = web_sys::window().unwrap().navigator();
     console_dbg!("clipboard write");
     if let Some(clipboard) = navigator.clipboard() {
@@ -576,40 +1650,86 @@ impl Component for Model {
     type Message = Msg;
     type Properties = ();
 
-    fn create(_ctx: &yew::Context<Self>) -> Self {
-        let local_store = LocalStore::new(STORAGE_KEY_CARDS, &Self::default_card_data()).unwrap();
-        let cards: Vec<Card> = serde_json::from_str(&local_store.value()).unwrap();
-        let mut instance = Self {
-            cards,
+    fn create(ctx: &yew::Context<Self>) -> Self {
+        ctx.link().send_future(async {
+            match storage::open(&Self::default_card_data()).await {
+                Ok((store, saved)) => Msg::StoreOpened(store, saved),
+                Err(e) => Msg::SetFatalError(format!("{:?}", e.context("opening storage"))),
+            }
+        });
+        Self {
+            anki_counts: false,
+            cards: vec![],
             choose_missed: true,
             choose_neglected: false,
             clear_counts_request: false,
             clipboard_error: None,
+            conflicts: vec![],
             copy_border_opacity: 0.0,
             copy_border_fader: None,
             current_card: None,
+            deck_deletion_request: false,
+            deck_name_text: "".to_owned(),
+            decks: vec![],
             deletion_target: None,
+            download_error: None,
             display_history: LinkedList::new(),
             fatal_error: None,
             focus_node: NodeRef::default(),
+            graded_mode: false,
             help_html: None,
             help_node: NodeRef::default(),
-            local_store,
+            history: History::default(),
+            last_answer: None,
+            last_daily_point: None,
+            last_point_kept: 0.0,
             mode: Mode::Study,
             n_rows_displayed: 0,
             need_key_focus: true,
             new_back_text: "".to_owned(),
             new_front_text: "".to_owned(),
+            new_tags_text: "".to_owned(),
+            quiz_card: None,
+            quiz_choices: vec![],
+            quiz_feedback: None,
+            merge_strategies: Strategies::default(),
             new_cards: None,
             readers: vec![],
+            reload_pending: false,
+            reloading: false,
             rerender: None,
+            restore_error: None,
+            restore_points: vec![],
             reverse_mode: false,
+            reviews: vec![],
+            rng: RefCell::new(select::session_rng(None)),
+            salvaged: None,
+            scheduler: Scheduler::Beta,
+            selected_deck: None,
+            session_seed: None,
+            shown_at: now_ms(),
+            store: None,
+            tag_filter: TagFilter::default(),
+            tag_filter_text: "".to_owned(),
+            target_retention: DEFAULT_TARGET_RETENTION,
+            typed_answer: "".to_owned(),
+            typed_mode: false,
+            undo_keys: listen_for_undo_keys(ctx),
+            unreadable: None,
             upload_clearer: None,
+            upload: Upload::default(),
             upload_error: None,
             visible_face: Face::Prompt,
-        };
-        instance.current_card = instance.choose_card();
-        instance
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &yew::Context<Self>) {
+        if let (Some(window), Some(on_keydown)) = (web_sys::window(), &self.undo_keys) {
+            let _ = window.remove_event_listener_with_callback(
+                "keydown",
+                on_keydown.as_ref().unchecked_ref(),
+            );
+        }
     }
 
     fn rendered(&mut self, _ctx: &yew::Context<Self>, _first_render: bool) {
@@ -627,18 +1747,29 @@ impl Component for Model {
     }
 
     fn update(&mut self, ctx: &yew::Context<Self>, msg: Self::Message) -> bool {
+        let shown_card = self.current_card;
+        let before = msg.is_undoable().then(|| self.version());
         let need_render = match msg {
             Msg::AddCard => {
                 if self.mode == Mode::Edit {
-                    self.cards[self.current_card.unwrap()].prompt = self.new_front_text.clone();
-                    self.cards[self.current_card.unwrap()].response = self.new_back_text.clone();
+                    let front = self.new_front_text.clone();
+                    let back = self.new_back_text.clone();
+                    let tags = tags::parse_tags(&self.new_tags_text);
+                    if let Some(card) = self.current_card.and_then(|id| self.card_mut(id)) {
+                        card.prompt = front;
+                        card.response = back;
+                        card.tags = tags;
+                    }
                     self.change_mode(Mode::Study);
                 } else {
-                    let card = Card::new(&self.new_front_text, &self.new_back_text);
+                    let mut card = Card::new(&self.new_front_text, &self.new_back_text);
+                    card.deck = self.current_deck();
+                    card.tags = tags::parse_tags(&self.new_tags_text);
                     self.cards.push(card);
                 }
                 self.new_back_text = "".to_owned();
                 self.new_front_text = "".to_owned();
+                self.new_tags_text = "".to_owned();
                 ctx.link().send_message(Msg::StoreCards);
                 true
             }
@@ -647,12 +1778,9 @@ impl Component for Model {
                 true
             }
             Msg::AddNewCards => {
-                if let Some(new_cards) = &mut self.new_cards {
-                    self.display_history.clear();
-                    self.cards.append(new_cards);
-                    self.new_cards = None;
-                    self.current_card = self.choose_card();
-                    self.visible_face = Face::Prompt;
+                if let Some(new_cards) = self.new_cards.take() {
+                    merge::merge(&mut self.cards, new_cards, &self.merge_strategies);
+                    self.sync_decks();
                     ctx.link().send_message(Msg::StoreCards);
                 }
                 true
@@ -661,23 +1789,34 @@ impl Component for Model {
                 self.change_mode(Mode::AllCards);
                 true
             }
+            Msg::AnkiCountsToggle => {
+                self.anki_counts = !self.anki_counts;
+                true
+            }
             Msg::CancelUpload => {
                 self.new_cards = None;
+                self.upload = Upload::default();
                 true
             }
             Msg::ChooseMissedToggle => {
                 self.choose_missed = !self.choose_missed;
+                ctx.link().send_message(Msg::StoreCards);
                 true
             }
             Msg::ChooseNeglectedToggle => {
                 self.choose_neglected = !self.choose_neglected;
+                ctx.link().send_message(Msg::StoreCards);
                 true
             }
             Msg::ClearCounts(yesno) => {
                 if yesno {
                     if self.clear_counts_request {
+                        self.keep_restore_point(ctx, Reason::ClearCounts);
+                        let deck = self.selected_deck.clone();
                         for c in self.cards.iter_mut() {
-                            c.clear_counts(self.reverse_mode);
+                            if deck.as_ref().is_none_or(|d| *d == c.deck) {
+                                c.clear_counts(self.reverse_mode);
+                            }
                         }
                         ctx.link().send_message(Msg::StoreCards);
                     }
@@ -688,9 +1827,10 @@ impl Component for Model {
                 true
             }
             Msg::CopyCards => {
+                let settings = self.settings();
                 let cards = self.cards.clone();
                 ctx.link().send_future(async move {
-                    match copy_cards_to_clipboard(&cards).await {
+                    match copy_cards_to_clipboard(&settings, &cards).await {
                         Err(e) => {
                             console_dbg!(&e);
                             Msg::SetClipboardError(e)
@@ -700,6 +1840,42 @@ impl Component for Model {
                 });
                 true
             }
+            Msg::DownloadCards => {
+                let filename = download::dated_filename("memoradical-cards", "json");
+                let result =
+                    serde_json::to_string_pretty(&Envelope::new(&self.settings(), &self.cards))
+                        .context("serializing cards")
+                        .and_then(|json| download::download(&filename, "application/json", &json));
+                self.download_error = result.err().map(|e| format!("{:#}", e));
+                true
+            }
+            Msg::DownloadUnreadable => {
+                if let Some(unreadable) = &self.unreadable {
+                    let stem = format!(
+                        "memoradical-unreadable-{}",
+                        unreadable.part.label().replace(' ', "-")
+                    );
+                    let filename = download::dated_filename(&stem, "json");
+                    let result =
+                        download::download(&filename, "application/json", unreadable.text());
+                    self.download_error = result.err().map(|e| format!("{:#}", e));
+                }
+                true
+            }
+            Msg::DownloadAnki => {
+                let filename = download::dated_filename("memoradical-anki", "txt");
+                let text = anki::export_text(&self.cards);
+                let result = download::download(&filename, "text/plain", &text);
+                self.download_error = result.err().map(|e| format!("{:#}", e));
+                true
+            }
+            Msg::DownloadTable(delimiter) => {
+                let filename = download::dated_filename("memoradical-cards", delimiter.extension());
+                let text = delimited::export(&self.cards, delimiter);
+                let result = download::download(&filename, delimiter.mime_type(), &text);
+                self.download_error = result.err().map(|e| format!("{:#}", e));
+                true
+            }
             Msg::CopyCardsSuccess => {
                 self.copy_border_opacity = 1.0;
                 let handle = {
@@ -711,21 +1887,42 @@ impl Component for Model {
                 self.copy_border_fader = Some(handle);
                 true
             }
-            Msg::DeleteCard(i) => {
-                if self.deletion_target.is_some() && self.deletion_target.unwrap() == i {
-                    if let Some(curr) = self.current_card {
-                        self.current_card = match curr.cmp(&i) {
-                            Ordering::Equal => None,
-                            Ordering::Greater => Some(curr - 1),
-                            Ordering::Less => Some(curr),
-                        };
+            Msg::CreateDeck => {
+                let name = self.deck_name_text.trim().to_owned();
+                if name.is_empty() || self.decks.contains(&name) {
+                    false
+                } else {
+                    self.decks.push(name.clone());
+                    self.deck_name_text = "".to_owned();
+                    self.selected_deck = Some(name);
+                    self.current_card = None;
+                    self.sync_decks();
+                    ctx.link().send_message(Msg::StoreCards);
+                    true
+                }
+            }
+            Msg::DeleteDeck(yesno) => {
+                if yesno && self.deck_deletion_request {
+                    if let Some(deck) = self.selected_deck.take() {
+                        self.keep_restore_point(ctx, Reason::DeleteDeck);
+                        self.cards.retain(|c| c.deck != deck);
+                        self.decks.retain(|d| *d != deck);
+                        self.forget_missing_cards();
+                        ctx.link().send_message(Msg::StoreCards);
                     }
-                    self.display_history.clear(); // because the numbers changed
-                    self.cards.remove(i);
+                    self.deck_deletion_request = false;
+                } else {
+                    self.deck_deletion_request = yesno;
+                }
+                true
+            }
+            Msg::DeleteCard(id) => {
+                if self.deletion_target == Some(id) {
+                    self.cards.retain(|c| c.id != id);
+                    self.forget_missing_cards();
                     ctx.link().send_message(Msg::StoreCards);
-                    self.deletion_target = None;
                 } else {
-                    self.deletion_target = Some(i);
+                    self.deletion_target = Some(id);
                 }
                 true
             }
@@ -742,15 +1939,15 @@ impl Component for Model {
                 }
                 false
             }
-            Msg::Edit(i) => {
+            Msg::Edit(id) => {
                 let mut redraw = false;
-                let card_index = if i.is_none() { self.current_card } else { i };
-                if let Some(i) = card_index {
-                    if let Some(card) = self.cards.get(i) {
+                if let Some(id) = id.or(self.current_card) {
+                    if let Some(card) = self.card(id).cloned() {
                         redraw = true;
-                        self.current_card = Some(i);
-                        self.new_front_text = card.prompt.clone();
-                        self.new_back_text = card.response.clone();
+                        self.current_card = Some(id);
+                        self.new_front_text = card.prompt;
+                        self.new_back_text = card.response;
+                        self.new_tags_text = card.tags.join(", ");
                         self.change_mode(Mode::Edit);
                     }
                 }
@@ -771,6 +1968,12 @@ impl Component for Model {
                 };
                 true
             }
+            Msg::Grade(grade) => self.grade_current(ctx, grade, None),
+            Msg::GradedModeToggle => {
+                self.graded_mode = !self.graded_mode;
+                ctx.link().send_message(Msg::StoreCards);
+                true
+            }
             Msg::HelpMode => {
                 self.change_mode(Mode::Help);
                 if self.help_html.is_none() {
@@ -786,49 +1989,57 @@ impl Component for Model {
                 }
                 true
             }
-            Msg::Hit => {
-                if let Some(card) = self.current_card {
-                    if self.reverse_mode {
-                        let rhits = self.cards[card].reverse_hits;
-                        self.cards[card].reverse_hits = Some(rhits.map_or(1, |v| v + 1));
-                    } else {
-                        self.cards[card].hits += 1;
-                    }
-                    self.visible_face = Face::Prompt;
-                    ctx.link().send_message(Msg::StoreCards);
-                    ctx.link().send_message(Msg::Next);
-                    true
-                } else {
-                    false
+            Msg::Hit => self.grade_current(ctx, Grade::Good, None),
+            Msg::ImportCommentsToggle => {
+                if let Some(import) = self.upload.table_mut() {
+                    import.skip_comments = !import.skip_comments;
                 }
+                true
             }
-            Msg::IngestNewCards(json) => {
-                match serde_json::from_str::<Vec<Card>>(&json) {
-                    Err(e) => {
-                        ctx.link()
-                            .send_message(Msg::SetUploadError(Some(format!("{e}"))));
-                    }
-                    Ok(cards) => {
-                        self.new_cards = Some(cards);
-                    }
+            Msg::ImportHeaderToggle => {
+                if let Some(import) = self.upload.table_mut() {
+                    import.has_header = !import.has_header;
                 }
                 true
             }
-            Msg::Miss => {
-                if let Some(card) = self.current_card {
-                    if self.reverse_mode {
-                        let rmisses = self.cards[card].reverse_misses;
-                        self.cards[card].reverse_misses = Some(rmisses.map_or(1, |v| v + 1));
-                    } else {
-                        self.cards[card].misses += 1;
+            Msg::ImportGoodRecords => {
+                let upload = std::mem::take(&mut self.upload);
+                self.stage_new_cards(upload.cards);
+                true
+            }
+            Msg::ImportTable => {
+                if let Some(import) = self.upload.table() {
+                    match import.cards() {
+                        Err(e) => {
+                            ctx.link()
+                                .send_message(Msg::SetUploadError(Some(format!("{e}"))));
+                        }
+                        Ok((cards, problems)) => {
+                            let file = import.file.clone();
+                            self.upload.tables.pop_front();
+                            self.upload.add(&file, cards, problems);
+                            self.finish_upload();
+                        }
                     }
-                    self.visible_face = Face::Prompt;
+                }
+                true
+            }
+            Msg::IngestFile(name, data) => {
+                self.upload.pending = self.upload.pending.saturating_sub(1);
+                match data {
+                    Err(e) => self.upload.problems.push(Problem::whole_file(&name, e)),
+                    Ok(data) => self.ingest(name, data),
+                }
+                self.finish_upload();
+                true
+            }
+            Msg::Miss => self.grade_current(ctx, Grade::Again, None),
+            Msg::MoveCard(id, deck) => {
+                if let Some(card) = self.card_mut(id) {
+                    card.deck = deck;
                     ctx.link().send_message(Msg::StoreCards);
-                    ctx.link().send_message(Msg::Next);
-                    true
-                } else {
-                    false
                 }
+                true
             }
             Msg::Next => {
                 if self.current_card.is_some() {
@@ -848,20 +2059,241 @@ impl Component for Model {
                     false
                 }
             }
+            Msg::QuizAnswer(choice) => {
+                if let Some(card) = self.current_card.and_then(|id| self.card(id)) {
+                    let id = card.id;
+                    let answer = self.answer_text(card);
+                    if choice == id {
+                        self.quiz_feedback = Some(format!("Correct: \"{answer}\""));
+                        self.grade_current(ctx, Grade::Good, None)
+                    } else {
+                        self.quiz_feedback = Some(format!("Missed: the answer was \"{answer}\""));
+                        self.grade_current(ctx, Grade::Again, Some(choice))
+                    }
+                } else {
+                    false
+                }
+            }
+            Msg::QuizMode => {
+                self.change_mode(Mode::Quiz);
+                self.quiz_feedback = None;
+                true
+            }
+            Msg::Redo => {
+                let redone = self.history.redo(&self.version());
+                redone.is_some_and(|version| self.use_version(ctx, version))
+            }
+            Msg::Reloaded(Reloaded { base, theirs }) => {
+                self.reloading = false;
+                // what was undoable may have been built on since in another tab
+                self.history.clear();
+                if self.settings() == base.settings {
+                    self.apply_settings(theirs.settings);
+                }
+                let (cards, conflicts) = sync::merge_cards(&base.cards, &self.cards, &theirs.cards);
+                self.cards = cards;
+                self.decks = sync::merge_decks(&base.decks, &self.decks, &theirs.decks);
+                self.reviews = sync::merge_reviews(&base.reviews, &self.reviews, &theirs.reviews);
+                for conflict in conflicts {
+                    self.conflicts.retain(|c| c.id != conflict.id);
+                    self.conflicts.push(conflict);
+                }
+                self.forget_missing_cards();
+                self.sync_decks();
+                if self
+                    .selected_deck
+                    .as_ref()
+                    .is_some_and(|deck| !self.decks.contains(deck))
+                {
+                    self.selected_deck = None;
+                }
+                ctx.link().send_message(Msg::StoreCards);
+                if std::mem::take(&mut self.reload_pending) {
+                    ctx.link().send_message(Msg::StorageChanged);
+                }
+                true
+            }
             Msg::Render => true,
+            Msg::RenameDeck => {
+                let name = self.deck_name_text.trim().to_owned();
+                match self.selected_deck.clone() {
+                    Some(old) if !name.is_empty() && !self.decks.contains(&name) => {
+                        for card in self.cards.iter_mut().filter(|c| c.deck == old) {
+                            card.deck = name.clone();
+                        }
+                        self.decks.retain(|d| *d != old);
+                        self.decks.push(name.clone());
+                        self.deck_name_text = "".to_owned();
+                        self.selected_deck = Some(name);
+                        self.sync_decks();
+                        ctx.link().send_message(Msg::StoreCards);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Msg::ResetStorage => {
+                let (_, cards, _) = schema::read("default cards", &Self::default_card_data());
+                self.recover(
+                    ctx,
+                    Saved {
+                        cards,
+                        ..Saved::default()
+                    },
+                );
+                true
+            }
+            Msg::ResolveConflicts(id, side) => {
+                let (resolved, remaining): (Vec<_>, Vec<_>) = std::mem::take(&mut self.conflicts)
+                    .into_iter()
+                    .partition(|c| id.is_none_or(|id| c.id == id));
+                self.conflicts = remaining;
+                if side == Side::Theirs {
+                    for conflict in resolved {
+                        let position = self.cards.iter().position(|c| c.id == conflict.id);
+                        match (position, conflict.theirs) {
+                            (Some(i), Some(card)) => self.cards[i] = card,
+                            (Some(i), None) => {
+                                self.cards.remove(i);
+                            }
+                            (None, Some(card)) => self.cards.push(card),
+                            (None, None) => (),
+                        }
+                    }
+                    self.forget_missing_cards();
+                    self.sync_decks();
+                    ctx.link().send_message(Msg::StoreCards);
+                }
+                true
+            }
+            Msg::Restore(timestamp) => {
+                let Some((point, cards, _)) = self
+                    .restore_points
+                    .iter()
+                    .find(|(p, ..)| p.timestamp == timestamp)
+                    .cloned()
+                else {
+                    return false;
+                };
+                self.keep_restore_point(ctx, Reason::Restore);
+                self.cards = cards;
+                self.decks = point.decks;
+                self.forget_missing_cards();
+                self.sync_decks();
+                if self
+                    .selected_deck
+                    .as_ref()
+                    .is_some_and(|deck| !self.decks.contains(deck))
+                {
+                    self.selected_deck = None;
+                }
+                ctx.link().send_message(Msg::StoreCards);
+                self.list_restore_points(ctx);
+                true
+            }
+            Msg::RestoreMode => {
+                self.change_mode(Mode::Restore);
+                self.list_restore_points(ctx);
+                true
+            }
+            Msg::RestorePoints(points) => {
+                let last_daily = points
+                    .iter()
+                    .filter(|p| p.reason == Reason::Daily)
+                    .map(|p| p.timestamp)
+                    .fold(0.0, f64::max);
+                self.last_daily_point = Some(last_daily);
+                self.keep_daily_point(ctx);
+                if self.mode == Mode::Restore {
+                    self.restore_points = points
+                        .into_iter()
+                        .rev()
+                        .map(|point| {
+                            let (cards, problems) = point.read_cards();
+                            (point, cards, problems.len())
+                        })
+                        .collect();
+                }
+                true
+            }
             Msg::ReverseModeToggle => {
                 self.clear_counts_request = false;
                 self.reverse_mode = !self.reverse_mode;
+                ctx.link().send_message(Msg::StoreCards);
+                true
+            }
+            Msg::SalvageStorage => {
+                self.salvaged = self.unreadable.as_ref().map(|u| u.raw.salvage());
+                true
+            }
+            Msg::SelectDeck(deck) => {
+                self.selected_deck = deck;
+                self.deck_deletion_request = false;
+                self.clear_counts_request = false;
+                self.start_session();
+                if self
+                    .current_card
+                    .and_then(|id| self.card(id))
+                    .is_some_and(|c| !self.in_session(c))
+                {
+                    self.current_card = None;
+                    self.visible_face = Face::Prompt;
+                }
                 true
             }
             Msg::SetClipboardError(e) => {
                 self.clipboard_error = Some(format!("{}", e));
                 true
             }
+            Msg::SetFatalError(err) => {
+                self.fatal_error = Some(err);
+                true
+            }
             Msg::SetHelp(help) => {
                 self.help_html = Some(help);
                 true
             }
+            Msg::SetImportColumn(column, field) => {
+                if let Some(import) = self.upload.table_mut() {
+                    import.columns[column] = field;
+                }
+                true
+            }
+            Msg::SetImportDelimiter(delimiter) => {
+                if let Some(import) = self.upload.table_mut() {
+                    import.set_delimiter(delimiter);
+                }
+                true
+            }
+            Msg::SetMergeStrategy(change, strategy) => {
+                self.merge_strategies.set(change, strategy);
+                true
+            }
+            Msg::SetScheduler(scheduler) => {
+                self.scheduler = scheduler;
+                ctx.link().send_message(Msg::StoreCards);
+                self.start_session();
+                self.current_card = self.choose_card();
+                self.visible_face = Face::Prompt;
+                true
+            }
+            Msg::SetSessionSeed(seed) => {
+                self.session_seed = seed;
+                ctx.link().send_message(Msg::StoreCards);
+                self.start_session();
+                self.current_card = self.choose_card();
+                self.visible_face = Face::Prompt;
+                true
+            }
+            Msg::SetTargetRetention(retention) => {
+                self.target_retention = retention;
+                ctx.link().send_message(Msg::StoreCards);
+                true
+            }
+            Msg::SetRestoreError(e) => {
+                self.restore_error = Some(e);
+                true
+            }
             Msg::SetUploadError(e) => {
                 self.upload_error = e;
                 let handle = {
@@ -878,34 +2310,95 @@ impl Component for Model {
                 true
             }
             Msg::StoreCards => {
-                let json = serde_json::to_string(&self.cards).unwrap();
-                match self
-                    .local_store
-                    .save(&json)
-                    .context("storing existing cards")
-                {
-                    Ok(_) => (),
-                    Err(e) => {
-                        self.fatal_error = Some(format!("{e:?}"));
-                    }
+                self.keep_daily_point(ctx);
+                let settings = self.settings();
+                if let Some(store) = self.store.as_mut() {
+                    let saving = store.save(&settings, &self.cards, &self.decks, &self.reviews);
+                    ctx.link().send_future(async move {
+                        match saving.await {
+                            Ok(()) => Msg::Noop,
+                            Err(e) if e.is::<ChangedElsewhere>() => Msg::StorageChanged,
+                            Err(e) => Msg::SetFatalError(format!("{e:?}")),
+                        }
+                    });
                 }
                 true
             }
             Msg::StoreNewCards => {
-                if let Some(cards) = &self.new_cards {
-                    self.display_history.clear();
-                    self.cards = cards.clone();
-                    self.new_cards = None;
-                    self.current_card = self.choose_card();
-                    self.visible_face = Face::Prompt;
+                if let Some(cards) = self.new_cards.take() {
+                    self.keep_restore_point(ctx, Reason::Overwrite);
+                    // matched again among themselves, so that repeated cards are merged
+                    let uploaded = cards.into_iter().map(|i| i.card).collect();
+                    self.cards = Vec::new();
+                    let incoming = merge::classify(&[], uploaded);
+                    merge::merge(&mut self.cards, incoming, &Strategies::default());
+                    self.forget_missing_cards();
+                    self.sync_decks();
                     ctx.link().send_message(Msg::StoreCards);
                 }
                 true
             }
+            Msg::StorageChanged => {
+                if self.unreadable.is_some() {
+                    // nothing can be merged until the stored data is recovered
+                } else if self.reloading {
+                    self.reload_pending = true;
+                } else if let Some(store) = self.store.as_mut() {
+                    self.reloading = true;
+                    let reloading = store.reload();
+                    ctx.link().send_future(async move {
+                        match reloading.await {
+                            Ok(reloaded) => Msg::Reloaded(reloaded),
+                            Err(e) => Msg::SetFatalError(format!(
+                                "{:?}",
+                                e.context("reloading cards saved by another tab")
+                            )),
+                        }
+                    });
+                }
+                false
+            }
+            Msg::StoreOpened(mut store, loaded) => {
+                let link = ctx.link().clone();
+                store.watch(Box::new(move || link.send_message(Msg::StorageChanged)));
+                self.store = Some(store);
+                match loaded {
+                    Ok(saved) => self.use_saved(ctx, saved),
+                    Err(unreadable) => self.unreadable = Some(unreadable),
+                }
+                true
+            }
             Msg::StudyMode => {
                 self.change_mode(Mode::Study);
                 true
             }
+            Msg::SubmitAnswer => {
+                if let Some(card) = self.current_card.and_then(|id| self.card(id)) {
+                    let checked = answer::check(self.answer_text(card), &self.typed_answer);
+                    ctx.link()
+                        .send_message(if checked.correct { Msg::Hit } else { Msg::Miss });
+                    self.last_answer = Some(checked);
+                    self.typed_answer = "".to_owned();
+                    true
+                } else {
+                    false
+                }
+            }
+            Msg::TypedModeToggle => {
+                self.typed_mode = !self.typed_mode;
+                ctx.link().send_message(Msg::StoreCards);
+                self.last_answer = None;
+                self.need_key_focus = true;
+                true
+            }
+            Msg::Undo => {
+                let undone = self.history.undo(&self.version());
+                undone.is_some_and(|version| self.use_version(ctx, version))
+            }
+            Msg::UpdateDeckNameText(text) => {
+                self.deck_name_text = text;
+                true
+            }
             Msg::UpdateNewBackText(text) => {
                 self.new_back_text = text;
                 true
@@ -914,30 +2407,67 @@ impl Component for Model {
                 self.new_front_text = text;
                 true
             }
+            Msg::UpdateNewTagsText(text) => {
+                self.new_tags_text = text;
+                true
+            }
+            Msg::UpdateTagFilter(text) => {
+                self.tag_filter = TagFilter::parse(&text);
+                self.tag_filter_text = text;
+                self.start_session();
+                if self
+                    .current_card
+                    .and_then(|id| self.card(id))
+                    .is_some_and(|c| !self.in_session(c))
+                {
+                    self.current_card = None;
+                    self.visible_face = Face::Prompt;
+                }
+                true
+            }
+            Msg::UpdateTypedAnswer(text) => {
+                self.typed_answer = text;
+                false
+            }
             Msg::UploadCards(files) => {
-                if files.len() != 1 {
-                    ctx.link().send_message(Msg::SetUploadError(Some(
-                        "Multiple file upload unsupported".to_owned(),
-                    )));
-                    false
-                } else {
-                    let task = {
-                        let link = ctx.link().clone();
-                        read_as_text(&files[0], move |result| {
-                            link.send_message(Msg::IngestNewCards(result.unwrap()));
-                        })
-                    };
+                // the files are read concurrently, and each one is ingested as it arrives
+                for file in files {
+                    let link = ctx.link().clone();
+                    let name = file.name();
+                    let task = read_as_bytes(&file, move |result| {
+                        let data = result.map_err(|e| format!("cannot read the file: {e}"));
+                        link.send_message(Msg::IngestFile(name, data));
+                    });
                     self.readers.push(task);
-                    true
+                    self.upload.pending += 1;
+                }
+                true
+            }
+            Msg::UseSalvaged => {
+                if let Some((saved, _)) = self.salvaged.take() {
+                    self.recover(ctx, saved);
                 }
+                true
             }
         };
-        if self.mode == Mode::Study && self.current_card.is_none() {
+        if let Some(before) = before {
+            self.history.record(&before, &self.version());
+        }
+        let studying = self.mode == Mode::Study || self.mode == Mode::Quiz;
+        let mut need_render = if studying && self.current_card.is_none() {
             self.current_card = self.choose_card();
             true
         } else {
             need_render
+        };
+        if self.mode == Mode::Quiz && self.quiz_card != self.current_card {
+            self.make_quiz_choices();
+            need_render = true;
+        }
+        if self.current_card != shown_card {
+            self.shown_at = now_ms();
         }
+        need_render
     }
 
     fn view(&self, ctx: &yew::Context<Self>) -> Html {
@@ -967,23 +2497,56 @@ impl Component for Model {
                 </button>
             </span>
         };
+        let download_tables_html = Delimiter::ALL
+            .into_iter()
+            .map(|delimiter| {
+                html! {
+                    <button onclick={ctx.link().callback(move |_| Msg::DownloadTable(delimiter))}>
+                        {format!("Download {}", delimiter.extension().to_uppercase())}
+                    </button>
+                }
+            })
+            .collect::<Html>();
+        let download_cards_html = html! {
+            <span class={if self.download_error.is_some() { "tooltip" } else { "" }}>
+                <span class="tooltiptext">{self.download_error.clone().unwrap_or_default()}</span>
+                <button onclick={ctx.link().callback(|_| Msg::DownloadCards)}>
+                    {"Download Cards"}
+                </button>
+            </span>
+        };
         if let Some(err) = &self.fatal_error {
             return html! {
                 <>
                     <h2>{"Fatal Error"}</h2>
                     {copy_cards_html}
+                    {download_cards_html}
                     <pre>{err}</pre>
                 </>
             };
         }
+        if let Some(unreadable) = &self.unreadable {
+            return self.recovery_html(ctx, unreadable);
+        }
+        if self.store.is_none() {
+            return html! { <p>{"Loading cards..."}</p> };
+        }
         let mode_buttons = html! {
+            <>
             <nav>
                 <button disabled={self.mode == Mode::Help} onclick={ctx.link().callback(|_| Msg::HelpMode)}>{"Help"}</button>
                 <button disabled={self.mode == Mode::Study} onclick={ctx.link().callback(|_| Msg::StudyMode)}>{"Study"}</button>
                 <button disabled={self.mode == Mode::Add || self.mode == Mode::Edit} onclick={ctx.link().callback(|_| Msg::AddMode)}>{"Add Card"}</button>
                 <button disabled={self.mode == Mode::AllCards} onclick={ctx.link().callback(|_| Msg::AllCardsMode)}>{"All Cards"}</button>
+                <button disabled={self.mode == Mode::Quiz} onclick={ctx.link().callback(|_| Msg::QuizMode)}>{"Quiz"}</button>
                 <button disabled={self.mode == Mode::Stats} onclick={ctx.link().callback(|_| Msg::StatsMode)}>{"Stats"}</button>
+                <button disabled={self.mode == Mode::Restore} onclick={ctx.link().callback(|_| Msg::RestoreMode)}>{"Restore"}</button>
+                <button disabled={!self.history.can_undo()} onclick={ctx.link().callback(|_| Msg::Undo)}>{"Undo"}</button>
+                <button disabled={!self.history.can_redo()} onclick={ctx.link().callback(|_| Msg::Redo)}>{"Redo"}</button>
+                {self.deck_select_html(ctx)}
             </nav>
+            {self.conflicts_html(ctx)}
+            </>
         };
         let add_card_html = html! {
             <div>
@@ -1001,6 +2564,14 @@ impl Component for Model {
                         Msg::UpdateNewBackText(input.value())
                 })}
                 />
+                <input
+                id="new-tags" type="text" placeholder="tags, separated by commas"
+                value={self.new_tags_text.clone()}
+                oninput={ctx.link().callback(|e: InputEvent| {
+                        let input = e.target_unchecked_into::<HtmlInputElement>();
+                        Msg::UpdateNewTagsText(input.value())
+                })}
+                />
                 <button
                     onclick={ctx.link().callback(|_| Msg::AddCard)}
                 >{
@@ -1012,8 +2583,7 @@ impl Component for Model {
                 }</button>
             </div>
         };
-        let card_html = if let Some(card_index) = self.current_card {
-            let card = &self.cards[card_index];
+        let card_html = if let Some(card) = self.current_card.and_then(|id| self.card(id)) {
             let face = if self.reverse_mode {
                 Face::other_side(&self.visible_face)
             } else {
@@ -1021,27 +2591,56 @@ impl Component for Model {
             };
             let text = match face {
                 Face::Prompt => card.prompt.clone(),
-                Face::Response => card.response.clone()
+                Face::Response => card.response.clone(),
             };
-            let cls = format!("card {}", face.to_string());
+            let cls = format!("card {}", face);
             html! {
                 <p class={cls}>{text}</p>
             }
+        } else if self.scheduler != Scheduler::Beta && !self.session_cards().is_empty() {
+            let next = self
+                .next_due()
+                .map(|due| format!("The next card is due {}.", format_time(due)))
+                .unwrap_or_default();
+            html! {
+                <>
+                    <p class="card done">{"Done for today!"}</p>
+                    <div>{next}</div>
+                </>
+            }
         } else {
             html! {}
         };
         let upload_button_html = self.upload_button_html(ctx);
         let upload_html = html! {
-            <div>
+            <div
+                class="drop-zone"
+                ondragover={Callback::from(|e: DragEvent| e.prevent_default())}
+                ondrop={ctx.link().callback(|e: DragEvent| {
+                    e.prevent_default();
+                    Msg::UploadCards(file_list(e.data_transfer().and_then(|d| d.files())))
+                })}
+            >
                 {upload_button_html}
                 {copy_cards_html}
+                {download_cards_html}
+                {download_tables_html}
+                <button onclick={ctx.link().callback(|_| Msg::DownloadAnki)}>
+                    {"Download for Anki"}
+                </button>
             </div>
         };
         let onkeypress = {
             let link = ctx.link().clone();
-            link.batch_callback(|e: yew::events::KeyboardEvent| {
+            let graded_mode = self.graded_mode;
+            link.batch_callback(move |e: yew::events::KeyboardEvent| {
                 let k = e.key();
-                if k == "f" {
+                let grade = Grade::ALL.into_iter().find(|g| g.key() == k);
+                if e.target_dyn_into::<HtmlInputElement>().is_some() {
+                    None // typing into a text field
+                } else if let Some(grade) = grade.filter(|_| graded_mode) {
+                    Some(Msg::Grade(grade))
+                } else if k == "f" {
                     Some(Msg::Flip)
                 } else if k == "h" {
                     Some(Msg::Hit)
@@ -1058,6 +2657,25 @@ impl Component for Model {
                 }
             })
         };
+        let grade_buttons_html = if self.graded_mode {
+            Grade::ALL
+                .into_iter()
+                .map(|grade| {
+                    html! {
+                        <button onclick={ctx.link().callback(move |_| Msg::Grade(grade))}>
+                            {format!("{} ({})", grade, grade.key())}
+                        </button>
+                    }
+                })
+                .collect::<Html>()
+        } else {
+            html! {
+                <>
+                    <button onclick={ctx.link().callback(|_| Msg::Hit)}>{ "Hit" }</button>
+                    <button onclick={ctx.link().callback(|_| Msg::Miss)}>{ "Miss" }</button>
+                </>
+            }
+        };
         let reverse_mode_html = html! {
             <div class="form-check">
                 <input
@@ -1077,24 +2695,25 @@ impl Component for Model {
         };
         match self.mode {
             Mode::AllCards => {
-                if self.n_rows_displayed < self.cards.len() {
+                let cards = self.deck_cards();
+                if self.n_rows_displayed < cards.len() {
                     ctx.link().send_message(Msg::DisplayMoreRows);
                 }
                 let mut cards_html = vec![];
-                for (i, card) in self.cards.iter().take(self.n_rows_displayed).enumerate() {
-                    let delete_button_label =
-                        if self.deletion_target.is_some() && self.deletion_target.unwrap() == i {
-                            "Really? DELETE!"
-                        } else {
-                            "Delete"
-                        };
+                for card in cards.into_iter().take(self.n_rows_displayed) {
+                    let id = card.id;
+                    let delete_button_label = if self.deletion_target == Some(id) {
+                        "Really? DELETE!"
+                    } else {
+                        "Delete"
+                    };
                     let edit_button = html! {
-                        <button onclick={ctx.link().callback(move |_| Msg::Edit(Some(i)))}>
+                        <button onclick={ctx.link().callback(move |_| Msg::Edit(Some(id)))}>
                             {"Edit"}
                         </button>
                     };
                     let delete_button = html! {
-                        <button onclick={ctx.link().callback(move |_| Msg::DeleteCard(i))}>
+                        <button onclick={ctx.link().callback(move |_| Msg::DeleteCard(id))}>
                             {delete_button_label}
                         </button>
                     };
@@ -1102,6 +2721,8 @@ impl Component for Model {
                         <tr>
                             <td>{&card.prompt}</td>
                             <td>{&card.response}</td>
+                            <td>{card.tags.join(", ")}</td>
+                            <td>{self.move_card_html(ctx, card)}</td>
                             <td>{edit_button}</td>
                             <td>{delete_button}</td>
                         </tr>
@@ -1111,10 +2732,13 @@ impl Component for Model {
                     <div id="memoradical" class="memoradical-cards">
                         {mode_buttons}
                         {upload_html}
+                        {self.decks_html(ctx)}
                         <table class="striped">
                             <tr>
                                 <th>{"Prompt"}</th>
                                 <th>{"Response"}</th>
+                                <th>{"Tags"}</th>
+                                <th>{"Deck"}</th>
                                 <th colspan=2>{"Actions"}</th>
 
                             </tr>
@@ -1136,8 +2760,14 @@ impl Component for Model {
                     </div>
                 }
             }
+            Mode::Restore => html! {
+                <div id="memoradical" class="memoradical-restore">
+                    {mode_buttons}
+                    {self.restore_html(ctx)}
+                </div>
+            },
             Mode::Stats => {
-                if self.n_rows_displayed < self.cards.len() {
+                if self.n_rows_displayed < self.deck_cards().len() {
                     ctx.link().send_message(Msg::DisplayMoreRows);
                 }
                 let clear_html = if self.clear_counts_request {
@@ -1177,28 +2807,90 @@ impl Component for Model {
             }
             Mode::Study => {
                 let choice_checkboxes_html = self.study_checkboxes(ctx);
+                let tag_filter_html = html! {
+                    <div class="form-check">
+                        <label for="tag-filter">{"tags "}</label>
+                        <input
+                            id="tag-filter" type="text" placeholder="chapter1 -hard"
+                            value={self.tag_filter_text.clone()}
+                            onchange={ctx.link().callback(|e: Event| {
+                                let input: HtmlInputElement = e.target_unchecked_into();
+                                Msg::UpdateTagFilter(input.value())
+                            })}
+                        />
+                    </div>
+                };
                 html! {
                     <div id="memoradical" class="memoradical-study" {onkeypress}>
                         {mode_buttons}
                         <br/>
                         {reverse_mode_html}
                         {choice_checkboxes_html}
+                        {tag_filter_html}
                         {card_html}
-                        <button ref={self.focus_node.clone()}
-                            onclick={ctx.link().callback(|_| Msg::Flip)}>{ "Flip" }</button>
-                        <button onclick={ctx.link().callback(|_| Msg::Prev)}>{ "Prev" }</button>
-                        <button onclick={ctx.link().callback(|_| Msg::Next)}>{ "Next" }</button>
-                        <button onclick={ctx.link().callback(|_| Msg::Hit)}>{ "Hit" }</button>
-                        <button onclick={ctx.link().callback(|_| Msg::Miss)}>{ "Miss" }</button>
+                        {
+                            if self.typed_mode {
+                                self.typed_answer_html(ctx)
+                            } else {
+                                html! {
+                                    <>
+                                        <button ref={self.focus_node.clone()}
+                                            onclick={ctx.link().callback(|_| Msg::Flip)}>{ "Flip" }</button>
+                                        <button onclick={ctx.link().callback(|_| Msg::Prev)}>{ "Prev" }</button>
+                                        <button onclick={ctx.link().callback(|_| Msg::Next)}>{ "Next" }</button>
+                                        {grade_buttons_html}
+                                    </>
+                                }
+                            }
+                        }
                         <button onclick={ctx.link().callback(|_| Msg::Edit(None))}>{ "Edit" }</button>
                     </div>
                 }
             }
+            Mode::Quiz => {
+                // choices whose cards have gone, as through a merge, are left out
+                let cards = self
+                    .quiz_choices
+                    .iter()
+                    .filter_map(|id| self.card(*id))
+                    .collect::<Vec<_>>();
+                let choices = cards
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let id = c.id;
+                        html! {
+                            <button class="quiz-choice" onclick={ctx.link().callback(move |_| Msg::QuizAnswer(id))}>
+                                {format!("{}. {}", i + 1, self.answer_text(c))}
+                            </button>
+                        }
+                    })
+                    .collect::<Html>();
+                let onkeypress = {
+                    let choices = cards.iter().map(|c| c.id).collect::<Vec<_>>();
+                    ctx.link()
+                        .batch_callback(move |e: yew::events::KeyboardEvent| {
+                            let i = e.key().parse::<usize>().ok()?;
+                            choices
+                                .get(i.checked_sub(1)?)
+                                .map(|id| Msg::QuizAnswer(*id))
+                        })
+                };
+                html! {
+                    <div id="memoradical" class="memoradical-quiz" {onkeypress} tabindex="0"
+                        ref={self.focus_node.clone()}>
+                        {mode_buttons}
+                        <br/>
+                        {reverse_mode_html}
+                        <div>{self.quiz_feedback.clone().unwrap_or_default()}</div>
+                        {card_html}
+                        <div class="quiz-choices">{choices}</div>
+                        <button onclick={ctx.link().callback(|_| Msg::Next)}>{ "Next" }</button>
+                    </div>
+                }
+            }
             Mode::Add | Mode::Edit => {
-                let root_cls = format!(
-                    "memoradical-{}",
-                    self.mode.to_string()
-                );
+                let root_cls = format!("memoradical-{}", self.mode);
 
                 html! {
                     <div id="memoradical" class={root_cls}>
@@ -0,0 +1,255 @@
+//! Where cards, decks, and the review log are kept between visits
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::{anyhow, Context, Result};
+use gloo_console::log;
+use serde::Serialize;
+
+use memoradical_core::restore::RestorePoint;
+use memoradical_core::reviews::Review;
+use memoradical_core::schema::{self, Settings};
+use memoradical_core::validate::{self, Problem};
+use memoradical_core::Card;
+
+use crate::idbstore::IdbStore;
+use crate::localstore::{LocalStore, STORAGE_KEY_CARDS};
+
+/// A save in progress, which fails if the data could not be stored
+pub type Saving = Pin<Box<dyn Future<Output = Result<()>>>>;
+
+/// A reload in progress
+pub type Reloading = Pin<Box<dyn Future<Output = Result<Reloaded>>>>;
+
+/// Restore points being read, oldest first
+pub type Listing = Pin<Box<dyn Future<Output = Result<Vec<RestorePoint>>>>>;
+
+/// The error for a save that would overwrite what another tab has saved
+#[derive(Debug)]
+pub struct ChangedElsewhere;
+
+impl fmt::Display for ChangedElsewhere {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "stored cards have been changed by another tab since they were loaded"
+        )
+    }
+}
+
+impl std::error::Error for ChangedElsewhere {}
+
+/// Everything that is kept between visits
+#[derive(Clone, Debug, Default)]
+pub struct Saved {
+    pub settings: Settings,
+    pub cards: Vec<Card>,
+    pub decks: Vec<String>,
+    pub reviews: Vec<Review>,
+}
+
+/// Everything that is kept between visits, as the JSON text it is stored as
+#[derive(Clone, Debug, Serialize)]
+pub struct Raw {
+    pub cards: String, // an envelope of settings and cards, or a bare array from before there was one
+    pub decks: String,
+    pub reviews: String,
+}
+
+/// Which part of the stored data failed to parse
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Part {
+    Cards,
+    Decks,
+    Reviews,
+}
+
+impl Part {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Part::Cards => "cards",
+            Part::Decks => "decks",
+            Part::Reviews => "review log",
+        }
+    }
+}
+
+/// Stored data that cannot be parsed, kept as it is so that it can be recovered
+#[derive(Clone, Debug)]
+pub struct Unreadable {
+    pub raw: Raw,
+    pub part: Part,
+    pub error: String,
+}
+
+impl fmt::Display for Unreadable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "stored {} cannot be read: {}",
+            self.part.label(),
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for Unreadable {}
+
+impl Unreadable {
+    /// The text of the part that failed to parse
+    pub fn text(&self) -> &str {
+        match self.part {
+            Part::Cards => &self.raw.cards,
+            Part::Decks => &self.raw.decks,
+            Part::Reviews => &self.raw.reviews,
+        }
+    }
+}
+
+impl Raw {
+    /// Parse everything, upgrading cards saved by earlier versions.
+    pub fn parse(&self) -> Result<Saved, Unreadable> {
+        let unreadable = |part| {
+            move |error: String| Unreadable {
+                raw: self.clone(),
+                part,
+                error,
+            }
+        };
+        let json = |part| move |e: serde_json::Error| unreadable(part)(e.to_string());
+        let (settings, cards, problems) = schema::read("stored cards", &self.cards);
+        if let Some(problem) = problems.first() {
+            let error = format!("{} at {}", problem.message, problem.location());
+            return Err(unreadable(Part::Cards)(error));
+        }
+        Ok(Saved {
+            settings,
+            cards,
+            decks: serde_json::from_str(&self.decks).map_err(json(Part::Decks))?,
+            reviews: serde_json::from_str(&self.reviews).map_err(json(Part::Reviews))?,
+        })
+    }
+
+    /// Keep every record that can be parsed, and report the rest.
+    pub fn salvage(&self) -> (Saved, Vec<Problem>) {
+        let (settings, cards, mut problems) = schema::read("stored cards", &self.cards);
+        let (decks, deck_problems) = validate::json_records("stored decks", &self.decks);
+        let (reviews, review_problems) = validate::json_records("stored review log", &self.reviews);
+        problems.extend(deck_problems);
+        problems.extend(review_problems);
+        let saved = Saved {
+            settings,
+            cards,
+            decks,
+            reviews,
+        };
+        (saved, problems)
+    }
+}
+
+/// What another tab saved, and what this tab had last loaded or saved before that
+pub struct Reloaded {
+    pub base: Saved,
+    pub theirs: Saved,
+}
+
+pub trait Store {
+    /// Save the settings, cards, decks, and review log.
+    /// Saves finish in the order they were started,
+    /// and fail with `ChangedElsewhere` if another tab has saved in the meantime.
+    fn save(
+        &mut self,
+        settings: &Settings,
+        cards: &[Card],
+        decks: &[String],
+        reviews: &[Review],
+    ) -> Saving;
+
+    /// Read what other tabs have saved, which from then on is taken as this tab's last save.
+    fn reload(&mut self) -> Reloading;
+
+    /// Call `on_change` whenever another tab saves.
+    fn watch(&mut self, on_change: Box<dyn Fn()>);
+
+    /// Replace everything stored, after keeping a backup of the unreadable text.
+    fn replace(&mut self, saved: &Saved, unreadable: &Raw) -> Saving;
+
+    /// Keep a restore point, forgetting the oldest ones beyond the limit.
+    fn keep(&mut self, point: &RestorePoint) -> Saving;
+
+    /// The restore points kept so far
+    fn restore_points(&mut self) -> Listing;
+}
+
+/// What was stored, or the stored text if it cannot be read
+pub type Loaded = Result<Saved, Unreadable>;
+
+/// Open the IndexedDB store, moving any cards kept in local storage into it,
+/// or fall back on local storage where IndexedDB is unavailable.
+///
+/// Data that cannot be read is left as it is, for the user to recover.
+pub async fn open(default_cards: &str) -> Result<(Box<dyn Store>, Loaded)> {
+    let mut store = match IdbStore::open().await {
+        Ok(store) => store,
+        Err(e) => {
+            log!(format!(
+                "using local storage, because IndexedDB failed: {e:?}"
+            ));
+            let store = LocalStore::new(default_cards)?;
+            let loaded = store.load();
+            return Ok((Box::new(store), loaded));
+        }
+    };
+    if let Some(loaded) = store.load().await? {
+        return Ok((Box::new(store), loaded));
+    }
+    let (mut saved, origin) = if LocalStore::exists() {
+        let local = LocalStore::new(default_cards)?;
+        match local.load() {
+            Ok(saved) => (saved, STORAGE_KEY_CARDS),
+            // recover it where it is, and move it on a later visit
+            Err(unreadable) => return Ok((Box::new(local), Err(unreadable))),
+        }
+    } else {
+        let (settings, cards, problems) = schema::read("default cards", default_cards);
+        if let Some(problem) = problems.first() {
+            return Err(anyhow!("parsing default cards: {}", problem.message));
+        }
+        let saved = Saved {
+            settings,
+            cards,
+            ..Saved::default()
+        };
+        (saved, "default cards")
+    };
+    // cards are stored by id, so cards from before there were ids need them first
+    memoradical_core::assign_missing_ids(&mut saved.cards);
+    store
+        .initialize(&saved, origin)
+        .await
+        .context("copying cards into IndexedDB")?;
+    if origin == STORAGE_KEY_CARDS {
+        LocalStore::remove();
+        // the cards have moved, so a failure here only leaves the restore points behind
+        match move_restore_points(&mut store).await {
+            Ok(()) => LocalStore::remove_restore_points(),
+            Err(e) => log!(format!(
+                "leaving restore points in local storage, because moving them failed: {e:?}"
+            )),
+        }
+    }
+    Ok((Box::new(store), Ok(saved)))
+}
+
+/// Keep the restore points from local storage in IndexedDB, oldest first.
+async fn move_restore_points(store: &mut IdbStore) -> Result<()> {
+    for point in LocalStore::read_restore_points()? {
+        store
+            .keep(&point)
+            .await
+            .context("copying restore points into IndexedDB")?;
+    }
+    Ok(())
+}